@@ -1,6 +1,16 @@
 use tools::driller::next;
 use tools::encoder::encode_verb;
+use tools::host::Bot;
 
+pub struct Driller;
+
+impl Bot for Driller {
+  fn execute(&mut self) -> i64 {
+    encode_verb(next())
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
 #[no_mangle]
 pub fn execute() -> i64 {
   encode_verb(next())
@@ -0,0 +1,224 @@
+use driller::Driller;
+use harness::{bot, dummy, Scenario};
+use shipped::state::entity::{EconomyRules, Team, TemplateEntity};
+use shipped::state::geometry::{Direction, Symmetry};
+use shipped::state::materials::Materials;
+use shipped::state::neutral::Neutral;
+use shipped::state::state::{CombatRules, Event, Verb};
+use tools::encoder::encode_verb;
+use tools::game::{Displace, Neighbor, Verb as BotVerb};
+use tools::host::Bot;
+
+fn driller() -> TemplateEntity {
+  TemplateEntity {
+    drill_damage: 2,
+    ..bot("driller")
+  }
+}
+
+#[test]
+fn drills_enemy_to_the_north() {
+  let run = Scenario::new(
+    "A
+     0",
+  )
+  .blue(0, driller())
+  .native("driller", || Box::new(Driller))
+  .red(0, dummy())
+  .run(1)
+  .unwrap();
+  let driller = run.id_at_start(0, 0).unwrap();
+  assert!(matches!(
+    run.verbs(driller)[..],
+    [Verb::Drill(Direction::North)]
+  ));
+  assert_eq!(run.entity_at(0, 1).unwrap().hp, 8);
+}
+
+#[test]
+fn kills_enemy_after_enough_turns() {
+  let run = Scenario::new(".0A")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .red(0, dummy())
+    .run(5)
+    .unwrap();
+  assert!(run.entity_at(2, 0).is_none());
+}
+
+#[test]
+fn killed_enemies_drop_loot() {
+  let run = Scenario::new(".0A")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .red(0, dummy())
+    .run(5)
+    .unwrap();
+  let deaths: Vec<&Event> = run.events.iter().flatten().collect();
+  match deaths[..] {
+    [Event::Death { loot, .. }] => {
+      // a quarter of the carbon of its hp, over its tile and three neighbours
+      assert_eq!(loot.len(), 4);
+      assert!(loot.iter().all(|(pos, m)| run.floor(pos.x, pos.y) == m));
+    }
+    _ => panic!("expected one death, got {:?}", deaths),
+  }
+}
+
+// repairs whatever stands to its east, as it sees the board
+struct Medic;
+
+impl Bot for Medic {
+  fn execute(&mut self) -> i64 {
+    encode_verb(BotVerb::Repair(Neighbor::East))
+  }
+}
+
+#[test]
+fn drilled_enemies_are_repaired_by_their_medic() {
+  let run = Scenario::new(".0AB")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .red(0, dummy())
+    .red(
+      1,
+      TemplateEntity {
+        can_repair: true,
+        materials: Materials::new(10, 0, 0, 0),
+        ..bot("medic")
+      },
+    )
+    .native("medic", || Box::new(Medic))
+    .run(5)
+    .unwrap();
+  // without the medic it would be dead after five drills
+  assert!(run.entity_at(2, 0).unwrap().hp >= 8);
+  assert!(run.entity_at(3, 0).unwrap().materials.carbon < 10);
+}
+
+#[test]
+fn loot_is_priced_with_the_economy_of_the_level() {
+  let run = Scenario::new(".0A")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .red(0, dummy())
+    .economy(EconomyRules {
+      exponent: 1.0,
+      ..EconomyRules::default()
+    })
+    .run(5)
+    .unwrap();
+  // a quarter of the ten carbon that its hp costs without the exponent
+  let carbon: usize = run
+    .events
+    .iter()
+    .flatten()
+    .map(|event| match event {
+      Event::Death { loot, .. } => loot.iter().map(|(_, m)| m.carbon).sum::<usize>(),
+    })
+    .sum();
+  assert_eq!(carbon, 2);
+}
+
+#[test]
+fn armour_reduces_drill_damage() {
+  let run = Scenario::new(".0A")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .red(
+      0,
+      TemplateEntity {
+        armour: 1,
+        ..dummy()
+      },
+    )
+    .run(1)
+    .unwrap();
+  assert_eq!(run.entity_at(2, 0).unwrap().hp, 9);
+}
+
+// shoots two tiles to its east
+struct Gunner;
+
+impl Bot for Gunner {
+  fn execute(&mut self) -> i64 {
+    encode_verb(BotVerb::Shoot(Displace::new(2, 0)))
+  }
+}
+
+#[test]
+fn shots_pass_through_allies_when_the_level_allows() {
+  let run = Scenario::new("01A")
+    .blue(
+      0,
+      TemplateEntity {
+        gun_damage: 2,
+        materials: Materials::new(0, 0, 0, 1),
+        ..bot("gunner")
+      },
+    )
+    .native("gunner", || Box::new(Gunner))
+    .blue(1, dummy())
+    .red(0, dummy())
+    .combat(CombatRules {
+      pass_through_allies: true,
+      ..CombatRules::default()
+    })
+    .run(1)
+    .unwrap();
+  assert_eq!(run.entity_at(1, 0).unwrap().hp, 10);
+  assert_eq!(run.entity_at(2, 0).unwrap().hp, 8);
+}
+
+#[test]
+fn ignores_friends() {
+  let run = Scenario::new("01")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .blue(1, dummy())
+    .run(3)
+    .unwrap();
+  let driller = run.id_at_start(0, 0).unwrap();
+  assert!(run.verbs(driller).iter().all(|v| matches!(v, Verb::Wait)));
+  assert_eq!(run.entity_at(1, 0).unwrap().hp, 10);
+}
+
+#[test]
+fn drills_enemies_in_four_team_matches() {
+  // the second team starts at the south-east corner, rotated a quarter turn
+  let run = Scenario::new(".")
+    .symmetry(Symmetry::FourfoldRotation)
+    .team(Team(1), 0, driller())
+    .team(Team(3), 0, dummy())
+    .place(Team(1), 0, 59, 0)
+    .place(Team(3), 0, 58, 0)
+    .native("driller", || Box::new(Driller))
+    .run(1)
+    .unwrap();
+  assert_eq!(run.entity_at(58, 0).unwrap().hp, 8);
+}
+
+#[test]
+fn leaves_neutral_entities_alone() {
+  let run = Scenario::new("0")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .neutral(Neutral::Rock, 1, 0)
+    .run(3)
+    .unwrap();
+  let driller = run.id_at_start(0, 0).unwrap();
+  assert!(run.verbs(driller).iter().all(|v| matches!(v, Verb::Wait)));
+  assert_eq!(run.entity_at(1, 0).unwrap().hp, 30);
+}
+
+#[test]
+fn is_shot_by_neutral_turrets() {
+  let run = Scenario::new("0")
+    .blue(0, driller())
+    .native("driller", || Box::new(Driller))
+    .neutral(Neutral::Turret, 3, 0)
+    .run(1)
+    .unwrap();
+  assert_eq!(run.entity_at(0, 0).unwrap().hp, 8);
+  assert!(run.entity_at(3, 0).unwrap().team.is_none());
+}
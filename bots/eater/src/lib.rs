@@ -1,21 +1,17 @@
 use tools::abbrev::{GO_NORTH, GO_WEST};
 use tools::encoder::{decode_tile_materials, encode_displace, encode_verb};
 use tools::game::{Displace, Materials, Neighbor, Verb};
+use tools::host::{get_materials, Bot};
 
-extern "C" {
-  fn get_materials(_: u16) -> i64;
-}
-
-#[no_mangle]
-pub fn execute() -> i64 {
-  let code = unsafe { get_materials(encode_displace(&Displace { x: 0, y: 1 })) };
+fn next() -> Verb {
+  let code = get_materials(encode_displace(&Displace { x: 0, y: 1 }));
   let tile = decode_tile_materials(code);
   match tile {
     Some(materials) => {
       if materials.carbon == 0 {
-        encode_verb(GO_NORTH)
+        GO_NORTH
       } else {
-        encode_verb(Verb::GetMaterials(
+        Verb::GetMaterials(
           Neighbor::North,
           Materials {
             carbon: 1,
@@ -23,9 +19,23 @@ pub fn execute() -> i64 {
             plutonium: 0,
             copper: 0,
           },
-        ))
+        )
       }
     }
-    None => encode_verb(GO_WEST),
+    None => GO_WEST,
+  }
+}
+
+pub struct Eater;
+
+impl Bot for Eater {
+  fn execute(&mut self) -> i64 {
+    encode_verb(next())
   }
 }
+
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub fn execute() -> i64 {
+  encode_verb(next())
+}
@@ -0,0 +1,92 @@
+use eater::Eater;
+use harness::{bot, dummy, Scenario};
+use shipped::state::entity::TemplateEntity;
+use shipped::state::geometry::Neighbor;
+use shipped::state::materials::Materials;
+use shipped::state::state::Verb;
+use tools::encoder::encode_verb;
+use tools::game::{Materials as BotMaterials, Neighbor as BotNeighbor, Verb as BotVerb};
+use tools::host::Bot;
+
+#[test]
+fn picks_up_carbon_to_the_north() {
+  let run = Scenario::new(
+    "c
+     0",
+  )
+  .blue(0, bot("eater"))
+  .native("eater", || Box::new(Eater))
+  .run(1)
+  .unwrap();
+  let eater = run.id_at_start(0, 0).unwrap();
+  assert!(matches!(
+    run.verbs(eater)[..],
+    [Verb::GetMaterials(Neighbor::North, _)]
+  ));
+  assert_eq!(
+    run.entity(eater).unwrap().materials,
+    Materials::new(1, 0, 0, 0)
+  );
+  assert_eq!(*run.floor(0, 1), Materials::new(0, 0, 0, 0));
+}
+
+#[test]
+fn walks_north_when_there_is_no_carbon() {
+  let run = Scenario::new(
+    ".
+     .
+     0",
+  )
+  .blue(0, bot("eater"))
+  .native("eater", || Box::new(Eater))
+  .run(2)
+  .unwrap();
+  assert!(run.entity_at(0, 2).is_some());
+}
+
+// hands one carbon to whatever stands to its east
+struct Courier;
+
+impl Bot for Courier {
+  fn execute(&mut self) -> i64 {
+    encode_verb(BotVerb::Give(
+      BotNeighbor::East,
+      BotMaterials {
+        carbon: 1,
+        silicon: 0,
+        plutonium: 0,
+        copper: 0,
+      },
+    ))
+  }
+}
+
+#[test]
+fn gives_carbon_to_a_friend_until_it_is_full() {
+  let run = Scenario::new("01")
+    .blue(
+      0,
+      TemplateEntity {
+        materials: Materials::new(3, 0, 0, 0),
+        ..bot("courier")
+      },
+    )
+    .native("courier", || Box::new(Courier))
+    .blue(
+      1,
+      TemplateEntity {
+        inventory_size: 2,
+        ..dummy()
+      },
+    )
+    .run(3)
+    .unwrap();
+  assert_eq!(
+    run.entity_at(0, 0).unwrap().materials,
+    Materials::new(1, 0, 0, 0)
+  );
+  assert_eq!(
+    run.entity_at(1, 0).unwrap().materials,
+    Materials::new(2, 0, 0, 0)
+  );
+}
@@ -1,9 +1,31 @@
 use tools::encoder::encode_verb;
 use tools::game::Pos;
+use tools::host::Bot;
 use tools::mover::{Mover, MoverState};
 
+pub struct MoverBot {
+  target: MoverState,
+}
+
+impl Default for MoverBot {
+  fn default() -> Self {
+    MoverBot {
+      target: Pos { x: 32, y: 20 },
+    }
+  }
+}
+
+impl Bot for MoverBot {
+  fn execute(&mut self) -> i64 {
+    let mover = Mover::new(&mut self.target as *mut MoverState);
+    encode_verb(mover.next())
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
 static mut MOVER: MoverState = Pos { x: 32, y: 20 };
 
+#[cfg(target_arch = "wasm32")]
 #[no_mangle]
 pub fn execute() -> i64 {
   let mover = unsafe { Mover::new(&mut MOVER as *mut MoverState) };
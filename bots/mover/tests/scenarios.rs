@@ -0,0 +1,122 @@
+use harness::{bot, dummy, has_wasm, Scenario};
+use mover::MoverBot;
+use shipped::state::entity::{MovementType, TemplateEntity};
+use shipped::state::materials::Materials;
+use shipped::state::state::ActionCosts;
+
+// the mover bot heads to (32, 20), first along x and then along y
+
+#[test]
+fn walks_east_towards_target() {
+  let run = Scenario::new("0")
+    .blue(0, bot("mover"))
+    .native("mover", || Box::new(MoverBot::default()))
+    .run(3)
+    .unwrap();
+  assert!(run.entity_at(3, 0).is_some());
+}
+
+#[test]
+fn walks_east_towards_target_in_wasm() {
+  if !has_wasm("mover") {
+    return;
+  }
+  let run = Scenario::new("0").blue(0, bot("mover")).run(3).unwrap();
+  assert!(run.entity_at(3, 0).is_some());
+}
+
+#[test]
+fn goes_around_obstacles() {
+  let run = Scenario::new(
+    ". .
+     0 A",
+  )
+  .blue(0, bot("mover"))
+  .native("mover", || Box::new(MoverBot::default()))
+  .red(0, dummy())
+  .run(1)
+  .unwrap();
+  assert!(run.entity_at(0, 1).is_some());
+}
+
+#[test]
+fn goes_around_walls() {
+  let run = Scenario::new(
+    ". .
+     0 #",
+  )
+  .blue(0, bot("mover"))
+  .native("mover", || Box::new(MoverBot::default()))
+  .run(1)
+  .unwrap();
+  assert!(run.entity_at(0, 1).is_some());
+}
+
+#[test]
+fn stops_when_out_of_plutonium() {
+  let run = Scenario::new("0")
+    .blue(
+      0,
+      TemplateEntity {
+        materials: Materials::new(0, 0, 2, 0),
+        ..bot("mover")
+      },
+    )
+    .native("mover", || Box::new(MoverBot::default()))
+    .costs(ActionCosts {
+      moves_per_plutonium: 2,
+      ..ActionCosts::default()
+    })
+    .run(6)
+    .unwrap();
+  let mover = run.entity_at(4, 0).unwrap();
+  assert_eq!(mover.materials.plutonium, 0);
+}
+
+#[test]
+fn fast_movers_cover_several_tiles() {
+  let run = Scenario::new("0")
+    .blue(
+      0,
+      TemplateEntity {
+        steps: 2,
+        ..bot("mover")
+      },
+    )
+    .native("mover", || Box::new(MoverBot::default()))
+    .run(2)
+    .unwrap();
+  assert!(run.entity_at(4, 0).is_some());
+}
+
+#[test]
+fn slow_movers_rest_between_moves() {
+  let run = Scenario::new("0")
+    .blue(
+      0,
+      TemplateEntity {
+        rest: 1,
+        ..bot("mover")
+      },
+    )
+    .native("mover", || Box::new(MoverBot::default()))
+    .run(3)
+    .unwrap();
+  assert!(run.entity_at(2, 0).is_some());
+}
+
+#[test]
+fn flies_over_water() {
+  let run = Scenario::new("0~")
+    .blue(
+      0,
+      TemplateEntity {
+        movement_type: MovementType::Fly,
+        ..bot("mover")
+      },
+    )
+    .native("mover", || Box::new(MoverBot::default()))
+    .run(2)
+    .unwrap();
+  assert!(run.entity_at(2, 0).is_some());
+}
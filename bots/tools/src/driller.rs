@@ -1,10 +1,7 @@
 use super::abbrev::WAIT;
 use super::encoder::{decode_view, encode_displace};
 use super::game::{Direction, Displace, Team, Verb, ViewResult};
-
-extern "C" {
-  fn get_entity(_: u16) -> i64;
-}
+use super::host::get_entity;
 
 pub fn next() -> Verb {
   for disp in [
@@ -13,7 +10,7 @@ pub fn next() -> Verb {
     Displace { x: 1, y: 0 },
     Displace { x: -1, y: 0 },
   ] {
-    let code = unsafe { get_entity(encode_displace(&disp)) };
+    let code = get_entity(encode_displace(&disp));
     let entity: ViewResult = decode_view(code);
     match entity {
       ViewResult::Entity(e) => {
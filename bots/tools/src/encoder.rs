@@ -1,6 +1,6 @@
 use super::game::{
-  Direction, Displace, Materials, MovementType, Neighbor, Pos, Team, Verb, ViewAction, ViewResult,
-  ViewedEntity,
+  CombatRules, Direction, Displace, Materials, MovementType, Neighbor, Pos, Stat, Team, Terrain,
+  Verb, ViewAction, ViewResult, ViewedEntity,
 };
 use std::cmp::{max, min};
 
@@ -29,6 +29,16 @@ pub fn encode_neighbor(n: Neighbor) -> u8 {
   }
 }
 
+pub fn encode_stat(s: Stat) -> u8 {
+  match s {
+    Stat::Hp => 0,
+    Stat::InventorySize => 1,
+    Stat::GunDamage => 2,
+    Stat::DrillDamage => 3,
+    Stat::Sight => 4,
+  }
+}
+
 pub fn encode_materials(mat: Materials) -> u32 {
   let carbon: u32 = min(mat.carbon, 255).try_into().unwrap();
   let silicon: u32 = min(mat.silicon, 255).try_into().unwrap();
@@ -75,6 +85,19 @@ pub fn encode_verb(verb: Verb) -> i64 {
       let dir_code = encode_direction(direction) as i64;
       0x0007000000000000 + ((template_code as i64) << 40) + (dir_code << 32)
     }
+    Verb::Repair(neigh) => {
+      let neigh_code = encode_neighbor(neigh);
+      0x0008000000000000 + ((neigh_code as i64) << 40)
+    }
+    Verb::Upgrade(stat) => {
+      let stat_code = encode_stat(stat);
+      0x0009000000000000 + ((stat_code as i64) << 40)
+    }
+    Verb::Give(neigh, mat) => {
+      let neigh_code = encode_neighbor(neigh);
+      let mat_code = encode_materials(mat);
+      0x000A000000000000 + ((neigh_code as i64) << 40) + (mat_code as i64)
+    }
     _ => 0x0001000000000000,
   }
 }
@@ -102,6 +125,26 @@ pub fn decode_tile_materials(code: i64) -> Option<Materials> {
   }
 }
 
+pub fn decode_tile_terrain(code: i64) -> Option<Terrain> {
+  match (code & 0x00FF000000000000) >> 48 {
+    0x01 => match code & 0x00000000000000FF {
+      0 => Some(Terrain::Floor),
+      1 => Some(Terrain::Wall),
+      2 => Some(Terrain::Water),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+pub fn decode_combat_rules(code: i64) -> CombatRules {
+  CombatRules {
+    friendly_fire: code & 0x01 != 0,
+    pass_through_allies: code & 0x02 != 0,
+    falloff: ((code & 0xFF00) >> 8) as usize,
+  }
+}
+
 pub fn decode_pos(code: u16) -> Pos {
   Pos {
     x: (code & 0x00FF).try_into().unwrap(),
@@ -113,7 +156,7 @@ pub fn decode_view(code: i64) -> ViewResult {
   match (code & 0x0F00000000000000) >> 56 {
     0 => ViewResult::OutOfBounds,
     1 => ViewResult::Empty,
-    2 => ViewResult::Entity(decode_entity(code & 0x0000FFFFFFFFFFFF)),
+    2 => ViewResult::Entity(decode_entity(code & 0x00FFFFFFFFFFFFFF)),
     _ => ViewResult::Error,
   }
 }
@@ -123,18 +166,26 @@ pub fn decode_entity(code: i64) -> ViewedEntity {
   let hp: usize = ((code & 0x0000000000FF0000) >> 16) as usize;
   let gun_damage: usize = ((code & 0x000000000F000000) >> 24) as usize;
   let drill_damage: usize = ((code & 0x00000000F0000000) >> 28) as usize;
-  let team: Team = match code & 0x0000000100000000 {
-    0 => Team::Blue,
+  let team: Team = match (code & 0x0000000100000000, code & 0x0001000000000000) {
+    (_, 0x0001000000000000) => Team::Neutral,
+    (0, _) => Team::Blue,
     _ => Team::Red,
   };
-  let movement_type: MovementType = match code & 0x0000000200000000 {
-    0 => MovementType::Still,
-    _ => MovementType::Walk,
+  let movement_type: MovementType = match (code & 0x0000000200000000, code & 0x0002000000000000) {
+    (0, _) => MovementType::Still,
+    (_, 0) => MovementType::Walk,
+    _ => MovementType::Fly,
   };
   let inventory_size: usize = ((code & 0x0000003FC0000000) >> 34) as usize;
   let tokens: usize = ((code & 0x000003C000000000) >> 42) as usize;
+  let team_number: usize = ((code & 0x0000C00000000000) >> 46) as usize;
+  let steps: usize = ((code & 0x000C000000000000) >> 50) as usize;
+  let rest: usize = ((code & 0x0030000000000000) >> 52) as usize;
   ViewedEntity {
     tokens,
+    team_number,
+    steps,
+    rest,
     team,
     pos,
     hp,
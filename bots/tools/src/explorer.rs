@@ -1,4 +1,5 @@
 use super::game::{Pos, Verb};
+use super::host::get_rand;
 use super::mover::{Mover, MoverState};
 
 pub type ExplorerState = MoverState;
@@ -7,10 +8,6 @@ pub struct Explorer {
   pointer: *mut MoverState,
 }
 
-extern "C" {
-  fn get_rand() -> u32;
-}
-
 impl Explorer {
   pub fn new(pointer: *mut ExplorerState) -> Self {
     Explorer { pointer }
@@ -25,6 +25,17 @@ pub enum Verb {
   Drill(Direction),
   Construct(usize, Direction),
   SetMessage(Message),
+  Repair(Neighbor),
+  Upgrade(Stat),
+  Give(Neighbor, Materials),
+}
+
+pub enum Stat {
+  Hp,
+  InventorySize,
+  GunDamage,
+  DrillDamage,
+  Sight,
 }
 
 pub enum Direction {
@@ -106,21 +117,58 @@ pub struct ViewedEntity {
   pub hp: usize,                   // 16-23 8 bits
   pub gun_damage: usize,           // 24-27 4 bits
   pub drill_damage: usize,         // 28-31 4 bits
-  pub team: Team,                  // 32-32 1 bit
-  pub movement_type: MovementType, // 33-33 1 bit
+  pub team: Team,                  // 32-32 1 bit, 48-48 1 bit (neutral)
+  pub movement_type: MovementType, // 33-33 1 bit, 49-49 1 bit (flies)
   pub inventory_size: usize,       // 34-41 8 bits
-  pub tokens: usize,               // 42-45 4 bits (total 46?)
+  pub tokens: usize,               // 42-45 4 bits
+  pub team_number: usize,          // 46-47 2 bits
+  pub steps: usize,                // 50-51 2 bits
+  pub rest: usize,                 // 52-53 2 bits
   pub last_action: ViewAction,     // TODO: implement vieweing last action
 }
 
+// how shots hit and hurt in the current level
+pub struct CombatRules {
+  pub friendly_fire: bool,
+  pub pass_through_allies: bool,
+  pub falloff: usize,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Terrain {
+  Floor,
+  Wall,
+  Water,
+}
+
+impl Terrain {
+  // whether a bot moving this way can enter the tile
+  pub fn can_cross(&self, movement: &MovementType) -> bool {
+    match movement {
+      MovementType::Still => false,
+      MovementType::Walk => *self == Terrain::Floor,
+      MovementType::Fly => *self != Terrain::Wall,
+    }
+  }
+}
+
+// bots see their own team as blue, and any other as red. In matches with
+// more teams, ViewedEntity::team_number tells the others apart. Entities
+// placed by the level belong to no team and are neutral.
+#[derive(Clone, Copy, PartialEq)]
 pub enum Team {
   Blue,
   Red,
+  Neutral,
 }
 
+// walkers move every turn, unless they rest after each move, and may cover
+// several tiles per move. Fliers also cross water.
+#[derive(Clone, Copy, PartialEq)]
 pub enum MovementType {
   Still,
   Walk,
+  Fly,
 }
 
 pub enum ViewAction {
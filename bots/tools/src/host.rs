@@ -0,0 +1,145 @@
+// Functions the engine provides to bots. Compiled to wasm they are imports
+// from the "env" module; compiled natively they are routed to the Host that
+// the engine installs while it runs a bot.
+
+pub trait Host {
+  fn get_coord(&self) -> u32;
+  fn get_materials(&self, displace: u16) -> i64;
+  // the entity on exactly the tile at `displace`, or out of bounds when the
+  // tile is beyond the sight of the bot or something stands in between.
+  // Before sight it returned the first entity along the line instead
+  fn get_entity(&self, displace: u16) -> i64;
+  fn get_terrain(&self, displace: u16) -> i64;
+  fn get_rand(&self) -> u32;
+  fn get_rules(&self) -> i64;
+  fn debug_log(&self, message: &str);
+}
+
+// A bot that is linked natively into the engine, returning encoded verbs
+// exactly like the wasm `execute` export.
+pub trait Bot {
+  fn execute(&mut self) -> i64;
+}
+
+#[cfg(target_arch = "wasm32")]
+mod imports {
+  extern "C" {
+    pub fn get_coord() -> u32;
+    pub fn get_materials(_: u16) -> i64;
+    pub fn get_entity(_: u16) -> i64;
+    pub fn get_terrain(_: u16) -> i64;
+    pub fn get_rand() -> u32;
+    pub fn get_rules() -> i64;
+    pub fn debug_log(ptr: u32, len: u32);
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_coord() -> u32 {
+  unsafe { imports::get_coord() }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_materials(displace: u16) -> i64 {
+  unsafe { imports::get_materials(displace) }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_entity(displace: u16) -> i64 {
+  unsafe { imports::get_entity(displace) }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_terrain(displace: u16) -> i64 {
+  unsafe { imports::get_terrain(displace) }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn get_rand() -> u32 {
+  unsafe { imports::get_rand() }
+}
+
+// the combat rules of the level, see encoder::decode_combat_rules
+#[cfg(target_arch = "wasm32")]
+pub fn get_rules() -> i64 {
+  unsafe { imports::get_rules() }
+}
+
+// prints a message in the replay, next to this bot. Only a few short
+// messages per turn are kept.
+#[cfg(target_arch = "wasm32")]
+pub fn debug_log(message: &str) {
+  unsafe { imports::debug_log(message.as_ptr() as u32, message.len() as u32) }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+  use super::Host;
+  use std::cell::RefCell;
+
+  thread_local! {
+    pub static HOST: RefCell<Option<Box<dyn Host>>> = RefCell::new(None);
+  }
+
+  pub fn with_current<R>(f: impl FnOnce(&dyn Host) -> R) -> R {
+    HOST.with(|h| {
+      f(h
+        .borrow()
+        .as_deref()
+        .expect("bot called the host outside of its turn"))
+    })
+  }
+
+  // puts back the host that was installed before, also when the bot panics
+  pub struct Restore(pub Option<Box<dyn Host>>);
+
+  impl Drop for Restore {
+    fn drop(&mut self) {
+      let previous = self.0.take();
+      HOST.with(|h| h.replace(previous));
+    }
+  }
+}
+
+// installs `host` for the duration of `f`, which is how the engine runs a
+// native bot
+#[cfg(not(target_arch = "wasm32"))]
+pub fn with_host<R>(host: Box<dyn Host>, f: impl FnOnce() -> R) -> R {
+  let _restore = native::Restore(native::HOST.with(|h| h.replace(Some(host))));
+  f()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_coord() -> u32 {
+  native::with_current(|h| h.get_coord())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_materials(displace: u16) -> i64 {
+  native::with_current(|h| h.get_materials(displace))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_entity(displace: u16) -> i64 {
+  native::with_current(|h| h.get_entity(displace))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_terrain(displace: u16) -> i64 {
+  native::with_current(|h| h.get_terrain(displace))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_rand() -> u32 {
+  native::with_current(|h| h.get_rand())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn get_rules() -> i64 {
+  native::with_current(|h| h.get_rules())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn debug_log(message: &str) {
+  native::with_current(|h| h.debug_log(message))
+}
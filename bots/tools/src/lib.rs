@@ -3,4 +3,6 @@ pub mod driller;
 pub mod encoder;
 pub mod explorer;
 pub mod game;
+pub mod host;
 pub mod mover;
+pub mod neutral;
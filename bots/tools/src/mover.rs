@@ -1,16 +1,9 @@
 use super::abbrev::{GO_EAST, GO_NORTH, GO_SOUTH, GO_WEST, WAIT};
-use super::encoder::{decode_coord, decode_view, encode_displace};
-use super::game::{Displace, Pos, Verb, ViewResult};
+use super::encoder::{decode_coord, decode_tile_terrain, decode_view, encode_displace};
+use super::game::{Displace, MovementType, Pos, Verb, ViewResult};
+use super::host::{get_coord, get_entity, get_terrain};
 use std::cmp::Ordering;
 
-extern "C" {
-  fn get_coord() -> u32;
-}
-
-extern "C" {
-  fn get_entity(_: u16) -> i64;
-}
-
 pub type MoverState = Pos;
 
 pub struct Mover {
@@ -22,21 +15,17 @@ impl Mover {
     Mover { pointer }
   }
   pub fn next(&self) -> Verb {
-    let code = unsafe { get_coord() };
+    let code = get_coord();
     let (x, y) = decode_coord(code);
     let target: Pos = unsafe { (*(self.pointer as *mut Pos)).clone() };
     match x.cmp(&target.x) {
       Ordering::Less => {
-        let code = unsafe { get_entity(encode_displace(&Displace { x: 1, y: 0 })) };
-        let viewed = decode_view(code);
-        if let ViewResult::Empty = viewed {
+        if is_free(Displace { x: 1, y: 0 }) {
           return GO_EAST;
         }
       }
       Ordering::Greater => {
-        let code = unsafe { get_entity(encode_displace(&Displace { x: -1, y: 0 })) };
-        let viewed = decode_view(code);
-        if let ViewResult::Empty = viewed {
+        if is_free(Displace { x: -1, y: 0 }) {
           return GO_WEST;
         }
       }
@@ -44,16 +33,12 @@ impl Mover {
     };
     match y.cmp(&target.y) {
       Ordering::Less => {
-        let code = unsafe { get_entity(encode_displace(&Displace { x: 0, y: 1 })) };
-        let viewed = decode_view(code);
-        if let ViewResult::Empty = viewed {
+        if is_free(Displace { x: 0, y: 1 }) {
           return GO_NORTH;
         }
       }
       Ordering::Greater => {
-        let code = unsafe { get_entity(encode_displace(&Displace { x: 0, y: -1 })) };
-        let viewed = decode_view(code);
-        if let ViewResult::Empty = viewed {
+        if is_free(Displace { x: 0, y: -1 }) {
           return GO_SOUTH;
         }
       }
@@ -62,3 +47,18 @@ impl Mover {
     return WAIT;
   }
 }
+
+// how the bot itself moves
+pub fn own_movement() -> MovementType {
+  match decode_view(get_entity(encode_displace(&Displace::new(0, 0)))) {
+    ViewResult::Entity(me) => me.movement_type,
+    _ => MovementType::Still,
+  }
+}
+
+// whether the bot can step to the tile at `disp`
+pub fn is_free(disp: Displace) -> bool {
+  let viewed = decode_view(get_entity(encode_displace(&disp)));
+  let terrain = decode_tile_terrain(get_terrain(encode_displace(&disp)));
+  matches!(viewed, ViewResult::Empty) && terrain.is_some_and(|t| t.can_cross(&own_movement()))
+}
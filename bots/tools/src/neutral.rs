@@ -0,0 +1,46 @@
+// Behaviour of the neutral entities that levels place on the board. They
+// see every team as red and leave other neutral entities alone.
+
+use super::abbrev::{GO_EAST, GO_NORTH, GO_SOUTH, GO_WEST, WAIT};
+use super::driller;
+use super::encoder::{decode_view, encode_displace};
+use super::game::{Displace, Team, Verb, ViewResult};
+use super::host::{get_entity, get_rand};
+use super::mover::is_free;
+
+// shoots the first entity of a team in range
+pub fn turret(range: i64) -> Verb {
+  for x in -range..=range {
+    for y in -range..=range {
+      let disp = Displace { x, y };
+      if disp.square_norm() == 0 || disp.square_norm() > range * range {
+        continue;
+      }
+      if let ViewResult::Entity(e) = decode_view(get_entity(encode_displace(&disp))) {
+        if e.team == Team::Red {
+          return Verb::Shoot(disp);
+        }
+      }
+    }
+  }
+  WAIT
+}
+
+// drills entities of a team next to it, otherwise steps in a random
+// direction
+pub fn creature() -> Verb {
+  if let Verb::Drill(dir) = driller::next() {
+    return Verb::Drill(dir);
+  }
+  let (verb, disp) = match get_rand() % 4 {
+    0 => (GO_NORTH, Displace { x: 0, y: 1 }),
+    1 => (GO_EAST, Displace { x: 1, y: 0 }),
+    2 => (GO_SOUTH, Displace { x: 0, y: -1 }),
+    _ => (GO_WEST, Displace { x: -1, y: 0 }),
+  };
+  if is_free(disp) {
+    verb
+  } else {
+    WAIT
+  }
+}
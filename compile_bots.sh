@@ -1,5 +1,9 @@
-for f in bots/*;
+for f in bots/*/;
 do echo "Building $f..."
-   cargo build --target wasm32-unknown-unknown --lib --release --manifest-path ${f}/Cargo.toml;
+   cargo build --target wasm32-unknown-unknown --lib --release --manifest-path ${f}Cargo.toml;
    [ $? -eq 0 ]  || exit 1
 done
+# the game loads bots from the bots/ of its data directory
+data=${SHIPPED_DATA:-.}
+mkdir -p $data/bots
+cp target/wasm32-unknown-unknown/release/*.wasm $data/bots/
@@ -0,0 +1,421 @@
+// Scripted scenarios for testing bots outside of the gui.
+//
+// A scenario is a small ascii board, read with the first line as the
+// northernmost row and placed at the south-west corner of the battlefield:
+//
+//   .  empty tile
+//   0-3  blue entity built from blue template n
+//   A-D  red entity built from red template n
+//   c s p o  one unit of carbon, silicon, plutonium or copper on the floor
+//   #  wall
+//   ~  water
+//
+// Spaces are ignored, so boards can be written as "0 . c" or "0.c". The
+// battlefield has the default size, unless another is given with
+// Scenario::board.
+//
+// Bots are loaded from the wasm built by compile_bots.sh, unless they are
+// registered with Scenario::native, in which case they run natively and
+// there is no need to compile them to wasm first.
+
+use snafu::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+use shipped::state::brain::{FaultPolicy, NativeBrains};
+use shipped::state::config::Config;
+use shipped::state::constants::{NUM_TEMPLATES, RANGE};
+use shipped::state::entity::{
+  ActiveEntity, EconomyRules, Full, MovementType, Team, TemplateEntity,
+};
+use shipped::state::geometry::{Board, Pos, Symmetry};
+use shipped::state::materials::Materials;
+use shipped::state::neutral::Neutral;
+use shipped::state::run::{play, RunError};
+use shipped::state::state::{
+  ActionCosts, CombatRules, Command, Event, Fault, Frame, Id, Log, LootRules, Script, State,
+  StateError, Terrain, Tile, Verb,
+};
+use tools::host::Bot;
+
+#[derive(Debug, Snafu)]
+pub enum HarnessError {
+  #[snafu(display("Scenario of size {width}x{height} does not fit the board"))]
+  TooLarge { width: usize, height: usize },
+  #[snafu(display("Unknown symbol {symbol:?} at {pos:?}"))]
+  UnknownSymbol { symbol: char, pos: Pos },
+  #[snafu(display("Could not place entity at {pos:?}"))]
+  PlaceEntity { source: StateError, pos: Pos },
+  #[snafu(display("{source}"))]
+  Run { source: RunError },
+}
+
+// path of a bot compiled by compile_bots.sh
+pub fn wasm_path(name: &str) -> String {
+  Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("../target/wasm32-unknown-unknown/release")
+    .join(format!("{}.wasm", name))
+    .to_string_lossy()
+    .into_owned()
+}
+
+// whether compile_bots.sh has built the bot, scenarios that run its wasm
+// are skipped otherwise
+pub fn has_wasm(name: &str) -> bool {
+  Path::new(&wasm_path(name)).is_file()
+}
+
+// a walking bot running the given wasm, to be tweaked with struct update
+// syntax, e.g. TemplateEntity { drill_damage: 2, ..bot("driller") }
+pub fn bot(name: &str) -> TemplateEntity {
+  TemplateEntity {
+    hp: 10,
+    inventory_size: 10,
+    materials: Materials::new(0, 0, 0, 0),
+    movement_type: MovementType::Walk,
+    steps: 1,
+    rest: 0,
+    gun_damage: 0,
+    drill_damage: 0,
+    sight: RANGE,
+    armour: 0,
+    can_repair: false,
+    can_upgrade: false,
+    message: None,
+    brain: Some(Full {
+      half: [0, 0],
+      code_name: wasm_path(name),
+      gas: 0,
+    }),
+  }
+}
+
+// an entity without brain, useful as a target or an obstacle
+pub fn dummy() -> TemplateEntity {
+  TemplateEntity {
+    hp: 10,
+    inventory_size: 0,
+    materials: Materials::new(0, 0, 0, 0),
+    movement_type: MovementType::Still,
+    steps: 1,
+    rest: 0,
+    gun_damage: 0,
+    drill_damage: 0,
+    sight: 0,
+    armour: 0,
+    can_repair: false,
+    can_upgrade: false,
+    message: None,
+    brain: None,
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct Scenario {
+  board: Board,
+  rows: Vec<Vec<char>>,
+  // indexed by team, as many as the symmetry with most regions
+  templates: Vec<[Option<TemplateEntity>; NUM_TEMPLATES]>,
+  floor: Vec<(Pos, Materials)>,
+  placements: Vec<(Team, usize, Pos)>,
+  neutrals: Vec<(Neutral, Pos)>,
+  natives: NativeBrains,
+  // bots are named by absolute wasm paths, so its data directory is unused
+  config: Config,
+  fault_policy: FaultPolicy,
+  loot: LootRules,
+  costs: ActionCosts,
+  combat: CombatRules,
+  economy: EconomyRules,
+  min_tokens: usize,
+}
+
+impl Scenario {
+  pub fn new(board: &str) -> Self {
+    Scenario {
+      board: Board::default(),
+      rows: board
+        .lines()
+        .map(|l| {
+          l.chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<Vec<char>>()
+        })
+        .filter(|r| !r.is_empty())
+        .collect(),
+      templates: vec![Default::default(); Symmetry::FourfoldRotation.regions()],
+      floor: vec![],
+      placements: vec![],
+      neutrals: vec![],
+      natives: NativeBrains::new(),
+      config: Config::resolve(None, None, None),
+      fault_policy: FaultPolicy::default(),
+      loot: LootRules::default(),
+      costs: ActionCosts::default(),
+      combat: CombatRules::default(),
+      economy: EconomyRules::default(),
+      min_tokens: 0,
+    }
+  }
+
+  // plays on a battlefield of the given size, the side split in halves
+  // must be even
+  pub fn board(mut self, width: usize, height: usize) -> Self {
+    self.board = Board::new(width, height).with_symmetry(self.board.symmetry);
+    self
+  }
+
+  // how the bots of the other teams see the board, rotated by default. Four
+  // teams play with Symmetry::FourfoldRotation.
+  pub fn symmetry(mut self, symmetry: Symmetry) -> Self {
+    self.board = self.board.with_symmetry(symmetry);
+    self
+  }
+
+  pub fn blue(self, template: usize, entity: TemplateEntity) -> Self {
+    self.team(Team::BLUE, template, entity)
+  }
+
+  pub fn red(self, template: usize, entity: TemplateEntity) -> Self {
+    self.team(Team::RED, template, entity)
+  }
+
+  pub fn team(mut self, team: Team, template: usize, entity: TemplateEntity) -> Self {
+    self.templates[team.0][template] = Some(entity);
+    self
+  }
+
+  // places an entity of any team, for those that have no ascii symbol
+  pub fn place(mut self, team: Team, template: usize, x: usize, y: usize) -> Self {
+    self.placements.push((team, template, Pos::new(x, y)));
+    self
+  }
+
+  // places an entity of no team, running its built-in brain
+  pub fn neutral(mut self, neutral: Neutral, x: usize, y: usize) -> Self {
+    self.neutrals.push((neutral, Pos::new(x, y)));
+    self
+  }
+
+  // runs templates made with bot(name) natively, e.g.
+  // .native("driller", || Box::new(Driller))
+  pub fn native(mut self, name: &str, factory: fn() -> Box<dyn Bot>) -> Self {
+    self.natives.insert(wasm_path(name), factory);
+    self
+  }
+
+  pub fn fault_policy(mut self, policy: FaultPolicy) -> Self {
+    self.fault_policy = policy;
+    self
+  }
+
+  // what dead entities drop on the floor
+  pub fn loot(mut self, loot: LootRules) -> Self {
+    self.loot = loot;
+    self
+  }
+
+  // what entities spend from their inventory to act
+  pub fn costs(mut self, costs: ActionCosts) -> Self {
+    self.costs = costs;
+    self
+  }
+
+  // how shots hit and hurt
+  pub fn combat(mut self, combat: CombatRules) -> Self {
+    self.combat = combat;
+    self
+  }
+
+  // how bodies are priced, for construction, loot and upgrades
+  pub fn economy(mut self, economy: EconomyRules) -> Self {
+    self.economy = economy;
+    self
+  }
+
+  // tokens a team needs to stay in the match
+  pub fn min_tokens(mut self, min_tokens: usize) -> Self {
+    self.min_tokens = min_tokens;
+    self
+  }
+
+  // adds materials to a tile, for piles larger than a single unit
+  pub fn floor(mut self, x: usize, y: usize, materials: Materials) -> Self {
+    self.floor.push((Pos::new(x, y), materials));
+    self
+  }
+
+  pub fn build(&self) -> Result<State, HarnessError> {
+    let height = self.rows.len();
+    let width = self.rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    ensure!(
+      self.board.is_valid() && width <= self.board.width && height <= self.board.height,
+      TooLargeSnafu { width, height }
+    );
+    let teams = self.board.teams();
+    let mut state = State::new(
+      self.board,
+      self.min_tokens,
+      vec![Default::default(); teams],
+      HashMap::new(),
+      self.templates[..teams].to_vec(),
+      (0..self.board.size())
+        .map(|_| Tile {
+          entity_id: None,
+          terrain: Terrain::Floor,
+          materials: Materials::new(0, 0, 0, 0),
+        })
+        .collect(),
+    );
+    state.loot = self.loot;
+    state.costs = self.costs;
+    state.combat = self.combat;
+    state.economy = self.economy;
+    for (j, row) in self.rows.iter().enumerate() {
+      for (x, symbol) in row.iter().enumerate() {
+        let pos = Pos::new(x, height - j - 1);
+        let materials = &mut state.get_mut_tile(pos).materials;
+        match symbol {
+          '.' => {}
+          'c' => materials.carbon += 1,
+          's' => materials.silicon += 1,
+          'p' => materials.plutonium += 1,
+          'o' => materials.copper += 1,
+          '#' => state.get_mut_tile(pos).terrain = Terrain::Wall,
+          '~' => state.get_mut_tile(pos).terrain = Terrain::Water,
+          '0'..='9' => state
+            .build_entity_from_template(Team::BLUE, 1, (*symbol as usize) - ('0' as usize), pos)
+            .context(PlaceEntitySnafu { pos })?,
+          'A'..='Z' => state
+            .build_entity_from_template(Team::RED, 1, (*symbol as usize) - ('A' as usize), pos)
+            .context(PlaceEntitySnafu { pos })?,
+          _ => {
+            return Err(HarnessError::UnknownSymbol {
+              symbol: *symbol,
+              pos,
+            })
+          }
+        }
+      }
+    }
+    for (pos, materials) in self.floor.iter() {
+      state.get_mut_tile(*pos).materials += materials.clone();
+    }
+    for (team, template, pos) in self.placements.iter() {
+      state
+        .build_entity_from_template(*team, 1, *template, *pos)
+        .context(PlaceEntitySnafu { pos: *pos })?;
+    }
+    for (neutral, pos) in self.neutrals.iter() {
+      state
+        .build_neutral(*neutral, *pos)
+        .context(PlaceEntitySnafu { pos: *pos })?;
+    }
+    Ok(state)
+  }
+
+  // runs the scenario for a number of turns as a match would, bot faults
+  // are recorded in the run
+  pub fn run(&self, turns: usize) -> Result<Run, HarnessError> {
+    let played = play(
+      &self.config,
+      self.build()?,
+      &self.natives,
+      self.fault_policy,
+      turns,
+    )
+    .context(RunSnafu)?;
+    let script = played.script;
+    Ok(Run {
+      genesis: script.genesis,
+      state: played.state,
+      frames: script.frames,
+      emitted: played.emitted,
+      logs: script.logs,
+      faults: script.faults,
+      events: script.events,
+      hashes: script.hashes,
+    })
+  }
+}
+
+pub struct Run {
+  pub genesis: State,
+  pub state: State,
+  // commands that were executed, as in a Script
+  pub frames: Vec<Frame>,
+  // every command the bots returned, including those the state rejected
+  pub emitted: Vec<Frame>,
+  // debug messages printed by the bots, one vector per turn
+  pub logs: Vec<Vec<Log>>,
+  // bot faults, one vector per turn
+  pub faults: Vec<Vec<Fault>>,
+  // deaths and other events, one vector per turn
+  pub events: Vec<Vec<Event>>,
+  // State::hash after each turn
+  pub hashes: Vec<u64>,
+}
+
+impl Run {
+  // the run as the script of a match, to replay it
+  pub fn script(&self) -> Script {
+    Script {
+      genesis: self.genesis.clone(),
+      frames: self.frames.clone(),
+      logs: self.logs.clone(),
+      faults: self.faults.clone(),
+      events: self.events.clone(),
+      hashes: self.hashes.clone(),
+    }
+  }
+
+  // id of the entity that started the scenario at (x, y)
+  pub fn id_at_start(&self, x: usize, y: usize) -> Option<Id> {
+    self.genesis.get_tile(Pos::new(x, y)).entity_id
+  }
+
+  pub fn entity(&self, id: Id) -> Option<&ActiveEntity> {
+    self.state.get_entity_by_id(id).ok()
+  }
+
+  pub fn entity_at(&self, x: usize, y: usize) -> Option<&ActiveEntity> {
+    self.state.get_entity_option(Pos::new(x, y))
+  }
+
+  pub fn floor(&self, x: usize, y: usize) -> &Materials {
+    self.state.get_floor_mat(Pos::new(x, y))
+  }
+
+  // verbs returned by an entity, one per turn it was alive
+  pub fn verbs(&self, id: Id) -> Vec<Verb> {
+    self
+      .emitted
+      .iter()
+      .flat_map(|f| f.iter())
+      .filter(|c: &&Command| c.entity_id == id)
+      .map(|c| c.verb.clone())
+      .collect()
+  }
+
+  // faults of an entity, over all turns
+  pub fn faults(&self, id: Id) -> Vec<Fault> {
+    self
+      .faults
+      .iter()
+      .flat_map(|f| f.iter())
+      .filter(|f| f.entity_id == id)
+      .cloned()
+      .collect()
+  }
+
+  // messages printed by an entity, over all turns
+  pub fn logs(&self, id: Id) -> Vec<String> {
+    self
+      .logs
+      .iter()
+      .flat_map(|l| l.iter())
+      .filter(|l| l.entity_id == id)
+      .map(|l| l.message.clone())
+      .collect()
+  }
+}
@@ -0,0 +1,44 @@
+use shipped::state::bundle::Bundle;
+use shipped::state::config::Config;
+use std::path::Path;
+
+const USAGE: &str = "usage: bundle [--data <dir>] export <file> <level> [<squad>...]
+       bundle [--data <dir>] import <file>";
+
+fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let config = match Config::load(&args) {
+    Ok(config) => config,
+    Err(e) => {
+      println!("{}", e);
+      return;
+    }
+  };
+  let rest = Config::other_args(&args);
+  let numbers: Option<Vec<usize>> = rest.iter().skip(2).map(|n| n.parse().ok()).collect();
+  match (rest.first(), rest.get(1), numbers) {
+    (Some(&"export"), Some(file), Some(numbers)) if !numbers.is_empty() => {
+      match Bundle::export(&config, numbers[0], &numbers[1..])
+        .and_then(|bundle| bundle.write(Path::new(file)))
+      {
+        Ok(()) => println!("Exported level {} to {}", numbers[0], file),
+        Err(e) => println!("{}", e),
+      }
+    }
+    (Some(&"import"), Some(file), _) if rest.len() == 2 => {
+      match Bundle::read(Path::new(file)).and_then(|bundle| bundle.import(&config)) {
+        Ok(imported) => {
+          println!("Imported level {:05}", imported.level);
+          for squad in imported.squads {
+            println!("Imported squad {:05}", squad);
+          }
+          for (old, new) in imported.renamed {
+            println!("Bot {} was saved as {}", old, new);
+          }
+        }
+        Err(e) => println!("{}", e),
+      }
+    }
+    _ => println!("{}", USAGE),
+  }
+}
@@ -1,6 +1,8 @@
-pub mod state;
 pub mod ui;
 
+pub use shipped::state;
+
+use crate::state::config::{init, Config};
 use crate::ui::landing::{Landing, LandingCommand};
 use crate::ui::ui::{get_input, Input, Rect, Ui};
 use macroquad::prelude::*;
@@ -21,6 +23,16 @@ fn window_conf() -> Conf {
 #[macroquad::main(window_conf)]
 async fn main() {
   env_logger::init();
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  match Config::load(&args) {
+    Ok(config) => {
+      init(config);
+    }
+    Err(e) => {
+      println!("{}", e);
+      return;
+    }
+  }
   let mut landing = Landing::new(Rect::new(0.0, 0.0, WIN_WIDTH, WIN_HEIGHT), ());
   let mut seconds: f64 = get_time();
 
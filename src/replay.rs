@@ -0,0 +1,35 @@
+use shipped::state::config::Config;
+use shipped::state::run::check_replay;
+
+const USAGE: &str = "usage: replay [--data <dir>] check <number>";
+
+fn main() {
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  let config = match Config::load(&args) {
+    Ok(config) => config,
+    Err(e) => {
+      println!("{}", e);
+      return;
+    }
+  };
+  match Config::other_args(&args)[..] {
+    ["check", n] => match n.parse() {
+      Ok(n) => match check_replay(&config, n) {
+        Ok(script) if script.hashes.is_empty() => {
+          println!("Replay {:05} has no hashes to check", n)
+        }
+        Ok(script) => println!(
+          "Replay {:05} plays back as recorded over {} frames",
+          n,
+          script.frames.len()
+        ),
+        Err(e) => {
+          println!("{}", e);
+          std::process::exit(1);
+        }
+      },
+      Err(_) => println!("{}", USAGE),
+    },
+    _ => println!("{}", USAGE),
+  }
+}
@@ -3,19 +3,26 @@ extern crate rand_chacha;
 
 use init_array::init_array;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use snafu::prelude::*;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use crate::state::constants::{HEIGHT, NUM_TEMPLATES, WIDTH};
+use crate::state::binary::{self, BinaryError};
+use crate::state::config::Config;
+use crate::state::constants::{NUM_TEMPLATES, RANGE};
 use crate::state::entity::{
-  cost_template, ActiveEntity, Mix, MixTemplate, MovementType, Team, TemplateEntity,
+  cost_template, ActiveEntity, EconomyError, EconomyRules, Mix, MixTemplate, MovementType, Team,
+  TemplateEntity,
 };
-use crate::state::geometry::{half_board_iterator, Pos};
+use crate::state::geometry::{Board, Pos};
 use crate::state::materials::Materials;
-use crate::state::state::{Id, State, Tile};
+use crate::state::neutral::Neutral;
+use crate::state::state::{
+  ActionCosts, CombatRules, Id, LootRules, State, StateError, Terrain, Tile,
+};
+use crate::state::utils::get_next_file_number;
 
 #[derive(Clone, Debug)]
 pub enum MatName {
@@ -33,11 +40,153 @@ pub enum EntityState {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BFState {
+  // layout the file was saved with, files from before versions were
+  // recorded are version 0
+  #[serde(default)]
+  version: usize,
+  // levels saved before boards had a size use the default one
+  #[serde(default)]
+  board: Board,
   materials: Materials,
   tokens: usize,
   min_tokens: usize,
   tiles: Vec<Tile>,
   entities: [EntityState; NUM_TEMPLATES],
+  // entities of no team in the region of the first team, repeated in every
+  // region like the rest of the level
+  #[serde(default)]
+  neutrals: Vec<(Pos, Neutral)>,
+  #[serde(default)]
+  loot: LootRules,
+  #[serde(default)]
+  costs: ActionCosts,
+  #[serde(default)]
+  combat: CombatRules,
+  #[serde(default)]
+  economy: EconomyRules,
+  #[serde(default)]
+  metadata: Metadata,
+  #[serde(default)]
+  manifest: Option<Manifest>,
+}
+
+// what the author tells about a level or squad, files are still named by
+// their number
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Metadata {
+  pub title: String,
+  pub author: String,
+  pub description: String,
+  // seconds since the unix epoch, 0 if unknown
+  pub created: u64,
+  pub tags: Vec<String>,
+}
+
+impl Metadata {
+  // the title and author in one line, for browsing
+  pub fn headline(&self) -> String {
+    let title = if self.title.is_empty() {
+      "Untitled"
+    } else {
+      &self.title
+    };
+    if self.author.is_empty() {
+      title.to_string()
+    } else {
+      format!("{} by {}", title, self.author)
+    }
+  }
+
+  pub fn created_date(&self) -> Option<String> {
+    if self.created == 0 {
+      return None;
+    }
+    let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(self.created);
+    let date = humantime::format_rfc3339_seconds(time).to_string();
+    Some(date[..10].to_string())
+  }
+}
+
+// hashes recorded when a level or squad is saved, so that files edited by
+// hand or moved under another level are refused when loading
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+  // hash of the level a squad derives from, levels have none
+  pub level: Option<String>,
+  // hash of everything else in the file
+  pub content: String,
+}
+
+// the contents hashed by files of version 0, which had no version nor
+// metadata
+#[derive(Serialize)]
+struct UnversionedContents<'a> {
+  board: &'a Board,
+  materials: &'a Materials,
+  tokens: usize,
+  min_tokens: usize,
+  tiles: &'a Vec<Tile>,
+  entities: &'a [EntityState; NUM_TEMPLATES],
+  neutrals: &'a Vec<(Pos, Neutral)>,
+  loot: &'a LootRules,
+  costs: &'a ActionCosts,
+  combat: &'a CombatRules,
+  economy: &'a EconomyRules,
+  manifest: Option<Manifest>,
+}
+
+// layout of the files saved by this build
+pub const BF_VERSION: usize = 1;
+
+// the nth migration takes the json of a file from version n to n + 1
+const MIGRATIONS: [fn(&mut serde_json::Value); BF_VERSION] = [
+  // every field added before versions were recorded has a serde default
+  |_| {},
+];
+
+#[derive(Debug, Snafu)]
+pub enum LoadError {
+  #[snafu(display("{} does not exist", path.display()))]
+  Missing { path: PathBuf },
+  #[snafu(display("Could not read {}: {}", path.display(), source))]
+  Unreadable {
+    source: std::io::Error,
+    path: PathBuf,
+  },
+  #[snafu(display("{} is malformed: {}", path.display(), source))]
+  Malformed { source: BinaryError, path: PathBuf },
+  #[snafu(display(
+    "{} was saved with version {}, newer than {}",
+    path.display(),
+    version,
+    BF_VERSION
+  ))]
+  TooNew { version: usize, path: PathBuf },
+  #[snafu(display("{}: {}", path.display(), source))]
+  Untrusted {
+    source: IntegrityError,
+    path: PathBuf,
+  },
+  #[snafu(display("{} is malformed: {}", path.display(), source))]
+  Inconsistent {
+    source: ValidationError,
+    path: PathBuf,
+  },
+  #[snafu(display("Level of the squad: {}", source))]
+  BrokenLevel {
+    #[snafu(source(from(LoadError, Box::new)))]
+    source: Box<LoadError>,
+  },
+}
+
+#[derive(Debug, Snafu)]
+pub enum IntegrityError {
+  #[snafu(display("Squad has no manifest"))]
+  Unsealed {},
+  #[snafu(display("File was modified after it was saved"))]
+  Tampered {},
+  #[snafu(display("Squad derives from another level"))]
+  Orphaned {},
 }
 
 #[derive(Debug, Snafu)]
@@ -56,6 +205,144 @@ pub enum ValidationError {
   NotEnoughTokensToValidate { tokens: usize },
   #[snafu(display("Board not symmetric at {:?}", pos))]
   NotSymmetric { pos: Pos },
+  #[snafu(display("Board {:}x{:} differs from level", board.width, board.height))]
+  DifferentBoard { board: Board },
+  #[snafu(display("Invalid board {:}x{:} for {:?}", board.width, board.height, board.symmetry))]
+  InvalidBoardLevel { board: Board },
+  #[snafu(display("Tile ({:}, {:}) has template {:}, which does not exist", pos.x, pos.y, index))]
+  UnknownTemplate { pos: Pos, index: usize },
+  #[snafu(display("Cannot change terrain of level ({:}, {:})", pos.x, pos.y))]
+  ChangedTerrain { pos: Pos },
+  #[snafu(display("Cannot change neutral entities of level"))]
+  ChangedNeutrals {},
+  #[snafu(display("Cannot change the economy of level"))]
+  ChangedEconomy {},
+  #[snafu(display("Invalid economy: {:}", source))]
+  InvalidEconomy { source: EconomyError },
+}
+
+// a problem found while validating a squad against its level, with the
+// tile and the template it concerns, if any
+#[derive(Debug)]
+pub struct Diagnostic {
+  pub pos: Option<Pos>,
+  pub template: Option<usize>,
+  pub error: ValidationError,
+}
+
+impl From<ValidationError> for Diagnostic {
+  fn from(error: ValidationError) -> Self {
+    let (pos, template) = match &error {
+      ValidationError::RemoveEntityFromLevel { pos }
+      | ValidationError::RemoveMaterialFromLevel { pos }
+      | ValidationError::NotSymmetric { pos }
+      | ValidationError::ChangedTerrain { pos } => (Some(*pos), None),
+      ValidationError::UnknownTemplate { pos, index } => (Some(*pos), Some(*index)),
+      ValidationError::RemoveBotFromLevel { index }
+      | ValidationError::IncompatibleBot { index } => (None, Some(*index)),
+      _ => (None, None),
+    };
+    Diagnostic {
+      pos,
+      template,
+      error,
+    }
+  }
+}
+
+impl std::fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.error)
+  }
+}
+
+#[derive(Debug, Snafu)]
+pub enum BuildError {
+  #[snafu(display("A match needs between 2 and {teams} squads, got {squads}"))]
+  WrongNumberOfSquads { squads: usize, teams: usize },
+  #[snafu(display("Squad {squad} has {} problems with the level", diagnostics.len()))]
+  InvalidSquad {
+    squad: usize,
+    diagnostics: Vec<Diagnostic>,
+  },
+  #[snafu(display("Placing entity in {:?}", pos))]
+  PlacementError { source: StateError, pos: Pos },
+}
+
+// every way in which `squad` does not respect `level`
+pub fn validate(level: &BFState, squad: &BFState) -> Vec<Diagnostic> {
+  let mut problems: Vec<ValidationError> = vec![];
+  if squad.board != level.board {
+    // nothing else can be compared
+    return vec![ValidationError::DifferentBoard { board: squad.board }.into()];
+  }
+  // nor can files whose tiles cannot be indexed
+  for state in [level, squad] {
+    if let Err(e) = state.check_structure() {
+      return vec![e.into()];
+    }
+  }
+  if let Err(e) = squad.check_symmetry() {
+    problems.push(e);
+  }
+  // squads are priced with the economy of the level
+  if squad.economy != level.economy {
+    problems.push(ValidationError::ChangedEconomy {});
+  }
+  if let Err(source) = level.economy.validate() {
+    problems.push(ValidationError::InvalidEconomy { source });
+  }
+  // verify that costs match
+  let new_cost = squad.cost();
+  let ref_cost = level.cost();
+  if !(new_cost.0 <= ref_cost.0) {
+    problems.push(ValidationError::NotEnoughMaterial {});
+  }
+  if new_cost.1 > ref_cost.1 {
+    problems.push(ValidationError::NotEnoughTokensToValidate { tokens: new_cost.1 });
+  }
+  for i in 0..NUM_TEMPLATES {
+    if new_cost.2[i] < ref_cost.2[i] {
+      problems.push(ValidationError::RemoveBotFromLevel { index: i });
+    }
+  }
+  // loop through board, verify deletions
+  let board = squad.board;
+  for pos in board.region_iter() {
+    let ref_tile = &level.tiles[board.region_index(pos)];
+    let new_tile = &squad.tiles[board.region_index(pos)];
+    if ref_tile.entity_id.is_some() & (new_tile.entity_id != ref_tile.entity_id) {
+      problems.push(ValidationError::RemoveEntityFromLevel { pos });
+    }
+    if !(ref_tile.materials <= new_tile.materials) {
+      problems.push(ValidationError::RemoveMaterialFromLevel { pos });
+    }
+    if new_tile.terrain != ref_tile.terrain {
+      problems.push(ValidationError::ChangedTerrain { pos });
+    }
+  }
+  if squad.neutrals != level.neutrals {
+    problems.push(ValidationError::ChangedNeutrals {});
+  }
+  // loop through templates, verifying bots
+  for i in 0..NUM_TEMPLATES {
+    match (&squad.entities[i], &level.entities[i]) {
+      (EntityState::Empty, EntityState::Empty) => {}
+      (EntityState::Empty, _) => {
+        // already reported if the level placed any
+        if new_cost.2[i] >= ref_cost.2[i] {
+          problems.push(ValidationError::RemoveBotFromLevel { index: i });
+        }
+      }
+      (EntityState::Entity(e, _), EntityState::Entity(ref_e, _)) => {
+        if !e.compatible(ref_e) {
+          problems.push(ValidationError::IncompatibleBot { index: i });
+        }
+      }
+      (EntityState::Entity(_, _), EntityState::Empty) => {}
+    }
+  }
+  problems.into_iter().map(Diagnostic::from).collect()
 }
 
 #[derive(Debug, Snafu)]
@@ -64,6 +351,8 @@ pub enum UpdateError {
   EmptyTile { pos: Pos },
   #[snafu(display("Tile {:?} is occupied", pos))]
   TileOccupied { pos: Pos },
+  #[snafu(display("Tile {:?} cannot be walked on", pos))]
+  TileImpassable { pos: Pos },
   #[snafu(display("No bots of type {:} owned", index))]
   NoBotsOwned { index: usize },
   #[snafu(display("Cannot add empty bot {:}", index))]
@@ -90,44 +379,112 @@ pub enum UpdateError {
   InitTwice { index: usize },
   #[snafu(display("Out of bounds {:?}", pos))]
   OutOfBounds { pos: Pos },
+  #[snafu(display("Invalid board {:}x{:}", board.width, board.height))]
+  InvalidBoard { board: Board },
 }
 
-pub fn load_level_file(n: usize) -> Option<BFState> {
-  let path = Path::new("./levels");
-  let dest_filename = format!("{:05}", n);
-  let mut dest = path.join(dest_filename);
-  dest.set_extension("lvl");
-  if dest.exists() {
-    let mut file = File::open(dest).unwrap();
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
-    Some(serde_json::from_str(&contents).unwrap())
-  } else {
-    None
+// reads a level or squad saved with any version of the layout, in json or
+// binary, returning it along with the version it was saved with
+pub fn parse_bf(contents: &[u8], path: &Path) -> Result<(BFState, usize), LoadError> {
+  let mut value = binary::to_value(contents).context(MalformedSnafu { path })?;
+  let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+  ensure!(version <= BF_VERSION, TooNewSnafu { version, path });
+  for migration in &MIGRATIONS[version..] {
+    migration(&mut value);
+  }
+  let mut state: BFState = binary::from_value(value).context(MalformedSnafu { path })?;
+  state.version = BF_VERSION;
+  state
+    .check_structure()
+    .context(InconsistentSnafu { path })?;
+  Ok((state, version))
+}
+
+// parses a level, or a squad of `level`, and checks its manifest the way
+// the version the file was saved with computed it. Only then are older
+// files sealed again, as migrations change what their manifest hashed
+pub fn parse_verified(
+  contents: &[u8],
+  path: &Path,
+  level: Option<&BFState>,
+) -> Result<BFState, LoadError> {
+  let (mut state, version) = parse_bf(contents, path)?;
+  state
+    .verify_as(level, version)
+    .context(UntrustedSnafu { path })?;
+  if version < BF_VERSION {
+    state.seal(level);
   }
+  Ok(state)
 }
 
-pub fn load_squad_file(level: usize, n: usize) -> Option<BFState> {
-  let path = &Path::new("./squads/").join(format!("{:05}", level));
-  if !path.exists() {
-    return None;
-  };
-  let dest_filename = format!("{:05}", n);
-  let mut dest = path.join(dest_filename);
+fn read_bf(dest: &Path, level: Option<&BFState>) -> Result<BFState, LoadError> {
+  ensure!(dest.exists(), MissingSnafu { path: dest });
+  let contents = fs::read(dest).context(UnreadableSnafu { path: dest })?;
+  parse_verified(&contents, dest, level)
+}
+
+pub fn load_level_file(config: &Config, n: usize) -> Result<BFState, LoadError> {
+  let mut dest = config.levels_dir().join(format!("{:05}", n));
+  dest.set_extension("lvl");
+  read_bf(&dest, None)
+}
+
+pub fn load_squad_file(config: &Config, level: usize, n: usize) -> Result<BFState, LoadError> {
+  let mut dest = config.squads_dir(level).join(format!("{:05}", n));
   dest.set_extension("sqd");
-  if dest.exists() {
-    let mut file = File::open(dest).unwrap();
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
-    Some(serde_json::from_str(&contents).unwrap())
-  } else {
-    None
+  // a missing squad is reported as such even if its level is broken
+  ensure!(dest.exists(), MissingSnafu { path: dest });
+  let level = load_level_file(config, level).context(BrokenLevelSnafu)?;
+  read_bf(&dest, Some(&level))
+}
+
+// writes `state` under the next free number of `dir`
+fn write_bf(
+  config: &Config,
+  dir: &Path,
+  extension: &str,
+  state: &BFState,
+) -> std::io::Result<usize> {
+  fs::create_dir_all(dir)?;
+  let n = get_next_file_number(dir, extension.to_string());
+  let mut dest = dir.join(format!("{:05}", n));
+  dest.set_extension(extension);
+  fs::write(dest, binary::to_bytes(state, config.format))?;
+  Ok(n)
+}
+
+// seals and saves a new level, returning its number
+pub fn save_level_file(config: &Config, state: &BFState) -> std::io::Result<usize> {
+  let mut state = state.clone();
+  state.seal(None);
+  write_bf(config, &config.levels_dir(), "lvl", &state)
+}
+
+// seals and saves a new squad for the given level, returning its number
+pub fn save_squad_file(
+  config: &Config,
+  level: usize,
+  reference: &BFState,
+  state: &BFState,
+) -> std::io::Result<usize> {
+  let mut state = state.clone();
+  state.seal(Some(reference));
+  write_bf(config, &config.squads_dir(level), "sqd", &state)
+}
+
+impl LoadError {
+  pub fn is_missing(&self) -> bool {
+    matches!(self, LoadError::Missing { .. })
   }
 }
 
-pub fn join_tiles(blue: &BFState, red: &BFState) -> Vec<Tile> {
-  let mut result: Vec<Tile> = Vec::with_capacity(HEIGHT * WIDTH);
-  for _ in 0..HEIGHT * WIDTH {
+// the tiles of the whole board, with the region of each team taken from
+// its squad
+pub fn join_tiles(squads: &[&BFState]) -> Vec<Tile> {
+  let board = squads[0].board;
+  let mut result: Vec<Tile> = Vec::with_capacity(board.size());
+  for _ in 0..board.size() {
     result.push(Tile {
       materials: Materials {
         carbon: 0,
@@ -136,51 +493,63 @@ pub fn join_tiles(blue: &BFState, red: &BFState) -> Vec<Tile> {
         copper: 0,
       },
       entity_id: None,
+      terrain: Terrain::Floor,
     });
   }
-  for pos in half_board_iterator() {
-    result[pos.to_index()] = blue.get_tiles()[pos.to_index()].clone();
-    result[pos.invert().to_index()] = red.get_tiles()[pos.to_index()].clone();
+  for (team, squad) in squads.iter().enumerate() {
+    for pos in board.region_iter() {
+      result[board.index(board.from_team(Team(team), pos))] =
+        squad.get_tiles()[board.region_index(pos)].clone();
+    }
   }
   result
 }
 
-// TODO: make this function return errors instead of unwrapping.
-pub fn build_state(level: &BFState, blue: &BFState, red: &BFState) -> State {
-  assert!(blue.is_compatible(level).unwrap());
-  assert!(red.is_compatible(level).unwrap());
-  let joined_tiles = join_tiles(&blue, &red);
+// There is one squad per team, in the order of the regions of the board.
+// Regions left without a squad only keep the terrain and materials of the
+// level. Neutral entities are taken from the level in every region.
+pub fn build_state(level: &BFState, squads: &[&BFState]) -> Result<State, BuildError> {
+  let board = level.board;
+  ensure!(
+    squads.len() >= 2 && squads.len() <= board.teams(),
+    WrongNumberOfSquadsSnafu {
+      squads: squads.len(),
+      teams: board.teams(),
+    }
+  );
+  for (squad, state) in squads.iter().enumerate() {
+    let diagnostics = validate(level, state);
+    ensure!(
+      diagnostics.is_empty(),
+      InvalidSquadSnafu { squad, diagnostics }
+    );
+  }
 
   let mut state = State::new(
+    board,
     level.min_tokens,
-    init_array(|_| None),
-    init_array(|_| None),
+    squads.iter().map(|_| init_array(|_| None)).collect(),
     HashMap::<Id, ActiveEntity>::new(),
-    blue
-      .entities
-      .clone()
-      .into_iter()
-      .map(|es| match es {
-        EntityState::Entity(e, _) => e.try_into().ok(),
-        _ => None,
-      })
-      .collect::<Vec<Option<TemplateEntity>>>()
-      .try_into()
-      .unwrap(),
-    red
-      .entities
-      .clone()
-      .into_iter()
-      .map(|es| match es {
-        EntityState::Entity(e, _) => e.try_into().ok(),
-        _ => None,
+    squads
+      .iter()
+      .map(|squad| {
+        squad
+          .entities
+          .clone()
+          .into_iter()
+          .map(|es| match es {
+            EntityState::Entity(e, _) => e.try_into().ok(),
+            _ => None,
+          })
+          .collect::<Vec<Option<TemplateEntity>>>()
+          .try_into()
+          .unwrap()
       })
-      .collect::<Vec<Option<TemplateEntity>>>()
-      .try_into()
-      .unwrap(),
-    (0..(WIDTH * HEIGHT))
+      .collect(),
+    (0..board.size())
       .map(|_| Tile {
         entity_id: None,
+        terrain: Terrain::Floor,
         materials: Materials {
           carbon: 0,
           silicon: 0,
@@ -190,43 +559,261 @@ pub fn build_state(level: &BFState, blue: &BFState, red: &BFState) -> State {
       })
       .collect(),
   );
-  for pos in half_board_iterator() {
-    if let Some(id) = joined_tiles[pos.to_index()].entity_id {
-      let blue_entity = blue.entities[id].clone();
-      match blue_entity {
-        EntityState::Empty => unreachable!(),
-        EntityState::Entity(e, _) => {
-          state
-            .build_entity_from_template(Team::Blue, e.tokens, id, pos)
-            .unwrap();
-        }
+  state.loot = level.loot;
+  state.costs = level.costs;
+  state.combat = level.combat;
+  state.economy = level.economy;
+  for team in (0..board.teams()).map(Team) {
+    let squad = squads.get(team.0).unwrap_or(&level);
+    for pos in board.region_iter() {
+      let board_pos = board.from_team(team, pos);
+      let tile = &squad.tiles[board.region_index(pos)];
+      state.get_mut_tile(board_pos).materials = tile.materials.clone();
+      state.get_mut_tile(board_pos).terrain = level.tiles[board.region_index(pos)].terrain;
+      if let Some(neutral) = level.get_neutral(pos) {
+        state
+          .build_neutral(neutral, board_pos)
+          .context(PlacementSnafu { pos: board_pos })?;
+        continue;
       }
-    }
-    if let Some(id) = joined_tiles[pos.invert().to_index()].entity_id {
-      let red_entity = red.entities[id].clone();
-      match red_entity {
-        EntityState::Empty => unreachable!(),
-        EntityState::Entity(e, _) => {
-          state
-            .build_entity_from_template(Team::Red, e.tokens, id, pos.invert())
-            .unwrap();
-          if let EntityState::Entity(e, _) = &red.entities[id] {
-            state.get_mut_entity(pos.invert()).unwrap().tokens = e.tokens;
+      if team.0 >= squads.len() {
+        continue;
+      }
+      if let Some(id) = tile.entity_id {
+        match &squad.entities[id] {
+          EntityState::Empty => unreachable!(),
+          EntityState::Entity(e, _) => {
+            state
+              .build_entity_from_template(team, e.tokens, id, board_pos)
+              .context(PlacementSnafu { pos: board_pos })?;
           }
         }
       }
     }
-    state.get_mut_tile(pos).materials = blue.tiles[pos.to_index()].materials.clone();
-    state.get_mut_tile(pos.invert()).materials = red.tiles[pos.to_index()].materials.clone();
   }
-  state
+  Ok(state)
 }
 
 impl BFState {
+  pub fn get_board(&self) -> Board {
+    self.board
+  }
+
+  // changes the size or the symmetry of the board, returning what was on
+  // the tiles that are lost to the stock
+  pub fn resize(&mut self, board: Board) -> Result<(), UpdateError> {
+    ensure!(board.is_valid(), InvalidBoardSnafu { board });
+    let mut tiles: Vec<Tile> = (0..board.region_size())
+      .map(|_| Tile {
+        entity_id: None,
+        terrain: Terrain::Floor,
+        materials: Materials {
+          carbon: 0,
+          silicon: 0,
+          plutonium: 0,
+          copper: 0,
+        },
+      })
+      .collect();
+    for pos in self.board.region_iter() {
+      let tile = self.tiles[self.board.region_index(pos)].clone();
+      if board.contains_region(pos) {
+        tiles[board.region_index(pos)] = tile;
+      } else {
+        self.materials += tile.materials;
+        if let Some(i) = tile.entity_id {
+          if let EntityState::Entity(_, k) = &mut self.entities[i] {
+            *k += 1;
+          }
+        }
+      }
+    }
+    self.neutrals.retain(|(pos, _)| board.contains_region(*pos));
+    self.board = board;
+    self.tiles = tiles;
+    Ok(())
+  }
+
   pub fn get_tiles(&self) -> &Vec<Tile> {
     &self.tiles
   }
 
+  // the tiles of the whole board, with this squad in every region
+  pub fn mirrored_tiles(&self) -> Vec<Tile> {
+    join_tiles(&vec![self; self.board.teams()])
+  }
+
+  pub fn get_loot(&self) -> LootRules {
+    self.loot
+  }
+
+  pub fn set_loot(&mut self, loot: LootRules) {
+    self.loot = loot;
+  }
+
+  pub fn get_costs(&self) -> ActionCosts {
+    self.costs
+  }
+
+  pub fn set_costs(&mut self, costs: ActionCosts) {
+    self.costs = costs;
+  }
+
+  pub fn get_combat(&self) -> CombatRules {
+    self.combat
+  }
+
+  pub fn set_combat(&mut self, combat: CombatRules) {
+    self.combat = combat;
+  }
+
+  // hash of the file contents, leaving out the manifest
+  pub fn content_hash(&self) -> String {
+    self.content_hash_as(BF_VERSION)
+  }
+
+  // the hash that files of `version` recorded, they serialized the fields
+  // that version had
+  fn content_hash_as(&self, version: usize) -> String {
+    let serialized = if version == 0 {
+      serde_json::to_string(&UnversionedContents {
+        board: &self.board,
+        materials: &self.materials,
+        tokens: self.tokens,
+        min_tokens: self.min_tokens,
+        tiles: &self.tiles,
+        entities: &self.entities,
+        neutrals: &self.neutrals,
+        loot: &self.loot,
+        costs: &self.costs,
+        combat: &self.combat,
+        economy: &self.economy,
+        manifest: None,
+      })
+    } else {
+      let mut unsealed = self.clone();
+      unsealed.manifest = None;
+      serde_json::to_string(&unsealed)
+    };
+    format!("{:x}", Sha256::digest(serialized.unwrap().as_bytes()))
+  }
+
+  // records the hashes of this file and of its level, if it is a squad
+  pub fn seal(&mut self, level: Option<&BFState>) {
+    self.manifest = Some(Manifest {
+      level: level.map(|l| l.content_hash()),
+      content: self.content_hash(),
+    });
+  }
+
+  // levels saved before manifests existed are trusted, squads need one
+  // that matches them and `level` unless they are older than versions
+  pub fn verify(&self, level: Option<&BFState>) -> Result<(), IntegrityError> {
+    self.verify_as(level, BF_VERSION)
+  }
+
+  // like verify, for a file saved with `version`
+  fn verify_as(&self, level: Option<&BFState>, version: usize) -> Result<(), IntegrityError> {
+    let manifest = match (&self.manifest, level) {
+      (None, None) => return Ok(()),
+      // squads of version 0 may be from before manifests
+      (None, Some(_)) if version == 0 => return Ok(()),
+      (None, Some(_)) => return Err(IntegrityError::Unsealed {}),
+      (Some(manifest), _) => manifest,
+    };
+    ensure!(
+      manifest.content == self.content_hash_as(version),
+      TamperedSnafu
+    );
+    if let Some(level) = level {
+      ensure!(
+        manifest.level == Some(level.content_hash_as(version)),
+        OrphanedSnafu
+      );
+    }
+    Ok(())
+  }
+
+  pub fn get_economy(&self) -> EconomyRules {
+    self.economy
+  }
+
+  pub fn set_economy(&mut self, economy: EconomyRules) {
+    self.economy = economy;
+  }
+
+  // the code names of the wasm brains of the templates
+  pub fn brain_names(&self) -> Vec<String> {
+    self
+      .entities
+      .iter()
+      .filter_map(|e| match e {
+        EntityState::Entity(
+          MixTemplate {
+            brain: Mix::Full(full),
+            ..
+          },
+          _,
+        ) => Some(full.code_name.clone()),
+        _ => None,
+      })
+      .collect()
+  }
+
+  pub fn rename_brains(&mut self, rename: impl Fn(&str) -> String) {
+    for entity in self.entities.iter_mut() {
+      if let EntityState::Entity(
+        MixTemplate {
+          brain: Mix::Full(full),
+          ..
+        },
+        _,
+      ) = entity
+      {
+        full.code_name = rename(&full.code_name);
+      }
+    }
+  }
+
+  pub fn get_metadata(&self) -> &Metadata {
+    &self.metadata
+  }
+
+  pub fn set_metadata(&mut self, metadata: Metadata) {
+    self.metadata = metadata;
+  }
+
+  pub fn get_neutrals(&self) -> &Vec<(Pos, Neutral)> {
+    &self.neutrals
+  }
+
+  pub fn get_neutral(&self, pos: Pos) -> Option<Neutral> {
+    self
+      .neutrals
+      .iter()
+      .find(|(p, _)| *p == pos)
+      .map(|(_, neutral)| *neutral)
+  }
+
+  pub fn add_neutral(&mut self, neutral: Neutral, pos: Pos) -> Result<(), UpdateError> {
+    if !self.board.contains_region(pos) {
+      return Err(UpdateError::OutOfBounds { pos });
+    }
+    let tile = &self.tiles[self.board.region_index(pos)];
+    if tile.entity_id.is_some() || self.get_neutral(pos).is_some() {
+      return Err(UpdateError::TileOccupied { pos });
+    }
+    if !tile.terrain.is_walkable() {
+      return Err(UpdateError::TileImpassable { pos });
+    }
+    self.neutrals.push((pos, neutral));
+    Ok(())
+  }
+
+  pub fn erase_neutral(&mut self, pos: Pos) {
+    self.neutrals.retain(|(p, _)| *p != pos);
+  }
+
   pub fn get_entities(&self) -> &[EntityState; NUM_TEMPLATES] {
     &self.entities
   }
@@ -263,32 +850,32 @@ impl BFState {
     pos: Pos,
     amount: usize,
   ) -> Result<(), UpdateError> {
-    if !pos.is_withing_half() {
+    if !self.board.contains_region(pos) {
       return Err(UpdateError::OutOfBounds { pos });
     }
     self.try_sub_material(mat_name.clone(), amount)?;
     match mat_name {
       MatName::Carbon => {
-        self.tiles[pos.to_index()].materials.carbon += amount;
+        self.tiles[self.board.region_index(pos)].materials.carbon += amount;
       }
       MatName::Silicon => {
-        self.tiles[pos.to_index()].materials.silicon += amount;
+        self.tiles[self.board.region_index(pos)].materials.silicon += amount;
       }
       MatName::Plutonium => {
-        self.tiles[pos.to_index()].materials.plutonium += amount;
+        self.tiles[self.board.region_index(pos)].materials.plutonium += amount;
       }
       MatName::Copper => {
-        self.tiles[pos.to_index()].materials.copper += amount;
+        self.tiles[self.board.region_index(pos)].materials.copper += amount;
       }
     }
     Ok(())
   }
 
   pub fn erase_material_tile(&mut self, pos: Pos, remainder: Materials) -> Result<(), UpdateError> {
-    if !pos.is_withing_half() {
+    if !self.board.contains_region(pos) {
       return Err(UpdateError::OutOfBounds { pos });
     }
-    let tile = &mut self.tiles[pos.to_index()];
+    let tile = &mut self.tiles[self.board.region_index(pos)];
     if !(tile.materials >= remainder) {
       return Err(UpdateError::NotEnoughMaterialRemainder {});
     }
@@ -298,8 +885,21 @@ impl BFState {
     Ok(())
   }
 
+  pub fn set_terrain(&mut self, pos: Pos, terrain: Terrain) -> Result<(), UpdateError> {
+    if !self.board.contains_region(pos) {
+      return Err(UpdateError::OutOfBounds { pos });
+    }
+    let occupied = self.get_neutral(pos).is_some();
+    let tile = &mut self.tiles[self.board.region_index(pos)];
+    if !terrain.is_walkable() && (tile.entity_id.is_some() || occupied) {
+      return Err(UpdateError::TileOccupied { pos });
+    }
+    tile.terrain = terrain;
+    Ok(())
+  }
+
   pub fn add_bot_board(&mut self, bot_index: usize, pos: Pos) -> Result<(), UpdateError> {
-    if !pos.is_withing_half() {
+    if !self.board.contains_region(pos) {
       return Err(UpdateError::OutOfBounds { pos });
     }
     match &mut self.entities[bot_index] {
@@ -310,11 +910,18 @@ impl BFState {
         if *k == 0 {
           return Err(UpdateError::NoBotsOwned { index: bot_index });
         } else {
-          if self.tiles[pos.to_index()].entity_id.is_some() {
+          if self.tiles[self.board.region_index(pos)].entity_id.is_some()
+            || self.neutrals.iter().any(|(p, _)| *p == pos)
+          {
             return Err(UpdateError::TileOccupied { pos });
+          } else if !self.tiles[self.board.region_index(pos)]
+            .terrain
+            .is_walkable()
+          {
+            return Err(UpdateError::TileImpassable { pos });
           } else {
             *k -= 1;
-            self.tiles[pos.to_index()].entity_id = Some(bot_index);
+            self.tiles[self.board.region_index(pos)].entity_id = Some(bot_index);
           }
         }
       }
@@ -340,8 +947,14 @@ impl BFState {
             copper: 0,
           },
           movement_type: MovementType::Still,
+          steps: 1,
+          rest: 0,
           gun_damage: 0,
           drill_damage: 0,
+          sight: RANGE,
+          armour: 0,
+          can_repair: false,
+          can_upgrade: false,
           brain: Mix::Bare,
         },
         0,
@@ -365,10 +978,10 @@ impl BFState {
   }
 
   pub fn erase_bot_from_board(&mut self, pos: Pos) -> Result<(), UpdateError> {
-    if !pos.is_withing_half() {
+    if !self.board.contains_region(pos) {
       return Err(UpdateError::OutOfBounds { pos });
     }
-    let tile = &mut self.tiles[pos.to_index()];
+    let tile = &mut self.tiles[self.board.region_index(pos)];
     match &mut tile.entity_id {
       None => {
         return Err(UpdateError::EmptyTile { pos });
@@ -454,7 +1067,7 @@ impl BFState {
       EntityState::Entity(e, j) => {
         if *j > 0 {
           *j -= 1;
-          self.materials += cost_template(&e);
+          self.materials += cost_template(&e, &self.economy);
           let tokens = e.tokens;
           self.add_tokens(tokens);
           return Ok(());
@@ -471,7 +1084,7 @@ impl BFState {
         return Err(UpdateError::EmptyBot { index });
       }
       EntityState::Entity(e, j) => {
-        if !(self.materials >= cost_template(&e)) {
+        if !(self.materials >= cost_template(&e, &self.economy)) {
           return Err(UpdateError::NoMaterialToBuyBot { index });
         } else {
           let entity = e.clone();
@@ -480,7 +1093,7 @@ impl BFState {
           } else {
             *j += 1;
             self.try_sub_tokens(entity.tokens)?;
-            self.materials -= cost_template(&entity);
+            self.materials -= cost_template(&entity, &self.economy);
             return Ok(());
           }
         }
@@ -488,8 +1101,10 @@ impl BFState {
     }
   }
 
-  pub fn new() -> Self {
+  pub fn new(board: Board) -> Self {
     BFState {
+      version: BF_VERSION,
+      board,
       materials: Materials {
         carbon: 0,
         silicon: 0,
@@ -498,9 +1113,10 @@ impl BFState {
       },
       tokens: 0,
       min_tokens: 0,
-      tiles: (0..(WIDTH * HEIGHT / 2))
+      tiles: (0..board.region_size())
         .map(|_| Tile {
           entity_id: None,
+          terrain: Terrain::Floor,
           materials: Materials {
             carbon: 0,
             silicon: 0,
@@ -515,6 +1131,13 @@ impl BFState {
         EntityState::Empty,
         EntityState::Empty,
       ],
+      neutrals: vec![],
+      loot: LootRules::default(),
+      costs: ActionCosts::default(),
+      combat: CombatRules::default(),
+      economy: EconomyRules::default(),
+      metadata: Metadata::default(),
+      manifest: None,
     }
   }
 
@@ -533,18 +1156,14 @@ impl BFState {
       EntityState::Entity(e, k) => {
         let mut num_entities = *k;
         // loop through board, summing materials/entities
-        for pos in half_board_iterator() {
-          let tile_entity = self.tiles[pos.to_index()].entity_id;
+        for pos in self.board.region_iter() {
+          let tile_entity = self.tiles[self.board.region_index(pos)].entity_id;
           if tile_entity == Some(i) {
             num_entities += 1;
           }
         }
-        let mut entities_cost = cost_template(&e);
-        entities_cost.carbon *= num_entities;
-        entities_cost.silicon *= num_entities;
-        entities_cost.plutonium *= num_entities;
-        entities_cost.copper *= num_entities;
-        (entities_cost, e.tokens * num_entities)
+        let entities_cost = cost_template(&e, &self.economy).saturating_mul(num_entities);
+        (entities_cost, e.tokens.saturating_mul(num_entities))
       }
     }
   }
@@ -554,13 +1173,13 @@ impl BFState {
     let mut entities: [usize; 4] = [0; NUM_TEMPLATES];
     let mut tokens = self.tokens;
     // loop through board, summing materials/entities
-    for pos in half_board_iterator() {
-      let tile_entity = self.tiles[pos.to_index()].entity_id;
+    for pos in self.board.region_iter() {
+      let tile_entity = self.tiles[self.board.region_index(pos)].entity_id;
       if let Some(e) = tile_entity {
         entities[e] += 1;
       }
-      let tile_material = &self.tiles[pos.to_index()].materials;
-      material_cost += tile_material.clone();
+      let tile_material = &self.tiles[self.board.region_index(pos)].materials;
+      material_cost = material_cost.saturating_add(tile_material);
     }
     // loop through templates, summing entities costs
     for i in 0..NUM_TEMPLATES {
@@ -569,20 +1188,51 @@ impl BFState {
         EntityState::Empty => {}
         EntityState::Entity(e, k) => {
           entities[i] += *k;
-          let mut entities_cost = cost_template(&e);
-          entities_cost.carbon *= entities[i];
-          entities_cost.silicon *= entities[i];
-          entities_cost.plutonium *= entities[i];
-          entities_cost.copper *= entities[i];
-          material_cost += entities_cost;
-          tokens += e.tokens * entities[i];
+          let entities_cost = cost_template(&e, &self.economy).saturating_mul(entities[i]);
+          material_cost = material_cost.saturating_add(&entities_cost);
+          tokens = tokens.saturating_add(e.tokens.saturating_mul(entities[i]));
         }
       }
     }
     (material_cost, tokens, entities)
   }
 
+  // levels only store the region of the first team, which is repeated in
+  // every region, so the symmetry can only be broken by data that reaches
+  // outside of it, such as neutrals edited into the file by hand
+  // what the other checks index with: the tiles cover the region of the
+  // board and their entities come from templates that exist
+  pub fn check_structure(&self) -> Result<(), ValidationError> {
+    let board = self.board;
+    if !board.is_valid() || self.tiles.len() != board.region_size() {
+      return Err(ValidationError::InvalidBoardLevel { board });
+    }
+    for pos in board.region_iter() {
+      if let Some(index) = self.tiles[board.region_index(pos)].entity_id {
+        if !matches!(self.entities.get(index), Some(EntityState::Entity(..))) {
+          return Err(ValidationError::UnknownTemplate { pos, index });
+        }
+      }
+    }
+    Ok(())
+  }
+
+  pub fn check_symmetry(&self) -> Result<(), ValidationError> {
+    self.check_structure()?;
+    let board = self.board;
+    match self
+      .neutrals
+      .iter()
+      .find(|(pos, _)| !board.contains_region(*pos))
+    {
+      Some((pos, _)) => Err(ValidationError::NotSymmetric { pos: *pos }),
+      None => Ok(()),
+    }
+  }
+
   pub fn check_validity(&self) -> Result<(), ValidationError> {
+    self.check_symmetry()?;
+    self.economy.validate().context(InvalidEconomySnafu)?;
     let tokens = self.cost().1;
     if tokens < self.min_tokens {
       return Err(ValidationError::NotEnoughTokensToValidate { tokens });
@@ -591,53 +1241,11 @@ impl BFState {
     }
   }
 
+  // the first of the problems found by validate
   pub fn is_compatible(&self, reference: &BFState) -> Result<bool, ValidationError> {
-    // verify that costs match
-    let new_cost = self.cost();
-    let ref_cost = reference.cost();
-    if !(new_cost.0 <= ref_cost.0) {
-      return Err(ValidationError::NotEnoughMaterial {});
-    }
-    if new_cost.1 > ref_cost.1 {
-      return Err(ValidationError::NotEnoughTokensToValidate { tokens: new_cost.1 });
-    }
-    for i in 0..NUM_TEMPLATES {
-      if new_cost.2[i] < ref_cost.2[i] {
-        return Err(ValidationError::RemoveBotFromLevel { index: i });
-      }
-    }
-    // loop through board, verify deletions
-    for pos in half_board_iterator() {
-      let ref_entity = reference.tiles[pos.to_index()].entity_id;
-      let new_entity = self.tiles[pos.to_index()].entity_id;
-      if ref_entity.is_some() & (new_entity != ref_entity) {
-        return Err(ValidationError::RemoveEntityFromLevel { pos });
-      }
-      let ref_mat = &reference.tiles[pos.to_index()].materials;
-      let new_mat = &self.tiles[pos.to_index()].materials;
-      if !(ref_mat <= new_mat) {
-        return Err(ValidationError::RemoveMaterialFromLevel { pos });
-      }
-    }
-    // loop through templates, verifying bots
-    for i in 0..NUM_TEMPLATES {
-      let new_entity = &self.entities[i];
-      let ref_entity = &reference.entities[i];
-      match new_entity {
-        EntityState::Empty => {
-          if !matches!(ref_entity, EntityState::Empty) {
-            return Err(ValidationError::RemoveBotFromLevel { index: i });
-          }
-        }
-        EntityState::Entity(e, _) => {
-          if let EntityState::Entity(ref_e, _) = ref_entity {
-            if !e.compatible(ref_e) {
-              return Err(ValidationError::IncompatibleBot { index: i });
-            }
-          }
-        }
-      }
+    match validate(reference, self).into_iter().next() {
+      Some(diagnostic) => Err(diagnostic.error),
+      None => Ok(true),
     }
-    Ok(true)
   }
 }
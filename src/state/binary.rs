@@ -0,0 +1,284 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use snafu::prelude::*;
+use std::io::{Read, Write};
+
+// files start with these bytes when they are in the binary format, json
+// never does
+pub const MAGIC: &[u8; 4] = b"SHPB";
+// layout of the binary encoding, written after the magic bytes
+pub const BINARY_VERSION: u8 = 1;
+// limits on what files can make the reader build, our own nest a few
+// levels deep and repeat at most the tiles of the largest board
+pub const MAX_DEPTH: usize = 64;
+// bytes that binary data may inflate to, far beyond the replay of a long
+// match on the largest board
+pub const MAX_INFLATED: usize = 64 << 20;
+pub const MIN_ITEMS: usize = 1 << 20;
+pub const ITEMS_PER_BYTE: usize = 64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+  #[default]
+  Json,
+  // the json values in tagged binary, with runs of equal array items
+  // stored once, compressed with deflate
+  Binary,
+}
+
+#[derive(Debug, Snafu)]
+pub enum BinaryError {
+  #[snafu(display("{}", source))]
+  Json { source: serde_json::Error },
+  #[snafu(display("Binary version {} is newer than {}", version, BINARY_VERSION))]
+  NewerBinary { version: u8 },
+  #[snafu(display("Could not inflate: {}", source))]
+  Inflate { source: std::io::Error },
+  #[snafu(display("Binary data ends early"))]
+  Truncated {},
+  #[snafu(display("Unknown tag {} in binary data", tag))]
+  UnknownTag { tag: u8 },
+  #[snafu(display("Invalid string in binary data"))]
+  InvalidString {},
+  #[snafu(display("Binary data nests deeper than {}", MAX_DEPTH))]
+  TooDeep {},
+  #[snafu(display("Binary data holds more than {} items", limit))]
+  TooManyItems { limit: usize },
+  #[snafu(display("Binary data inflates to more than {} bytes", MAX_INFLATED))]
+  TooLarge {},
+}
+
+const NULL: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const UINT: u8 = 3;
+// stores -(n + 1)
+const NEGATIVE: u8 = 4;
+const FLOAT: u8 = 5;
+const STRING: u8 = 6;
+const ARRAY: u8 = 7;
+const OBJECT: u8 = 8;
+// inside arrays, the previous item repeated this many more times
+const REPEAT: u8 = 9;
+
+impl Format {
+  pub fn detect(bytes: &[u8]) -> Format {
+    if bytes.starts_with(MAGIC) {
+      Format::Binary
+    } else {
+      Format::Json
+    }
+  }
+}
+
+pub fn to_bytes<T: Serialize>(value: &T, format: Format) -> Vec<u8> {
+  match format {
+    Format::Json => serde_json::to_vec(value).unwrap(),
+    Format::Binary => {
+      let mut payload = vec![];
+      write_value(&serde_json::to_value(value).unwrap(), &mut payload);
+      let mut encoder = DeflateEncoder::new(MAGIC.to_vec(), Compression::best());
+      encoder.write_all(&[BINARY_VERSION]).unwrap();
+      encoder.write_all(&payload).unwrap();
+      encoder.finish().unwrap()
+    }
+  }
+}
+
+// reads json or binary data into json values, which is what migrations
+// work with
+pub fn to_value(bytes: &[u8]) -> Result<Value, BinaryError> {
+  match Format::detect(bytes) {
+    Format::Json => serde_json::from_slice(bytes).context(JsonSnafu),
+    Format::Binary => {
+      let mut payload = vec![];
+      DeflateDecoder::new(&bytes[MAGIC.len()..])
+        .take(MAX_INFLATED as u64 + 1)
+        .read_to_end(&mut payload)
+        .context(InflateSnafu)?;
+      ensure!(payload.len() <= MAX_INFLATED, TooLargeSnafu);
+      let (version, mut rest) = payload.split_first().context(TruncatedSnafu)?;
+      ensure!(
+        *version <= BINARY_VERSION,
+        NewerBinarySnafu { version: *version }
+      );
+      let limit = MIN_ITEMS.saturating_add(ITEMS_PER_BYTE.saturating_mul(rest.len()));
+      let mut budget = Budget { limit, left: limit };
+      read_value(&mut rest, 0, &mut budget)
+    }
+  }
+}
+
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, BinaryError> {
+  serde_json::from_value(value).context(JsonSnafu)
+}
+
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BinaryError> {
+  from_value(to_value(bytes)?)
+}
+
+fn write_varint(mut n: u64, out: &mut Vec<u8>) {
+  while n >= 0x80 {
+    out.push((n as u8) | 0x80);
+    n >>= 7;
+  }
+  out.push(n as u8);
+}
+
+fn write_str(s: &str, out: &mut Vec<u8>) {
+  write_varint(s.len() as u64, out);
+  out.extend_from_slice(s.as_bytes());
+}
+
+fn write_value(value: &Value, out: &mut Vec<u8>) {
+  match value {
+    Value::Null => out.push(NULL),
+    Value::Bool(false) => out.push(FALSE),
+    Value::Bool(true) => out.push(TRUE),
+    Value::Number(n) => {
+      if let Some(u) = n.as_u64() {
+        out.push(UINT);
+        write_varint(u, out);
+      } else if let Some(i) = n.as_i64() {
+        out.push(NEGATIVE);
+        write_varint(!(i as u64), out);
+      } else {
+        out.push(FLOAT);
+        out.extend_from_slice(&n.as_f64().unwrap().to_le_bytes());
+      }
+    }
+    Value::String(s) => {
+      out.push(STRING);
+      write_str(s, out);
+    }
+    Value::Array(items) => {
+      out.push(ARRAY);
+      // the number of entries, counting each run as one
+      let runs: Vec<&[Value]> = items.chunk_by(|a, b| a == b).collect();
+      let entries = runs
+        .iter()
+        .map(|r| if r.len() > 1 { 2 } else { 1 })
+        .sum::<u64>();
+      write_varint(entries, out);
+      for run in runs {
+        write_value(&run[0], out);
+        if run.len() > 1 {
+          out.push(REPEAT);
+          write_varint(run.len() as u64 - 1, out);
+        }
+      }
+    }
+    Value::Object(fields) => {
+      out.push(OBJECT);
+      write_varint(fields.len() as u64, out);
+      for (key, value) in fields {
+        write_str(key, out);
+        write_value(value, out);
+      }
+    }
+  }
+}
+
+fn read_byte(input: &mut &[u8]) -> Result<u8, BinaryError> {
+  let (byte, rest) = input.split_first().context(TruncatedSnafu)?;
+  *input = rest;
+  Ok(*byte)
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, BinaryError> {
+  let mut n = 0;
+  for shift in (0..64).step_by(7) {
+    let byte = read_byte(input)?;
+    n |= ((byte & 0x7f) as u64) << shift;
+    if byte < 0x80 {
+      return Ok(n);
+    }
+  }
+  TruncatedSnafu.fail()
+}
+
+fn read_str(input: &mut &[u8]) -> Result<String, BinaryError> {
+  let len = read_varint(input)? as usize;
+  ensure!(input.len() >= len, TruncatedSnafu);
+  let (s, rest) = input.split_at(len);
+  *input = rest;
+  String::from_utf8(s.to_vec())
+    .ok()
+    .context(InvalidStringSnafu)
+}
+
+// how many more values the reader may build
+struct Budget {
+  limit: usize,
+  left: usize,
+}
+
+impl Budget {
+  fn spend(&mut self, items: usize) -> Result<(), BinaryError> {
+    self.left = self
+      .left
+      .checked_sub(items)
+      .context(TooManyItemsSnafu { limit: self.limit })?;
+    Ok(())
+  }
+}
+
+// the number of values in `value`, counting itself
+fn size(value: &Value) -> usize {
+  match value {
+    Value::Array(items) => 1 + items.iter().map(size).sum::<usize>(),
+    Value::Object(fields) => 1 + fields.values().map(size).sum::<usize>(),
+    _ => 1,
+  }
+}
+
+fn read_value(input: &mut &[u8], depth: usize, budget: &mut Budget) -> Result<Value, BinaryError> {
+  ensure!(depth < MAX_DEPTH, TooDeepSnafu);
+  budget.spend(1)?;
+  Ok(match read_byte(input)? {
+    NULL => Value::Null,
+    FALSE => Value::Bool(false),
+    TRUE => Value::Bool(true),
+    UINT => Value::from(read_varint(input)?),
+    NEGATIVE => Value::from(!read_varint(input)? as i64),
+    FLOAT => {
+      ensure!(input.len() >= 8, TruncatedSnafu);
+      let (bytes, rest) = input.split_at(8);
+      *input = rest;
+      let f = f64::from_le_bytes(bytes.try_into().unwrap());
+      Number::from_f64(f).map_or(Value::Null, Value::Number)
+    }
+    STRING => Value::String(read_str(input)?),
+    ARRAY => {
+      let entries = read_varint(input)?;
+      let mut items: Vec<Value> = vec![];
+      for _ in 0..entries {
+        if input.first() == Some(&REPEAT) {
+          read_byte(input)?;
+          let times = read_varint(input)?.try_into().unwrap_or(usize::MAX);
+          let last = items.last().context(TruncatedSnafu)?.clone();
+          budget.spend(size(&last).saturating_mul(times))?;
+          items.extend(std::iter::repeat_n(last, times));
+        } else {
+          items.push(read_value(input, depth + 1, budget)?);
+        }
+      }
+      Value::Array(items)
+    }
+    OBJECT => {
+      let len = read_varint(input)?;
+      let mut fields = Map::new();
+      for _ in 0..len {
+        let key = read_str(input)?;
+        fields.insert(key, read_value(input, depth + 1, budget)?);
+      }
+      Value::Object(fields)
+    }
+    tag => return UnknownTagSnafu { tag }.fail(),
+  })
+}
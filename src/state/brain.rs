@@ -4,41 +4,47 @@ use rand::rngs::StdRng;
 use rand::Rng;
 use rand::SeedableRng;
 use snafu::prelude::*;
-use std::path::{Path, PathBuf};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::Arc;
 use std::sync::Mutex;
 
 use init_array::init_array;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use wasmer::{
   imports, CompileError, ExportError, Function, FunctionEnv, FunctionEnvMut, Instance,
-  InstantiationError, Module, RuntimeError, Store, Value,
+  InstantiationError, Memory, Module, RuntimeError, Store, Value,
 };
 
-use crate::state::constants::{NUM_TEMPLATES, RANGE};
+use crate::state::config::Config;
+use crate::state::constants::{MAX_FAULTS, MAX_LOGS_PER_TURN, MAX_LOG_LENGTH, NUM_TEMPLATES};
 use crate::state::encoder::{
-  decode_displace, decode_verb, encode_coord, encode_materials, encode_view, ViewResult,
+  decode_displace, encode_combat_rules, encode_coord, encode_materials, encode_terrain,
+  encode_view, try_decode_verb, ViewResult, ViewedEntity,
 };
-use crate::state::entity::Team;
-use crate::state::geometry::{add_displace, Pos};
-use crate::state::state::{Command, Id, State, StateError};
+use crate::state::geometry::Pos;
+use crate::state::neutral::builtin_brain;
+use crate::state::state::{
+  Command, Fault, FaultAction, FaultKind, Id, Log, State, StateError, Verb,
+};
+use tools::host::{with_host, Bot, Host};
 
 #[derive(Debug, Snafu)]
 pub enum BrainError {
-  #[snafu(display("Could not create module {:}", index))]
+  #[snafu(display("Could not create module of template {:}", index))]
   CreateModule { source: CompileError, index: usize },
-  #[snafu(display("Could not create instance {:}", index))]
-  CreateInstance {
-    source: InstantiationError,
-    index: usize,
-  },
-  #[snafu(display("Could not load wasm code {:}", index))]
+  #[snafu(display("Could not create instance for entity {:}", id))]
+  CreateInstance { source: InstantiationError, id: Id },
+  #[snafu(display("Could not load wasm code of template {:} from {}", index, path.display()))]
   LoadWasm {
     source: std::io::Error,
     index: usize,
+    path: PathBuf,
   },
   #[snafu(display("No entity in state"))]
   NoEntity { source: StateError },
+  #[snafu(display("No template of entity {:} has brain {}", id, code_name))]
+  MissingBrain { id: Id, code_name: String },
 }
 
 #[derive(Debug, Snafu)]
@@ -47,15 +53,72 @@ pub enum ExecutionError {
   NoExecute { source: ExportError, index: usize },
   #[snafu(display("Error executing code for bot {:}", index))]
   Runtime { source: RuntimeError, index: usize },
+  #[snafu(display("Bot {:} panicked", index))]
+  Panic { index: usize },
+  #[snafu(display("Bot {:} did not return an i64", index))]
+  InvalidReturn { index: usize },
+  #[snafu(display("Bot {:} returned invalid opcode {:#x}", index, opcode))]
+  InvalidOpcode { index: usize, opcode: i64 },
+  #[snafu(display("No entity {:} in state", index))]
+  NoCurrentEntity { source: StateError, index: usize },
+}
+
+impl ExecutionError {
+  // the fault to record, for errors caused by the bot itself
+  fn fault_kind(&self) -> Option<FaultKind> {
+    match self {
+      ExecutionError::NoExecute { .. } => Some(FaultKind::NoExecute),
+      ExecutionError::Runtime { source, .. } => Some(FaultKind::Trap {
+        message: source.message(),
+      }),
+      ExecutionError::Panic { .. } => Some(FaultKind::Trap {
+        message: "panic".to_string(),
+      }),
+      ExecutionError::InvalidReturn { .. } => Some(FaultKind::InvalidReturn),
+      ExecutionError::InvalidOpcode { opcode, .. } => {
+        Some(FaultKind::InvalidOpcode { opcode: *opcode })
+      }
+      ExecutionError::NoCurrentEntity { .. } => None,
+    }
+  }
+}
+
+// Bots that fault (trap, lack an execute function or return something that
+// is not a verb) wait for that turn. After `max_faults` of them, `action`
+// is applied to the entity.
+#[derive(Debug, Clone, Copy)]
+pub struct FaultPolicy {
+  pub max_faults: usize,
+  pub action: FaultAction,
+}
+
+impl Default for FaultPolicy {
+  fn default() -> Self {
+    FaultPolicy {
+      max_faults: MAX_FAULTS,
+      action: FaultAction::Disable,
+    }
+  }
+}
+
+// Bots linked into the engine, by the code name that templates use to refer
+// to them. Templates whose code name is not here are loaded as wasm files.
+pub type NativeBrains = HashMap<String, fn() -> Box<dyn Bot>>;
+
+enum Brain {
+  Wasm(Instance),
+  Native(Box<dyn Bot>),
 }
 
 pub struct Brains {
   store: Store,
   env: Env,
-  blue_modules: [Option<Module>; NUM_TEMPLATES],
-  red_modules: [Option<Module>; NUM_TEMPLATES],
-  blue_brains: HashMap<Id, Option<Instance>>,
-  red_brains: HashMap<Id, Option<Instance>>,
+  // indexed by team
+  modules: Vec<[Option<Module>; NUM_TEMPLATES]>,
+  brains: HashMap<Id, Option<Brain>>,
+  fault_policy: FaultPolicy,
+  fault_counts: HashMap<Id, usize>,
+  faults: Vec<Fault>,
 }
 
 #[derive(Clone)]
@@ -63,48 +126,50 @@ struct Env {
   state: Arc<Mutex<State>>,
   current: Arc<Mutex<Id>>,
   rng: Arc<Mutex<StdRng>>,
+  // memory of the wasm instance being executed, to read strings from
+  memory: Arc<Mutex<Option<Memory>>>,
+  // messages logged during the current turn
+  logs: Arc<Mutex<Vec<Log>>>,
 }
 
-fn get_unencoded_coord(env: FunctionEnvMut<Env>) -> Pos {
-  let state = env.data().state.lock().unwrap();
-  let current = env.data().current.lock().unwrap();
-  let entity = state.get_entity_by_id(*current).unwrap();
-  match entity.team {
-    Team::Blue => entity.pos,
-    Team::Red => entity.pos.invert(),
-  }
+fn get_unencoded_coord(env: &Env) -> Option<Pos> {
+  let state = env.state.lock().unwrap();
+  let current = env.current.lock().unwrap();
+  let entity = state.get_entity_by_id(*current).ok()?;
+  Some(state.board.to_team(entity.side(), entity.pos))
 }
 
 // the function that the bot uses to get its coordinate from the enviroment
-fn get_coord(env: FunctionEnvMut<Env>) -> u32 {
-  let pos = get_unencoded_coord(env);
-  encode_coord(pos.x, pos.y)
+fn get_coord(env: &Env) -> u32 {
+  match get_unencoded_coord(env) {
+    Some(pos) => encode_coord(pos.x, pos.y),
+    None => 0,
+  }
 }
 
 // the function that the bot uses to get its coordinate from the enviroment
-fn get_rand(env: FunctionEnvMut<Env>) -> u32 {
-  let mut rng = env.data().rng.lock().unwrap();
+fn get_rand(env: &Env) -> u32 {
+  let mut rng = env.rng.lock().unwrap();
   rng.gen_range(0..0xFFFFFFFF)
 }
 
 // the function that the bot uses to get the materials in a tile around it
-fn get_materials(env: FunctionEnvMut<Env>, encoded_displace: u16) -> i64 {
-  let state = env.data().state.lock().unwrap();
-  let current = env.data().current.lock().unwrap();
-  let entity = state.get_entity_by_id(*current).unwrap();
-  let pos = entity.pos;
-  let displ = match entity.team {
-    Team::Blue => decode_displace(encoded_displace),
-    Team::Red => decode_displace(encoded_displace).invert(),
+fn get_materials(env: &Env, encoded_displace: u16) -> i64 {
+  let state = env.state.lock().unwrap();
+  let current = env.current.lock().unwrap();
+  let entity = match state.get_entity_by_id(*current) {
+    Ok(entity) => entity,
+    Err(_) => return 0x0000000000000000,
   };
-  if (displ.x < -(RANGE as i64))
-    && (displ.x > RANGE as i64)
-    && (displ.y < -(RANGE as i64))
-    && (displ.y > RANGE as i64)
-  {
+  let pos = entity.pos;
+  let displ = state
+    .board
+    .symmetry
+    .displace_from_team(entity.side(), &decode_displace(encoded_displace));
+  if !state.is_visible(pos, &displ, entity.get_sight()) {
     return 0x0000000000000000;
   }
-  match add_displace(pos, &displ) {
+  match state.board.add_displace(pos, &displ) {
     Err(_) => {
       return 0x0000000000000000;
     }
@@ -115,157 +180,263 @@ fn get_materials(env: FunctionEnvMut<Env>, encoded_displace: u16) -> i64 {
   }
 }
 
-// the function that the bot uses to get the bot in a tile around it
-fn get_entity(env: FunctionEnvMut<Env>, encoded_displace: u16) -> i64 {
-  let state = env.data().state.lock().unwrap();
-  let current = env.data().current.lock().unwrap();
-  let entity = state.get_entity_by_id(*current).unwrap();
+// the function that the bot uses to get the terrain of a tile around it
+fn get_terrain(env: &Env, encoded_displace: u16) -> i64 {
+  let state = env.state.lock().unwrap();
+  let current = env.current.lock().unwrap();
+  let entity = match state.get_entity_by_id(*current) {
+    Ok(entity) => entity,
+    Err(_) => return 0x0000000000000000,
+  };
   let pos = entity.pos;
-  let displ = match entity.team {
-    Team::Blue => decode_displace(encoded_displace),
-    Team::Red => decode_displace(encoded_displace).invert(),
+  let displ = state
+    .board
+    .symmetry
+    .displace_from_team(entity.side(), &decode_displace(encoded_displace));
+  if !state.is_visible(pos, &displ, entity.get_sight()) {
+    return 0x0000000000000000;
+  }
+  match state.board.add_displace(pos, &displ) {
+    Err(_) => 0x0000000000000000,
+    Ok(target_pos) => (encode_terrain(state.get_terrain(target_pos)) as i64) + 0x0001000000000000,
+  }
+}
+
+// the function that the bot uses to get the bot in a tile around it
+fn get_entity(env: &Env, encoded_displace: u16) -> i64 {
+  let state = env.state.lock().unwrap();
+  let current = env.current.lock().unwrap();
+  let entity = match state.get_entity_by_id(*current) {
+    Ok(entity) => entity,
+    Err(_) => return encode_view(ViewResult::Error),
   };
-  if (displ.x < -(RANGE as i64))
-    && (displ.x > RANGE as i64)
-    && (displ.y < -(RANGE as i64))
-    && (displ.y > RANGE as i64)
-  {
+  let pos = entity.pos;
+  let displ = state
+    .board
+    .symmetry
+    .displace_from_team(entity.side(), &decode_displace(encoded_displace));
+  if !state.is_visible(pos, &displ, entity.get_sight()) {
     return encode_view(ViewResult::OutOfBounds);
   }
-  encode_view(match state.get_visible(pos, &displ) {
-    None => ViewResult::OutOfBounds,
-    Some(viewed_pos) => match state.get_tile(viewed_pos).entity_id {
+  encode_view(match state.board.add_displace(pos, &displ) {
+    Err(_) => ViewResult::OutOfBounds,
+    Ok(viewed_pos) => match state.get_tile(viewed_pos).entity_id {
       None => ViewResult::Empty,
       Some(viewed_entity_id) => match state.get_entity_by_id(viewed_entity_id) {
         Err(_) => ViewResult::Error,
-        Ok(viewed_entity) => ViewResult::Entity(match entity.team {
-          Team::Blue => viewed_entity.clone().into(),
-          Team::Red => {
-            let mut inverted_color = viewed_entity.clone();
-            inverted_color.team = inverted_color.team.invert();
-            inverted_color.into()
-          }
-        }),
+        Ok(viewed_entity) => ViewResult::Entity(ViewedEntity::new(
+          viewed_entity.clone(),
+          entity.team,
+          state.teams(),
+        )),
       },
     },
   })
 }
 
+// the function that the bot uses to learn how shots hit and hurt
+fn get_rules(env: &Env) -> i64 {
+  encode_combat_rules(env.state.lock().unwrap().combat)
+}
+
+// the function that the bot uses to print debug messages. Each bot gets a
+// few lines of limited length per turn, the rest is dropped.
+fn debug_log(env: &Env, message: &str) {
+  let current = env.current.lock().unwrap();
+  let mut logs = env.logs.lock().unwrap();
+  let count = logs.iter().filter(|l| l.entity_id == *current).count();
+  if count >= MAX_LOGS_PER_TURN {
+    return;
+  }
+  logs.push(Log {
+    entity_id: *current,
+    message: message.chars().take(MAX_LOG_LENGTH).collect(),
+  });
+}
+
+// wasm imports, forwarding to the functions above
+fn wasm_get_coord(env: FunctionEnvMut<Env>) -> u32 {
+  get_coord(env.data())
+}
+
+fn wasm_get_rand(env: FunctionEnvMut<Env>) -> u32 {
+  get_rand(env.data())
+}
+
+fn wasm_get_materials(env: FunctionEnvMut<Env>, encoded_displace: u16) -> i64 {
+  get_materials(env.data(), encoded_displace)
+}
+
+fn wasm_get_entity(env: FunctionEnvMut<Env>, encoded_displace: u16) -> i64 {
+  get_entity(env.data(), encoded_displace)
+}
+
+fn wasm_get_terrain(env: FunctionEnvMut<Env>, encoded_displace: u16) -> i64 {
+  get_terrain(env.data(), encoded_displace)
+}
+
+fn wasm_get_rules(env: FunctionEnvMut<Env>) -> i64 {
+  get_rules(env.data())
+}
+
+fn wasm_debug_log(env: FunctionEnvMut<Env>, ptr: u32, len: u32) {
+  let memory = match env.data().memory.lock().unwrap().clone() {
+    Some(memory) => memory,
+    None => return,
+  };
+  let mut bytes = vec![0; (len as usize).min(4 * MAX_LOG_LENGTH)];
+  if memory.view(&env).read(ptr as u64, &mut bytes).is_err() {
+    return;
+  }
+  debug_log(env.data(), &String::from_utf8_lossy(&bytes));
+}
+
+// the same functions, offered to bots linked natively
+struct NativeHost {
+  env: Env,
+}
+
+impl Host for NativeHost {
+  fn get_coord(&self) -> u32 {
+    get_coord(&self.env)
+  }
+  fn get_materials(&self, displace: u16) -> i64 {
+    get_materials(&self.env, displace)
+  }
+  fn get_entity(&self, displace: u16) -> i64 {
+    get_entity(&self.env, displace)
+  }
+  fn get_terrain(&self, displace: u16) -> i64 {
+    get_terrain(&self.env, displace)
+  }
+  fn get_rand(&self) -> u32 {
+    get_rand(&self.env)
+  }
+  fn get_rules(&self) -> i64 {
+    get_rules(&self.env)
+  }
+  fn debug_log(&self, message: &str) {
+    debug_log(&self.env, message)
+  }
+}
+
 impl Brains {
-  pub fn new(state: Arc<Mutex<State>>) -> Result<Self, BrainError> {
+  pub fn new(config: &Config, state: Arc<Mutex<State>>) -> Result<Self, BrainError> {
+    Brains::new_with_natives(config, state, &NativeBrains::new())
+  }
+
+  pub fn new_with_natives(
+    config: &Config,
+    state: Arc<Mutex<State>>,
+    natives: &NativeBrains,
+  ) -> Result<Self, BrainError> {
     let id_vec = state.lock().unwrap().get_entities_ids();
     let mut store = Store::default();
 
     let current = Arc::new(Mutex::new(0));
     let rng = Arc::new(Mutex::new(StdRng::from_entropy()));
+    let memory = Arc::new(Mutex::new(None));
+    let logs = Arc::new(Mutex::new(vec![]));
     let env = FunctionEnv::new(
       &mut store,
       Env {
         state: state.clone(),
         current: current.clone(),
         rng: rng.clone(),
+        memory: memory.clone(),
+        logs: logs.clone(),
       },
     );
 
     let import_object = imports! {
               "env" => {
                   "get_coord" => Function::new_typed_with_env
-                  (&mut store, &env, get_coord),
+                  (&mut store, &env, wasm_get_coord),
                   "get_materials" => Function::new_typed_with_env
-                  (&mut store, &env, get_materials),
+                  (&mut store, &env, wasm_get_materials),
                   "get_entity" => Function::new_typed_with_env
-                  (&mut store, &env, get_entity),
+                  (&mut store, &env, wasm_get_entity),
+                  "get_terrain" => Function::new_typed_with_env
+                  (&mut store, &env, wasm_get_terrain),
                   "get_rand" => Function::new_typed_with_env
-                  (&mut store, &env, get_rand)
+                  (&mut store, &env, wasm_get_rand),
+                  "get_rules" => Function::new_typed_with_env
+                  (&mut store, &env, wasm_get_rules),
+                  "debug_log" => Function::new_typed_with_env
+                  (&mut store, &env, wasm_debug_log)
               },
     };
 
     //let code_vec: HashMap<u128, String> = get_code_vec();
 
-    let mut blue_modules: [Option<Module>; NUM_TEMPLATES] = init_array(|_| None);
-    let mut blue_brain_index: HashMap<String, usize> = HashMap::new();
-    let mut red_modules: [Option<Module>; NUM_TEMPLATES] = init_array(|_| None);
-    let mut red_brain_index: HashMap<String, usize> = HashMap::new();
-    {
-      let blue_templates = state.lock().unwrap().blue_templates.clone();
-      println!("{:?}", blue_templates);
-    }
-
-    for (index, template) in state
-      .lock()
-      .unwrap()
-      .blue_templates
-      .clone()
-      .iter()
-      .enumerate()
-    {
-      if let Some(template_entity) = template {
-        if let Some(brain) = template_entity.brain.clone() {
-          // println!(
-          //   "index: {index}, brain.code = {:?}, code_vec[index] = {:?}",
-          //   brain.code_index, code_vec[index]
-          // );
-          let wasm_bytes = std::fs::read(Path::new(&brain.code_name))
-            .context(LoadWasmSnafu { index: 0 as usize })?;
-          blue_brain_index.insert(brain.code_name, index);
-          let module =
-            Module::new(&store, wasm_bytes).context(CreateModuleSnafu { index: 0 as usize })?;
-          blue_modules[index] = Some(module.clone());
-        }
-      }
-    }
+    let templates = state.lock().unwrap().templates.clone();
+    let mut modules: Vec<[Option<Module>; NUM_TEMPLATES]> =
+      templates.iter().map(|_| init_array(|_| None)).collect();
+    let mut brain_indices: Vec<HashMap<String, usize>> =
+      templates.iter().map(|_| HashMap::new()).collect();
 
-    for (index, template) in state
-      .lock()
-      .unwrap()
-      .red_templates
-      .clone()
-      .iter()
-      .enumerate()
-    {
-      if let Some(template_entity) = template {
-        if let Some(brain) = template_entity.brain.clone() {
-          let wasm_bytes = std::fs::read(Path::new(&brain.code_name))
-            .context(LoadWasmSnafu { index: 0 as usize })?;
-          red_brain_index.insert(brain.code_name, index);
-          let module =
-            Module::new(&store, wasm_bytes).context(CreateModuleSnafu { index: 0 as usize })?;
-          red_modules[index] = Some(module.clone());
+    for (team, team_templates) in templates.iter().enumerate() {
+      for (index, template) in team_templates.iter().enumerate() {
+        if let Some(template_entity) = template {
+          if let Some(brain) = template_entity.brain.clone() {
+            if natives.contains_key(&brain.code_name) {
+              continue;
+            }
+            let path = config.bot_path(&brain.code_name);
+            let wasm_bytes = std::fs::read(&path).context(LoadWasmSnafu { index, path })?;
+            brain_indices[team].insert(brain.code_name, index);
+            let module = Module::new(&store, wasm_bytes).context(CreateModuleSnafu { index })?;
+            modules[team][index] = Some(module.clone());
+          }
         }
       }
     }
 
-    let mut blue_brains: HashMap<Id, Option<Instance>> = HashMap::new();
-    let mut red_brains: HashMap<Id, Option<Instance>> = HashMap::new();
+    let mut brains: HashMap<Id, Option<Brain>> = HashMap::new();
 
     for id in id_vec {
       let state_guard = state.lock().unwrap();
       let entity = state_guard.get_entity_by_id(id).context(NoEntitySnafu {})?;
-      let (module_vec, brains, brain_index): (
-        &[Option<Module>; NUM_TEMPLATES],
-        &mut HashMap<Id, Option<Instance>>,
-        &HashMap<String, usize>,
-      ) = match entity.team {
-        Team::Blue => (&blue_modules, &mut blue_brains, &blue_brain_index),
-        Team::Red => (&red_modules, &mut red_brains, &red_brain_index),
+      if let Some(factory) = entity.brain.as_ref().and_then(|brain| {
+        natives
+          .get(&brain.code_name)
+          .copied()
+          .or_else(|| builtin_brain(&brain.code_name))
+      }) {
+        brains.insert(id, Some(Brain::Native(factory())));
+        continue;
+      }
+      // neutral entities only run the brains built into the engine
+      let team = match entity.team {
+        Some(team) => team,
+        None => {
+          brains.insert(id, None);
+          continue;
+        }
       };
+      let (module_vec, brain_index) = (&modules[team.0], &brain_indices[team.0]);
       let optional_module = match entity.brain.clone() {
         None => None,
         Some(brain) => {
           // TODO: this is terrible, so one needs to fix it. The problem is that
           // once we instantiate an entity from a template, we erase the information
           // about which template we used.
-          module_vec[*brain_index.get(&brain.code_name).unwrap()].clone()
+          let index = brain_index
+            .get(&brain.code_name)
+            .context(MissingBrainSnafu {
+              id,
+              code_name: brain.code_name.clone(),
+            })?;
+          module_vec[*index].clone()
         }
       };
       let instance: Option<Instance> = match optional_module {
         None => None,
         Some(module) => Some(
-          Instance::new(&mut store, &module, &import_object)
-            .context(CreateInstanceSnafu { index: 0 as usize })?,
+          Instance::new(&mut store, &module, &import_object).context(CreateInstanceSnafu { id })?,
         ),
       };
-      brains.insert(id, instance.clone());
+      brains.insert(id, instance.map(Brain::Wasm));
     }
 
     Ok(Brains {
@@ -274,35 +445,82 @@ impl Brains {
         state: state.clone(),
         current: current.clone(),
         rng: rng.clone(),
+        memory,
+        logs,
       },
-      blue_modules,
-      red_modules,
-      blue_brains,
-      red_brains,
+      modules,
+      brains,
+      fault_policy: FaultPolicy::default(),
+      fault_counts: HashMap::new(),
+      faults: vec![],
     })
   }
 
-  pub fn get_command(&mut self, id: usize) -> Result<Command, ExecutionError> {
-    // in our enviroment, we first update the current bot
-    let mut current = self.env.current.lock().unwrap();
-    *current = id;
-    let state = self.env.state.lock().unwrap();
-    let entity = state.get_entity_by_id(*current).unwrap();
-    let team = entity.team.clone();
-    drop(current);
-    drop(state);
-    let current_brain = match team {
-      Team::Blue => &self.blue_brains,
-      Team::Red => &self.red_brains,
+  pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+    self.fault_policy = policy;
+  }
+
+  // faults recorded since the last call, to be passed to State::apply_faults
+  pub fn take_faults(&mut self) -> Vec<Fault> {
+    std::mem::take(&mut self.faults)
+  }
+
+  // messages logged by the bots since the last call
+  pub fn take_logs(&mut self) -> Vec<Log> {
+    std::mem::take(&mut *self.env.logs.lock().unwrap())
+  }
+
+  // the command of a bot for this turn. Faults of the bot are recorded and
+  // turned into a Wait, so the only error is asking for a missing entity.
+  pub fn get_command(&mut self, id: Id) -> Result<Command, ExecutionError> {
+    let (team, symmetry) = {
+      let state = self.env.state.lock().unwrap();
+      let entity = state
+        .get_entity_by_id(id)
+        .context(NoCurrentEntitySnafu { index: id })?;
+      (entity.side(), state.board.symmetry)
     };
-    match current_brain.get(&id) {
-      None | Some(None) => {
-        return Ok(Command {
-          entity_id: id,
-          verb: super::state::Verb::Wait,
-        })
+    let verb = match self.get_verb(id) {
+      Ok(verb) => verb,
+      Err(e) => {
+        let kind = e.fault_kind().ok_or(e)?;
+        self.record_fault(id, kind);
+        Verb::Wait
       }
-      Some(Some(instance)) => {
+    };
+    Ok(Command {
+      entity_id: id,
+      verb: verb.from_team(symmetry, team),
+    })
+  }
+
+  fn record_fault(&mut self, id: Id, kind: FaultKind) {
+    let count = self.fault_counts.entry(id).or_insert(0);
+    *count += 1;
+    let action = if *count >= self.fault_policy.max_faults {
+      self.fault_policy.action
+    } else {
+      FaultAction::Nothing
+    };
+    if action != FaultAction::Nothing {
+      self.brains.insert(id, None);
+    }
+    self.faults.push(Fault {
+      entity_id: id,
+      kind,
+      count: *count,
+      action,
+    });
+  }
+
+  // runs the brain of the bot and decodes its verb, as seen by the bot
+  fn get_verb(&mut self, id: Id) -> Result<Verb, ExecutionError> {
+    // in our enviroment, we first update the current bot
+    *self.env.current.lock().unwrap() = id;
+    let value = match self.brains.get_mut(&id) {
+      None | Some(None) => return Ok(Verb::Wait),
+      Some(Some(Brain::Wasm(instance))) => {
+        *self.env.memory.lock().unwrap() = instance.exports.get_memory("memory").ok().cloned();
         let execute = instance
           .exports
           .get_function("execute")
@@ -310,18 +528,24 @@ impl Brains {
         let result = execute
           .call(&mut self.store, &[])
           .context(RuntimeSnafu { index: id })?;
-        let value = match result[0] {
-          Value::I64(r) => r,
-          _ => 0x0001000000000000,
+        match result.first() {
+          Some(Value::I64(r)) => *r,
+          _ => return Err(ExecutionError::InvalidReturn { index: id }),
+        }
+      }
+      Some(Some(Brain::Native(bot))) => {
+        let host = NativeHost {
+          env: self.env.clone(),
         };
-        Ok(Command {
-          entity_id: id,
-          verb: match team {
-            Team::Blue => decode_verb(value),
-            Team::Red => decode_verb(value).invert(),
-          },
-        })
+        catch_unwind(AssertUnwindSafe(|| {
+          with_host(Box::new(host), || bot.execute())
+        }))
+        .map_err(|_| ExecutionError::Panic { index: id })?
       }
-    }
+    };
+    try_decode_verb(value).context(InvalidOpcodeSnafu {
+      index: id,
+      opcode: value,
+    })
   }
 }
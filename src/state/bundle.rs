@@ -0,0 +1,317 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snafu::prelude::*;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::state::bf::{
+  load_level_file, load_squad_file, parse_verified, save_level_file, save_squad_file, BFState,
+  LoadError,
+};
+use crate::state::config::{bot_file_name, Config};
+
+// layout of the bundles written by this build
+pub const BUNDLE_VERSION: usize = 1;
+// bytes a bundle may inflate to, room for a few dozen bots
+pub const MAX_BUNDLE: usize = 64 << 20;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct BundleManifest {
+  pub version: usize,
+  // numbers of the files where they were exported from
+  pub level: usize,
+  pub squads: Vec<usize>,
+  // sha256 of every bot, by file name
+  pub bots: BTreeMap<String, String>,
+}
+
+// a level with some of its squads and the wasm of their brains, written as
+// gzipped json. The level and squads are kept as the json of their files
+// so that importing them goes through their migrations
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bundle {
+  pub manifest: BundleManifest,
+  pub level: String,
+  pub squads: Vec<String>,
+  // hex of every wasm file, by file name
+  pub bots: BTreeMap<String, String>,
+}
+
+// where the files of an imported bundle ended up
+#[derive(Clone, Debug, PartialEq)]
+pub struct Imported {
+  pub level: usize,
+  pub squads: Vec<usize>,
+  // bots saved under another name because a different one had theirs
+  pub renamed: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum BundleError {
+  #[snafu(display("Exporting: {}", source))]
+  Export { source: LoadError },
+  #[snafu(display("Bundled {}: {}", what, source))]
+  Bundled { source: LoadError, what: String },
+  #[snafu(display("Could not read {}: {}", path.display(), source))]
+  Read { source: io::Error, path: PathBuf },
+  #[snafu(display("Could not write {}: {}", path.display(), source))]
+  Write { source: io::Error, path: PathBuf },
+  #[snafu(display("{} is not a bundle: {}", path.display(), source))]
+  NotABundle {
+    source: serde_json::Error,
+    path: PathBuf,
+  },
+  #[snafu(display("{} inflates to more than {} bytes", path.display(), MAX_BUNDLE))]
+  TooLarge { path: PathBuf },
+  #[snafu(display("Bundle version {} is newer than {}", version, BUNDLE_VERSION))]
+  NewerBundle { version: usize },
+  #[snafu(display("The manifest lists {} squads, the bundle has {}", listed, found))]
+  SquadCount { listed: usize, found: usize },
+  #[snafu(display("Bot {} is not in the bundle", name))]
+  MissingBot { name: String },
+  #[snafu(display("Bot {} does not match the manifest", name))]
+  CorruptBot { name: String },
+  #[snafu(display("Bot {} is not named by a wasm file name", name))]
+  BadBotName { name: String },
+}
+
+fn sha256(bytes: &[u8]) -> String {
+  format!("{:x}", Sha256::digest(bytes))
+}
+
+// the name under which a bot is kept when a different one has its name
+fn hashed_name(name: &str, hash: &str) -> String {
+  let stem = Path::new(name).file_stem().unwrap().to_string_lossy();
+  format!("{}-{}.wasm", stem, &hash[..8])
+}
+
+impl Bundle {
+  // the level with the given number and some of its squads in the data
+  // directory of `config`, with their brains named by file name
+  pub fn export(config: &Config, level: usize, squads: &[usize]) -> Result<Bundle, BundleError> {
+    let mut level_state = load_level_file(config, level).context(ExportSnafu)?;
+    let mut squad_states = squads
+      .iter()
+      .map(|n| load_squad_file(config, level, *n))
+      .collect::<Result<Vec<BFState>, LoadError>>()
+      .context(ExportSnafu)?;
+    let mut bots = BTreeMap::new();
+    let mut hashes: BTreeMap<String, String> = BTreeMap::new();
+    // the bundled name of every brain
+    let mut names = BTreeMap::new();
+    for state in std::iter::once(&level_state).chain(squad_states.iter()) {
+      for code_name in state.brain_names() {
+        if names.contains_key(&code_name) {
+          continue;
+        }
+        let path = config.bot_path(&code_name);
+        let bytes = fs::read(&path).context(ReadSnafu { path })?;
+        let hash = sha256(&bytes);
+        // brains from different directories may share their file name
+        let mut name = bot_file_name(&code_name).to_string();
+        if hashes.get(&name).is_some_and(|h| *h != hash) {
+          name = hashed_name(&name, &hash);
+        }
+        hashes.insert(name.clone(), hash);
+        bots.insert(name.clone(), hex::encode(bytes));
+        names.insert(code_name, name);
+      }
+    }
+    let rename = |n: &str| names[n].clone();
+    level_state.rename_brains(rename);
+    level_state.seal(None);
+    for squad in squad_states.iter_mut() {
+      squad.rename_brains(rename);
+      squad.seal(Some(&level_state));
+    }
+    Ok(Bundle {
+      manifest: BundleManifest {
+        version: BUNDLE_VERSION,
+        level,
+        squads: squads.to_vec(),
+        bots: hashes,
+      },
+      level: serde_json::to_string(&level_state).unwrap(),
+      squads: squad_states
+        .iter()
+        .map(|s| serde_json::to_string(s).unwrap())
+        .collect(),
+      bots,
+    })
+  }
+
+  pub fn write(&self, path: &Path) -> Result<(), BundleError> {
+    let file = File::create(path).context(WriteSnafu { path })?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder
+      .write_all(serde_json::to_string(self).unwrap().as_bytes())
+      .and_then(|_| encoder.finish().map(|_| ()))
+      .context(WriteSnafu { path })
+  }
+
+  pub fn read(path: &Path) -> Result<Bundle, BundleError> {
+    let file = File::open(path).context(ReadSnafu { path })?;
+    let mut contents = String::new();
+    GzDecoder::new(file)
+      .take(MAX_BUNDLE as u64 + 1)
+      .read_to_string(&mut contents)
+      .context(ReadSnafu { path })?;
+    ensure!(contents.len() <= MAX_BUNDLE, TooLargeSnafu { path });
+    let bundle: Bundle = serde_json::from_str(&contents).context(NotABundleSnafu { path })?;
+    ensure!(
+      bundle.manifest.version <= BUNDLE_VERSION,
+      NewerBundleSnafu {
+        version: bundle.manifest.version
+      }
+    );
+    Ok(bundle)
+  }
+
+  // checks everything in the bundle before saving its bots and files in
+  // the data directory of `config`. Levels and squads that are already
+  // there are not saved again, and bots are renamed if a different one has
+  // their name
+  pub fn import(&self, config: &Config) -> Result<Imported, BundleError> {
+    let level =
+      parse_verified(self.level.as_bytes(), Path::new("level"), None).context(BundledSnafu {
+        what: "level".to_string(),
+      })?;
+    ensure!(
+      self.manifest.squads.len() == self.squads.len(),
+      SquadCountSnafu {
+        listed: self.manifest.squads.len(),
+        found: self.squads.len(),
+      }
+    );
+    let mut squads = vec![];
+    for (squad, contents) in self.manifest.squads.iter().zip(self.squads.iter()) {
+      let what = format!("squad {}", squad);
+      let path = PathBuf::from(&what);
+      squads.push(
+        parse_verified(contents.as_bytes(), &path, Some(&level)).context(BundledSnafu { what })?,
+      );
+    }
+    let mut wasm = BTreeMap::new();
+    for state in std::iter::once(&level).chain(squads.iter()) {
+      for name in state.brain_names() {
+        // bots are written in bots/ under this name
+        ensure!(
+          bot_file_name(&name) == name && name.ends_with(".wasm"),
+          BadBotNameSnafu { name: name.clone() }
+        );
+        let hex = self
+          .bots
+          .get(&name)
+          .context(MissingBotSnafu { name: name.clone() })?;
+        let bytes = hex::decode(hex)
+          .ok()
+          .context(CorruptBotSnafu { name: name.clone() })?;
+        ensure!(
+          self.manifest.bots.get(&name) == Some(&sha256(&bytes)),
+          CorruptBotSnafu { name }
+        );
+        wasm.insert(name, bytes);
+      }
+    }
+
+    // files this import created, removed again if it fails halfway so that
+    // no bot is left without the level or squads that use it
+    let mut created = vec![];
+    let imported = save_imported(config, level, squads, &wasm, &mut created);
+    if imported.is_err() {
+      for path in created.iter().rev() {
+        let _ = fs::remove_file(path);
+      }
+    }
+    imported
+  }
+}
+
+// saves the bots, level and squads of a checked bundle, recording the
+// files it creates
+fn save_imported(
+  config: &Config,
+  mut level: BFState,
+  squads: Vec<BFState>,
+  wasm: &BTreeMap<String, Vec<u8>>,
+  created: &mut Vec<PathBuf>,
+) -> Result<Imported, BundleError> {
+  let bots_dir = config.bots_dir();
+  fs::create_dir_all(&bots_dir).context(WriteSnafu { path: &bots_dir })?;
+  let mut renamed = BTreeMap::new();
+  for (name, bytes) in wasm.iter() {
+    let mut dest = bots_dir.join(name);
+    if fs::read(&dest).is_ok_and(|existing| existing != *bytes) {
+      let new_name = hashed_name(name, &sha256(bytes));
+      dest = bots_dir.join(&new_name);
+      renamed.insert(name.clone(), new_name);
+    }
+    if !dest.exists() {
+      created.push(dest.clone());
+    }
+    fs::write(&dest, bytes).context(WriteSnafu { path: dest })?;
+  }
+  let rename = |n: &str| renamed.get(n).cloned().unwrap_or_else(|| n.to_string());
+
+  level.rename_brains(rename);
+  level.seal(None);
+  let level_number = match find_level(config, &level) {
+    Some(n) => n,
+    None => {
+      let n = save_level_file(config, &level).context(WriteSnafu {
+        path: config.levels_dir(),
+      })?;
+      created.push(config.levels_dir().join(format!("{:05}.lvl", n)));
+      n
+    }
+  };
+  let mut squad_numbers = vec![];
+  for mut squad in squads {
+    squad.rename_brains(rename);
+    squad.seal(Some(&level));
+    squad_numbers.push(match find_squad(config, level_number, &squad) {
+      Some(n) => n,
+      None => {
+        let n = save_squad_file(config, level_number, &level, &squad).context(WriteSnafu {
+          path: config.squads_dir(level_number),
+        })?;
+        created.push(
+          config
+            .squads_dir(level_number)
+            .join(format!("{:05}.sqd", n)),
+        );
+        n
+      }
+    });
+  }
+  Ok(Imported {
+    level: level_number,
+    squads: squad_numbers,
+    renamed,
+  })
+}
+
+// the number of a saved level with the same contents, broken files are
+// skipped
+fn find_level(config: &Config, level: &BFState) -> Option<usize> {
+  let hash = level.content_hash();
+  (0..)
+    .map(|n| (n, load_level_file(config, n)))
+    .take_while(|(_, l)| !matches!(l, Err(e) if e.is_missing()))
+    .find(|(_, l)| matches!(l, Ok(l) if l.content_hash() == hash))
+    .map(|(n, _)| n)
+}
+
+fn find_squad(config: &Config, level: usize, squad: &BFState) -> Option<usize> {
+  let hash = squad.content_hash();
+  (0..)
+    .map(|n| (n, load_squad_file(config, level, n)))
+    .take_while(|(_, s)| !matches!(s, Err(e) if e.is_missing()))
+    .find(|(_, s)| matches!(s, Ok(s) if s.content_hash() == hash))
+    .map(|(n, _)| n)
+}
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::state::binary::Format;
+
+// where the game keeps its files. The data directory holds levels/,
+// squads/, bots/ and replays/, and is chosen by, from first to last:
+// - the --data flag,
+// - the SHIPPED_DATA environment variable,
+// - the data_dir of the config file, which is SHIPPED_CONFIG, shipped.json
+//   in the working directory or ~/.config/shipped/config.json,
+// - the working directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+  pub data_dir: PathBuf,
+  pub assets_dir: PathBuf,
+  // of the files that are saved, any of them can be loaded
+  pub format: Format,
+}
+
+// the config file, whose relative paths start at the file
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConfigFile {
+  #[serde(default)]
+  pub data_dir: Option<PathBuf>,
+  #[serde(default)]
+  pub assets_dir: Option<PathBuf>,
+  #[serde(default)]
+  pub format: Format,
+}
+
+#[derive(Debug, Snafu)]
+pub enum ConfigError {
+  #[snafu(display("Could not read {}: {}", path.display(), source))]
+  UnreadableConfig {
+    source: std::io::Error,
+    path: PathBuf,
+  },
+  #[snafu(display("{} is malformed: {}", path.display(), source))]
+  MalformedConfig {
+    source: serde_json::Error,
+    path: PathBuf,
+  },
+  #[snafu(display("--data needs a directory"))]
+  MissingDataFlag {},
+}
+
+pub const DATA_ENV: &str = "SHIPPED_DATA";
+pub const CONFIG_ENV: &str = "SHIPPED_CONFIG";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// the config of this process, read from the environment and the config
+// file the first time it is needed unless `init` was called before
+pub fn config() -> &'static Config {
+  CONFIG.get_or_init(|| {
+    Config::load(&[]).unwrap_or_else(|e| {
+      println!("{}, using the working directory", e);
+      Config::resolve(None, None, None)
+    })
+  })
+}
+
+// sets the config of this process, returns false if it was already set
+pub fn init(config: Config) -> bool {
+  CONFIG.set(config).is_ok()
+}
+
+// brains are named by their file in bots/, but squads saved before that
+// named the whole path they were loaded from
+pub fn bot_file_name(code_name: &str) -> &str {
+  Path::new(code_name)
+    .file_name()
+    .and_then(|n| n.to_str())
+    .unwrap_or(code_name)
+}
+
+impl Config {
+  // reads the flags in `args`, the environment and the config file
+  pub fn load(args: &[String]) -> Result<Config, ConfigError> {
+    let mut flag = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      if arg == "--data" {
+        flag = Some(PathBuf::from(args.next().context(MissingDataFlagSnafu)?));
+      }
+    }
+    let env = std::env::var_os(DATA_ENV).map(PathBuf::from);
+    let file = match config_file_path() {
+      Some(path) => Some(read_config_file(&path)?),
+      None => None,
+    };
+    Ok(Config::resolve(flag, env, file))
+  }
+
+  // the arguments that are left once the flags read by `load` are taken out
+  pub fn other_args(args: &[String]) -> Vec<&str> {
+    let mut rest = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      if arg == "--data" {
+        args.next();
+      } else {
+        rest.push(arg.as_str());
+      }
+    }
+    rest
+  }
+
+  pub fn resolve(flag: Option<PathBuf>, env: Option<PathBuf>, file: Option<ConfigFile>) -> Config {
+    let file = file.unwrap_or_default();
+    let data_dir = flag
+      .or(env)
+      .or(file.data_dir)
+      .unwrap_or_else(|| PathBuf::from("."));
+    // assets come with the game rather than with the data, so without a
+    // config they are looked for next to the data and then next to the
+    // executable
+    let assets_dir = file.assets_dir.unwrap_or_else(|| {
+      let next_to_data = data_dir.join("assets");
+      if next_to_data.is_dir() {
+        next_to_data
+      } else {
+        installed_assets_dir()
+      }
+    });
+    Config {
+      data_dir,
+      assets_dir,
+      format: file.format,
+    }
+  }
+
+  pub fn levels_dir(&self) -> PathBuf {
+    self.data_dir.join("levels")
+  }
+
+  pub fn squads_dir(&self, level: usize) -> PathBuf {
+    self.data_dir.join("squads").join(format!("{:05}", level))
+  }
+
+  pub fn bots_dir(&self) -> PathBuf {
+    self.data_dir.join("bots")
+  }
+
+  pub fn replays_dir(&self) -> PathBuf {
+    self.data_dir.join("replays")
+  }
+
+  pub fn asset(&self, name: &str) -> String {
+    self.assets_dir.join(name).to_string_lossy().into_owned()
+  }
+
+  // the wasm file of a brain, absolute paths are kept as they are
+  pub fn bot_path(&self, code_name: &str) -> PathBuf {
+    let path = Path::new(code_name);
+    if path.is_absolute() {
+      path.to_path_buf()
+    } else {
+      self.bots_dir().join(bot_file_name(code_name))
+    }
+  }
+}
+
+// the assets shipped with the executable, the working directory if it
+// cannot tell where it is
+pub fn installed_assets_dir() -> PathBuf {
+  std::env::current_exe()
+    .ok()
+    .and_then(|exe| exe.parent().map(|dir| dir.join("assets")))
+    .unwrap_or_else(|| PathBuf::from("assets"))
+}
+
+fn config_file_path() -> Option<PathBuf> {
+  if let Some(path) = std::env::var_os(CONFIG_ENV) {
+    return Some(PathBuf::from(path));
+  }
+  let local = PathBuf::from("shipped.json");
+  let home = std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config/shipped/config.json"));
+  std::iter::once(local)
+    .chain(home)
+    .find(|path| path.is_file())
+}
+
+pub fn read_config_file(path: &Path) -> Result<ConfigFile, ConfigError> {
+  let contents = fs::read_to_string(path).context(UnreadableConfigSnafu { path })?;
+  let mut file: ConfigFile =
+    serde_json::from_str(&contents).context(MalformedConfigSnafu { path })?;
+  let base = path.parent().unwrap_or(Path::new("."));
+  file.data_dir = file.data_dir.map(|d| base.join(d));
+  file.assets_dir = file.assets_dir.map(|d| base.join(d));
+  Ok(file)
+}
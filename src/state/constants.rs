@@ -2,9 +2,20 @@ pub const NUM_SUB_ENTITIES: usize = 2;
 pub const NUM_TEMPLATES: usize = 4;
 pub const NUM_CODES: usize = 8;
 
+// size of the default board, levels can choose their own
 pub const WIDTH: usize = 60;
 pub const HEIGHT: usize = 60; // must be even!!!
+                              // bots see positions encoded in 8 bits per coordinate
+pub const MAX_BOARD_SIZE: usize = 256;
 
 pub const RANGE: usize = 3;
 
+// hp restored by each repair
+pub const REPAIR_HP: usize = 2;
+
+pub const MAX_LOGS_PER_TURN: usize = 4;
+pub const MAX_LOG_LENGTH: usize = 120;
+
+pub const MAX_FAULTS: usize = 10;
+
 pub const NUMBER_TURNS: usize = 10000;
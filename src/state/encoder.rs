@@ -1,7 +1,7 @@
-use crate::state::entity::{Action, ActiveEntity, Message, MovementType, Team};
+use crate::state::entity::{Action, ActiveEntity, Message, MovementType, Stat, Team};
 use crate::state::geometry::{Direction, Displace, Neighbor, Pos};
 use crate::state::materials::Materials;
-use crate::state::state::Verb;
+use crate::state::state::{CombatRules, Terrain, Verb};
 use std::cmp::min;
 
 fn decode_direction(code: u8) -> Option<Direction> {
@@ -25,6 +25,17 @@ fn decode_neighbor(code: u8) -> Option<Neighbor> {
   }
 }
 
+fn decode_stat(code: u8) -> Option<Stat> {
+  match code {
+    0 => Some(Stat::Hp),
+    1 => Some(Stat::InventorySize),
+    2 => Some(Stat::GunDamage),
+    3 => Some(Stat::DrillDamage),
+    4 => Some(Stat::Sight),
+    _ => None,
+  }
+}
+
 pub fn encode_materials(mat: Materials) -> u32 {
   let carbon: u32 = min(mat.carbon, 255).try_into().unwrap();
   let silicon: u32 = min(mat.silicon, 255).try_into().unwrap();
@@ -33,6 +44,20 @@ pub fn encode_materials(mat: Materials) -> u32 {
   (copper << 24) + (plutonium << 16) + (silicon << 8) + carbon
 }
 
+pub fn encode_terrain(terrain: Terrain) -> u32 {
+  match terrain {
+    Terrain::Floor => 0,
+    Terrain::Wall => 1,
+    Terrain::Water => 2,
+  }
+}
+
+// bit 0 friendly fire, bit 1 pass through allies, bits 8-15 falloff
+pub fn encode_combat_rules(rules: CombatRules) -> i64 {
+  let falloff = min(rules.falloff, 255) as i64;
+  (falloff << 8) + ((rules.pass_through_allies as i64) << 1) + (rules.friendly_fire as i64)
+}
+
 fn decode_materials(code: u32) -> Materials {
   let carbon: usize = (code & 0x000000FF).try_into().unwrap();
   let silicon: usize = ((code & 0x0000FF00) >> 8).try_into().unwrap();
@@ -57,27 +82,47 @@ pub enum ViewAction {
   SetMessage(Message),
 }
 
+// how a bot sees the team of another entity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Allegiance {
+  Own,
+  // numbered counting from the team of the bot, neutral bots count from
+  // the first team
+  Other(Team),
+  Neutral,
+}
+
 pub struct ViewedEntity {
   pub pos: Pos,                    // 00-15 16 bits
   pub hp: usize,                   // 16-23 8 bits
   pub gun_damage: usize,           // 24-27 4 bits
   pub drill_damage: usize,         // 28-31 4 bits
-  pub team: Team,                  // 32-32 1 bit
-  pub movement_type: MovementType, // 33-33 1 bit
-  pub inventory_size: usize,       // 34-41 8 bits
-  pub tokens: usize,               // 42-45 4 bits (total 46?)
-  pub last_action: ViewAction,     // TODO: implement vieweing last action
+  pub team: Allegiance, // 32-32 1 bit (not own team), 46-47 2 bits, 48-48 1 bit (neutral)
+  pub movement_type: MovementType, // 33-33 1 bit (moves), 49-49 1 bit (flies)
+  pub inventory_size: usize, // 34-41 8 bits
+  pub tokens: usize,    // 42-45 4 bits
+  pub steps: usize,     // 50-51 2 bits
+  pub rest: usize,      // 52-53 2 bits
+  pub last_action: ViewAction, // TODO: implement vieweing last action
 }
 
-impl From<ActiveEntity> for ViewedEntity {
-  fn from(entity: ActiveEntity) -> Self {
+impl ViewedEntity {
+  // the entity as seen by a bot of team `viewer`
+  pub fn new(entity: ActiveEntity, viewer: Option<Team>, teams: usize) -> Self {
     ViewedEntity {
       tokens: entity.tokens,
-      team: entity.team,
+      team: match (entity.team, viewer) {
+        (None, _) => Allegiance::Neutral,
+        (Some(team), Some(viewer)) if team == viewer => Allegiance::Own,
+        (Some(team), Some(viewer)) => Allegiance::Other(team.relative_to(viewer, teams)),
+        (Some(team), None) => Allegiance::Other(team),
+      },
       pos: entity.pos,
       hp: entity.hp,
       inventory_size: entity.inventory_size,
       movement_type: entity.movement_type,
+      steps: entity.steps,
+      rest: entity.rest,
       gun_damage: entity.gun_damage,
       drill_damage: entity.drill_damage,
       last_action: ViewAction::Wait, // TODO: implement vieweing last action
@@ -104,18 +149,17 @@ pub fn encode_entity(entity: ViewedEntity) -> i64 {
   result += (min(entity.hp, 255) as i64) << 16;
   result += (min(entity.gun_damage, 16) as i64) << 24;
   result += (min(entity.drill_damage, 16) as i64) << 28;
-  result += (match entity.team {
-    Team::Blue => 0,
-    Team::Red => 1,
-  } as i64)
-    << 32;
-  result += (match entity.movement_type {
-    MovementType::Still => 0,
-    MovementType::Walk => 1,
-  } as i64)
-    << 33;
+  result += ((entity.team != Allegiance::Own) as i64) << 32;
+  result += ((entity.movement_type != MovementType::Still) as i64) << 33;
   result += (min(entity.inventory_size, 256) as i64) << 34;
   result += (min(entity.tokens, 16) as i64) << 42;
+  if let Allegiance::Other(team) = entity.team {
+    result += (min(team.0, 3) as i64) << 46;
+  }
+  result += ((entity.team == Allegiance::Neutral) as i64) << 48;
+  result += ((entity.movement_type == MovementType::Fly) as i64) << 49;
+  result += (min(entity.steps, 3) as i64) << 50;
+  result += (min(entity.rest, 3) as i64) << 52;
   result
 }
 
@@ -139,70 +183,105 @@ pub fn decode_displace(code: u16) -> Displace {
   }
 }
 
-pub fn decode_verb(opcode: i64) -> Verb {
+// decodes a verb returned by a bot, or None if the opcode is invalid
+pub fn try_decode_verb(opcode: i64) -> Option<Verb> {
   match (opcode & 0x00FF000000000000) >> 48 {
-    1 => Verb::Wait,
+    1 => Some(Verb::Wait),
     2 => {
       // AttemptMove
       if let Ok(code_direction) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
         if let Some(direction) = decode_direction(code_direction) {
-          return Verb::AttemptMove(direction);
+          return Some(Verb::AttemptMove(direction));
         }
       }
-      Verb::Wait
+      None
     }
     3 => {
       // GetMaterials
       if let Ok(code_neighbor) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
         if let Some(neighbor) = decode_neighbor(code_neighbor) {
           if let Ok(code_mat) = (opcode & 0x000000FFFFFFFF).try_into() {
-            return Verb::GetMaterials(neighbor, decode_materials(code_mat));
+            return Some(Verb::GetMaterials(neighbor, decode_materials(code_mat)));
           }
         }
       }
-      Verb::Wait
+      None
     }
     4 => {
       // DropMaterials
       if let Ok(code_neighbor) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
         if let Some(neighbor) = decode_neighbor(code_neighbor) {
           if let Ok(code_mat) = ((opcode & 0x000000FFFFFFFF0000) >> 16).try_into() {
-            return Verb::DropMaterials(neighbor, decode_materials(code_mat));
+            return Some(Verb::DropMaterials(neighbor, decode_materials(code_mat)));
           }
         }
       }
-      Verb::Wait
+      None
     }
     5 => {
       // Shoot
       let code_displace: u16 = ((opcode & 0x0000FFFF00000000) >> 32).try_into().unwrap();
-      Verb::Shoot(decode_displace(code_displace))
+      Some(Verb::Shoot(decode_displace(code_displace)))
     }
     6 => {
       // Drill
       if let Ok(code_direction) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
         if let Some(direction) = decode_direction(code_direction) {
-          return Verb::Drill(direction);
+          return Some(Verb::Drill(direction));
         }
       }
-      Verb::Wait
+      None
     }
     7 => {
       // Construct
       if let Ok(template) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
         if let Ok(code_direction) = ((opcode & 0x000000FF00000000) >> 32).try_into() {
           if let Some(direction) = decode_direction(code_direction) {
-            return Verb::Construct(template, direction);
+            return Some(Verb::Construct(template, direction));
           }
         }
       }
-      Verb::Wait
+      None
+    }
+    8 => {
+      // Repair
+      if let Ok(code_neighbor) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
+        if let Some(neighbor) = decode_neighbor(code_neighbor) {
+          return Some(Verb::Repair(neighbor));
+        }
+      }
+      None
+    }
+    9 => {
+      // Upgrade
+      if let Ok(code_stat) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
+        if let Some(stat) = decode_stat(code_stat) {
+          return Some(Verb::Upgrade(stat));
+        }
+      }
+      None
+    }
+    10 => {
+      // Give
+      if let Ok(code_neighbor) = ((opcode & 0x0000FF0000000000) >> 40).try_into() {
+        if let Some(neighbor) = decode_neighbor(code_neighbor) {
+          if let Ok(code_mat) = (opcode & 0x000000FFFFFFFF).try_into() {
+            return Some(Verb::Give(neighbor, decode_materials(code_mat)));
+          }
+        }
+      }
+      None
     }
     // TODO set message
-    _ => Verb::Wait,
+    _ => None,
   }
 }
 
+// invalid opcodes are read as Wait
+pub fn decode_verb(opcode: i64) -> Verb {
+  try_decode_verb(opcode).unwrap_or(Verb::Wait)
+}
+
 pub fn encode_coord(x: usize, y: usize) -> u32 {
   let xprime: u16 = x.try_into().unwrap();
   let yprime: u16 = y.try_into().unwrap();
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
+use snafu::prelude::*;
+use std::cmp::max;
 
-use super::constants::NUM_SUB_ENTITIES;
+use super::constants::{NUM_SUB_ENTITIES, RANGE};
 use super::geometry::{Direction, Displace, Neighbor, Pos};
 use super::materials::Materials;
 
@@ -8,6 +10,8 @@ use super::materials::Materials;
 pub enum MovementType {
   Still,
   Walk,
+  // crosses water, but not walls
+  Fly,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -16,21 +20,32 @@ pub struct Message {
   pub pos: Pos,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
-pub enum Team {
-  Blue,
-  Red,
-}
+// Teams are numbered in the order of the regions of the board. Two player
+// matches are played by blue and red.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Team(pub usize);
 
 impl Team {
-  pub fn invert(&self) -> Self {
-    match self {
-      Team::Blue => Team::Red,
-      Team::Red => Team::Blue,
-    }
+  pub const BLUE: Team = Team(0);
+  pub const RED: Team = Team(1);
+
+  // the number under which bots of `viewer` see this team, they always see
+  // themselves as the first one
+  pub fn relative_to(&self, viewer: Team, teams: usize) -> Team {
+    Team((self.0 + teams - viewer.0 % teams) % teams)
   }
 }
 
+// the stats that an entity can upgrade
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Stat {
+  Hp,
+  InventorySize,
+  GunDamage,
+  DrillDamage,
+  Sight,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Action {
   Wait,
@@ -41,21 +56,47 @@ pub enum Action {
   Drill(Direction),
   Construct(usize, Direction),
   SetMessage(Message),
+  Repair(Neighbor),
+  Upgrade(Stat),
+  Give(Neighbor, Materials),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ActiveEntity {
   pub tokens: usize,
-  pub team: Team,
+  // neutral entities, placed by the level, belong to no team
+  pub team: Option<Team>,
   pub pos: Pos,
   pub hp: usize,
+  // hp the entity was built with
+  #[serde(default)]
+  pub max_hp: usize,
   pub inventory_size: usize,
   pub materials: Materials,
   pub movement_type: MovementType,
+  // tiles covered by each move
+  #[serde(default = "default_steps")]
+  pub steps: usize,
+  // move attempts spent resting after each move
+  #[serde(default)]
+  pub rest: usize,
   pub gun_damage: usize,
   pub drill_damage: usize,
+  pub sight: usize,
+  #[serde(default)]
+  pub armour: usize,
+  #[serde(default)]
+  pub can_repair: bool,
+  #[serde(default)]
+  pub can_upgrade: bool,
   pub last_action: Action,
   pub brain: Option<Full>,
+  // moves since the entity last spent plutonium, modulo the moves it buys
+  #[serde(default)]
+  pub moves: usize,
+  // move attempts left before it can move again
+  #[serde(default)]
+  pub resting: usize,
 }
 
 // Specifies which entities this one can build
@@ -84,8 +125,22 @@ pub struct MixTemplate {
   pub inventory_size: usize,
   pub materials: Materials,
   pub movement_type: MovementType,
+  // tiles covered by each move
+  #[serde(default = "default_steps")]
+  pub steps: usize,
+  // move attempts spent resting after each move
+  #[serde(default)]
+  pub rest: usize,
   pub gun_damage: usize,
   pub drill_damage: usize,
+  #[serde(default = "default_sight")]
+  pub sight: usize,
+  #[serde(default)]
+  pub armour: usize,
+  #[serde(default)]
+  pub can_repair: bool,
+  #[serde(default)]
+  pub can_upgrade: bool,
   pub brain: Mix,
 }
 
@@ -94,7 +149,7 @@ impl ActiveEntity {
     self.gun_damage > 0
   }
   pub fn can_move(&self) -> bool {
-    self.movement_type == MovementType::Walk
+    self.movement_type != MovementType::Still
   }
   pub fn get_drill_damage(&self) -> usize {
     self.drill_damage
@@ -102,15 +157,50 @@ impl ActiveEntity {
   pub fn get_gun_damage(&self) -> usize {
     self.gun_damage
   }
+  pub fn get_sight(&self) -> usize {
+    self.sight
+  }
   pub fn has_copper(&self) -> bool {
     self.materials.copper > 0
   }
-  pub fn swap_teams(&mut self) {
-    self.team = match self.team {
-      Team::Blue => Team::Red,
-      Team::Red => Team::Blue,
+  // the entity as it was built, without its inventory, to be priced with
+  // cost
+  pub fn body(&self) -> TemplateEntity {
+    TemplateEntity {
+      hp: max(self.hp, self.max_hp),
+      inventory_size: self.inventory_size,
+      materials: Materials::new(0, 0, 0, 0),
+      movement_type: self.movement_type.clone(),
+      steps: self.steps,
+      rest: self.rest,
+      gun_damage: self.gun_damage,
+      drill_damage: self.drill_damage,
+      sight: self.sight,
+      armour: self.armour,
+      can_repair: self.can_repair,
+      can_upgrade: self.can_upgrade,
+      message: None,
+      brain: self.brain.clone(),
+    }
+  }
+  // raises a stat by one, upgrading the hp raises the maximum as well
+  pub fn raise(&mut self, stat: Stat) {
+    match stat {
+      Stat::Hp => {
+        self.max_hp = max(self.max_hp, self.hp) + 1;
+        self.hp += 1;
+      }
+      Stat::InventorySize => self.inventory_size += 1,
+      Stat::GunDamage => self.gun_damage += 1,
+      Stat::DrillDamage => self.drill_damage += 1,
+      Stat::Sight => self.sight += 1,
     }
   }
+  // the team from whose side the entity sees the board, neutral entities
+  // see it as the first team
+  pub fn side(&self) -> Team {
+    self.team.unwrap_or(Team::BLUE)
+  }
 }
 
 #[rustfmt::skip]
@@ -120,8 +210,14 @@ pub fn same_bare (a: &MixTemplate, b: &MixTemplate) -> bool {
   if a.inventory_size != b.inventory_size { return false; }
   if a.materials != b.materials { return false; }
   if a.movement_type != b.movement_type { return false; }
+  if a.steps != b.steps { return false; }
+  if a.rest != b.rest { return false; }
   if a.gun_damage != b.gun_damage { return false; }
   if a.drill_damage != b.drill_damage { return false; }
+  if a.sight != b.sight { return false; }
+  if a.armour != b.armour { return false; }
+  if a.can_repair != b.can_repair { return false; }
+  if a.can_upgrade != b.can_upgrade { return false; }
   return true;
 }
 
@@ -162,8 +258,25 @@ pub struct TemplateEntity {
   pub inventory_size: usize,
   pub materials: Materials,
   pub movement_type: MovementType,
+  // tiles covered by each move
+  #[serde(default = "default_steps")]
+  pub steps: usize,
+  // move attempts spent resting after each move
+  #[serde(default)]
+  pub rest: usize,
   pub gun_damage: usize,
   pub drill_damage: usize,
+  #[serde(default = "default_sight")]
+  pub sight: usize,
+  // damage taken is reduced by this much
+  #[serde(default)]
+  pub armour: usize,
+  // repairs the hp of its neighbours with carbon
+  #[serde(default)]
+  pub can_repair: bool,
+  // raises its own stats with materials
+  #[serde(default)]
+  pub can_upgrade: bool,
   pub message: Option<Message>,
   pub brain: Option<Full>,
 }
@@ -177,8 +290,14 @@ impl TryFrom<MixTemplate> for TemplateEntity {
         inventory_size: mix.inventory_size,
         materials: mix.materials,
         movement_type: mix.movement_type,
+        steps: mix.steps,
+        rest: mix.rest,
         gun_damage: mix.gun_damage,
         drill_damage: mix.drill_damage,
+        sight: mix.sight,
+        armour: mix.armour,
+        can_repair: mix.can_repair,
+        can_upgrade: mix.can_upgrade,
         message: None,
         brain: Some(f),
       }),
@@ -187,8 +306,14 @@ impl TryFrom<MixTemplate> for TemplateEntity {
         inventory_size: mix.inventory_size,
         materials: mix.materials,
         movement_type: mix.movement_type,
+        steps: mix.steps,
+        rest: mix.rest,
         gun_damage: mix.gun_damage,
         drill_damage: mix.drill_damage,
+        sight: mix.sight,
+        armour: mix.armour,
+        can_repair: mix.can_repair,
+        can_upgrade: mix.can_upgrade,
         message: None,
         brain: None,
       }),
@@ -197,8 +322,14 @@ impl TryFrom<MixTemplate> for TemplateEntity {
         inventory_size: mix.inventory_size,
         materials: mix.materials,
         movement_type: mix.movement_type,
+        steps: mix.steps,
+        rest: mix.rest,
         gun_damage: mix.gun_damage,
         drill_damage: mix.drill_damage,
+        sight: mix.sight,
+        armour: mix.armour,
+        can_repair: mix.can_repair,
+        can_upgrade: mix.can_upgrade,
         message: None,
         brain: None,
       }),
@@ -207,51 +338,158 @@ impl TryFrom<MixTemplate> for TemplateEntity {
 }
 
 impl TemplateEntity {
-  pub fn upgrade(self, tokens: usize, team: Team, pos: Pos) -> ActiveEntity {
+  pub fn upgrade(self, tokens: usize, team: Option<Team>, pos: Pos) -> ActiveEntity {
     ActiveEntity {
       tokens,
       team,
       pos,
       hp: self.hp,
+      max_hp: self.hp,
       inventory_size: self.inventory_size,
       materials: self.materials,
       movement_type: self.movement_type,
+      steps: self.steps,
+      rest: self.rest,
       gun_damage: self.gun_damage,
       drill_damage: self.drill_damage,
+      sight: self.sight,
+      armour: self.armour,
+      can_repair: self.can_repair,
+      can_upgrade: self.can_upgrade,
       last_action: Action::Wait,
       brain: self.brain,
+      moves: 0,
+      resting: 0,
     }
   }
 }
 
-pub fn super_linear(i: usize) -> usize {
-  f64::powf(i as f64, 1.3).floor() as usize
+// templates saved before speed existed move one tile at a time
+fn default_steps() -> usize {
+  1
 }
 
-pub fn max_weight(body: &TemplateEntity) -> usize {
-  let mut result = 0;
-  result += body.hp;
-  result += body.inventory_size;
-  result
+// templates saved before sight existed see as far as the old fixed range
+fn default_sight() -> usize {
+  RANGE
 }
 
-pub fn cost(template: &TemplateEntity) -> Materials {
-  let w = max_weight(&template);
+// the coefficients of the cost of a body, chosen by each level
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EconomyRules {
+  // most stats cost their value raised to this power
+  pub exponent: f64,
+  // the weight of each point of hp and of inventory
+  pub hp_weight: usize,
+  pub inventory_weight: usize,
+  // plutonium per unit of weight for walkers, times their speed
+  pub walk_plutonium: usize,
+  // fliers pay this many times what walkers do
+  pub fly_factor: usize,
+  // a brain costs this much plutonium, plus one for every so much gas
+  pub brain_plutonium: usize,
+  pub gas_per_plutonium: usize,
+  // silicon for each capability, like repairing or upgrading
+  pub capability_silicon: usize,
+}
+
+impl Default for EconomyRules {
+  fn default() -> Self {
+    EconomyRules {
+      exponent: 1.3,
+      hp_weight: 1,
+      inventory_weight: 1,
+      walk_plutonium: 1,
+      fly_factor: 2,
+      brain_plutonium: 1,
+      gas_per_plutonium: 10,
+      capability_silicon: 2,
+    }
+  }
+}
+
+#[derive(Debug, Snafu)]
+pub enum EconomyError {
+  #[snafu(display("Exponent {:} is not a finite number at least zero", exponent))]
+  BadExponent { exponent: f64 },
+}
+
+impl EconomyRules {
+  // bigger stats must never cost less, or upgrading them would have a
+  // negative price
+  pub fn validate(&self) -> Result<(), EconomyError> {
+    ensure!(
+      self.exponent.is_finite() && self.exponent >= 0.0,
+      BadExponentSnafu {
+        exponent: self.exponent
+      }
+    );
+    Ok(())
+  }
+
+  pub fn super_linear(&self, i: usize) -> usize {
+    // the cast saturates, so huge stats cost usize::MAX
+    f64::powf(i as f64, self.exponent).floor() as usize
+  }
+
+  pub fn max_weight(&self, body: &TemplateEntity) -> usize {
+    let hp = self.hp_weight.saturating_mul(body.hp);
+    let inventory = self.inventory_weight.saturating_mul(body.inventory_size);
+    hp.saturating_add(inventory)
+  }
+}
+
+pub fn cost(template: &TemplateEntity, economy: &EconomyRules) -> Materials {
+  // templates come from files, so every sum saturates instead of overflowing
+  let w = economy.max_weight(&template);
   let mut result = template.materials.clone();
-  result.carbon += super_linear(template.hp);
-  result.carbon += super_linear(template.inventory_size);
+  let add = |amount: &mut usize, more: usize| *amount = amount.saturating_add(more);
+  add(&mut result.carbon, economy.super_linear(template.hp));
+  add(
+    &mut result.carbon,
+    economy.super_linear(template.inventory_size),
+  );
+  // faster movers pay more, slower ones less
+  let speed = economy
+    .walk_plutonium
+    .saturating_mul(w)
+    .saturating_mul(template.steps)
+    / template.rest.saturating_add(1);
   match template.movement_type {
     MovementType::Still => {}
-    MovementType::Walk => result.plutonium += w,
+    MovementType::Walk => add(&mut result.plutonium, speed),
+    MovementType::Fly => add(
+      &mut result.plutonium,
+      economy.fly_factor.saturating_mul(speed),
+    ),
+  }
+  add(&mut result.plutonium, template.drill_damage);
+  add(
+    &mut result.plutonium,
+    economy.super_linear(template.gun_damage),
+  );
+  // the range every template had before sight could be chosen is free
+  add(
+    &mut result.silicon,
+    economy.super_linear(template.sight.saturating_sub(RANGE)),
+  );
+  add(&mut result.copper, economy.super_linear(template.armour));
+  if template.can_repair {
+    add(&mut result.silicon, economy.capability_silicon);
+  }
+  if template.can_upgrade {
+    add(&mut result.silicon, economy.capability_silicon);
   }
-  result.plutonium += template.drill_damage;
-  result.plutonium += super_linear(template.gun_damage);
   if let Some(f) = &template.brain {
-    result.plutonium += f.gas / 10 + 1;
+    add(&mut result.plutonium, economy.brain_plutonium);
+    add(
+      &mut result.plutonium,
+      f.gas.checked_div(economy.gas_per_plutonium).unwrap_or(0),
+    );
   }
   result
 }
 
-pub fn cost_template(mix_template: &MixTemplate) -> Materials {
-  cost(&mix_template.clone().try_into().unwrap())
+pub fn cost_template(mix_template: &MixTemplate, economy: &EconomyRules) -> Materials {
+  cost(&mix_template.clone().try_into().unwrap(), economy)
 }
@@ -1,4 +1,5 @@
-use super::constants::{HEIGHT, WIDTH};
+use super::constants::{HEIGHT, MAX_BOARD_SIZE, WIDTH};
+use super::entity::Team;
 use std::{convert::TryFrom, num::TryFromIntError};
 
 use serde::{Deserialize, Serialize};
@@ -10,68 +11,234 @@ pub struct Pos {
   pub y: usize,
 }
 
-impl Pos {
-  pub fn invert(&self) -> Self {
-    Pos::new(WIDTH - self.x - 1, HEIGHT - self.y - 1)
+// How the regions of the other teams are obtained from the region of the
+// first one. Every bot sees the board transformed by it, so that they all
+// play as if they were in the first region.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Symmetry {
+  // 180 degrees around the center, blue at the bottom
+  #[default]
+  Rotation,
+  // mirror across the horizontal axis, blue at the bottom
+  MirrorHorizontal,
+  // mirror across the vertical axis, blue on the left
+  MirrorVertical,
+  // quarter turns counterclockwise around the center of a square board,
+  // four teams starting at the south-west corner
+  FourfoldRotation,
+}
+
+impl Symmetry {
+  pub fn regions(&self) -> usize {
+    match self {
+      Symmetry::FourfoldRotation => 4,
+      _ => 2,
+    }
+  }
+  // the symmetry applied `steps` times, which takes the region of the first
+  // team to that of team `steps`
+  fn turns(&self, steps: usize) -> usize {
+    steps % self.regions()
+  }
+  fn step_direction(&self, dir: Direction) -> Direction {
+    match (self, dir) {
+      (Symmetry::Rotation, _) => dir.invert(),
+      (Symmetry::MirrorHorizontal, Direction::North | Direction::South) => dir.invert(),
+      (Symmetry::MirrorVertical, Direction::East | Direction::West) => dir.invert(),
+      (Symmetry::FourfoldRotation, _) => dir.turn_left(),
+      _ => dir,
+    }
+  }
+  fn step_displace(&self, disp: &Displace) -> Displace {
+    match self {
+      Symmetry::Rotation => disp.invert(),
+      Symmetry::MirrorHorizontal => Displace::new(disp.x, -disp.y),
+      Symmetry::MirrorVertical => Displace::new(-disp.x, disp.y),
+      Symmetry::FourfoldRotation => Displace::new(-disp.y, disp.x),
+    }
+  }
+  // the direction on the board that a bot of `team` calls `dir`
+  pub fn direction_from_team(&self, team: Team, dir: Direction) -> Direction {
+    (0..self.turns(team.0)).fold(dir, |d, _| self.step_direction(d))
+  }
+  pub fn neighbor_from_team(&self, team: Team, neighbor: Neighbor) -> Neighbor {
+    match neighbor {
+      Neighbor::Here => Neighbor::Here,
+      Neighbor::North => self.direction_from_team(team, Direction::North).into(),
+      Neighbor::East => self.direction_from_team(team, Direction::East).into(),
+      Neighbor::South => self.direction_from_team(team, Direction::South).into(),
+      Neighbor::West => self.direction_from_team(team, Direction::West).into(),
+    }
+  }
+  pub fn displace_from_team(&self, team: Team, disp: &Displace) -> Displace {
+    (0..self.turns(team.0)).fold(disp.clone(), |d, _| self.step_displace(&d))
   }
 }
 
-pub struct BoardIterator {
-  i: usize,
-  j: usize,
+// Dimensions of the battlefield and the symmetry between the regions of the
+// teams. The sides that are split in regions must have even length.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Board {
+  pub width: usize,
+  pub height: usize,
+  #[serde(default)]
+  pub symmetry: Symmetry,
 }
 
-impl Iterator for BoardIterator {
-  type Item = Pos;
+impl Default for Board {
+  fn default() -> Self {
+    Board::new(WIDTH, HEIGHT)
+  }
+}
 
-  fn next(&mut self) -> Option<Self::Item> {
-    let (i, j) = (self.i, self.j);
-    if i < WIDTH - 1 {
-      self.i += 1;
-    } else {
-      self.i = 0;
-      self.j += 1;
+impl Board {
+  pub fn new(width: usize, height: usize) -> Self {
+    Board {
+      width,
+      height,
+      symmetry: Symmetry::Rotation,
     }
-
-    if j == HEIGHT {
-      return None;
+  }
+  pub fn with_symmetry(self, symmetry: Symmetry) -> Self {
+    Board { symmetry, ..self }
+  }
+  pub fn is_valid(&self) -> bool {
+    let regions_even = match self.symmetry {
+      Symmetry::Rotation | Symmetry::MirrorHorizontal => self.height % 2 == 0,
+      Symmetry::MirrorVertical => self.width % 2 == 0,
+      Symmetry::FourfoldRotation => self.width == self.height && self.width % 2 == 0,
     };
-    return Some(Pos::new(i, j));
+    self.width > 0
+      && self.height > 0
+      && regions_even
+      && self.width <= MAX_BOARD_SIZE
+      && self.height <= MAX_BOARD_SIZE
+  }
+  pub fn size(&self) -> usize {
+    self.width * self.height
+  }
+  pub fn teams(&self) -> usize {
+    self.symmetry.regions()
+  }
+  // width and height of the region of the first team
+  pub fn region_dimensions(&self) -> (usize, usize) {
+    match self.symmetry {
+      Symmetry::Rotation | Symmetry::MirrorHorizontal => (self.width, self.height / 2),
+      Symmetry::MirrorVertical => (self.width / 2, self.height),
+      Symmetry::FourfoldRotation => (self.width / 2, self.height / 2),
+    }
+  }
+  pub fn region_size(&self) -> usize {
+    let (width, height) = self.region_dimensions();
+    width * height
+  }
+  pub fn index(&self, pos: Pos) -> usize {
+    pos.x + pos.y * self.width
+  }
+  // index of a position of the first region, in the tiles of a level or squad
+  pub fn region_index(&self, pos: Pos) -> usize {
+    pos.x + pos.y * self.region_dimensions().0
+  }
+  fn step(&self, pos: Pos) -> Pos {
+    match self.symmetry {
+      Symmetry::Rotation => Pos::new(self.width - pos.x - 1, self.height - pos.y - 1),
+      Symmetry::MirrorHorizontal => Pos::new(pos.x, self.height - pos.y - 1),
+      Symmetry::MirrorVertical => Pos::new(self.width - pos.x - 1, pos.y),
+      Symmetry::FourfoldRotation => Pos::new(self.width - pos.y - 1, pos.x),
+    }
+  }
+  // the tile that a bot of `team` sees at `pos`
+  pub fn from_team(&self, team: Team, pos: Pos) -> Pos {
+    (0..self.symmetry.turns(team.0)).fold(pos, |p, _| self.step(p))
+  }
+  // where a bot of `team` sees the tile at `pos`
+  pub fn to_team(&self, team: Team, pos: Pos) -> Pos {
+    let regions = self.symmetry.regions();
+    self.from_team(Team(regions - self.symmetry.turns(team.0)), pos)
+  }
+  // the team in whose region the position is
+  pub fn team_at(&self, pos: Pos) -> Team {
+    (0..self.teams())
+      .map(Team)
+      .find(|t| self.contains_region(self.to_team(*t, pos)))
+      .unwrap_or(Team(0))
+  }
+  pub fn contains(&self, pos: Pos) -> bool {
+    pos.x < self.width && pos.y < self.height
+  }
+  pub fn contains_signed(&self, x: i64, y: i64) -> bool {
+    (x >= 0) & (x < self.width as i64) & (y >= 0) & (y < self.height as i64)
+  }
+  // whether the position is in the region of the first team
+  pub fn contains_region(&self, pos: Pos) -> bool {
+    let (width, height) = self.region_dimensions();
+    pos.x < width && pos.y < height
+  }
+  pub fn iter(&self) -> BoardIterator {
+    BoardIterator {
+      i: 0,
+      j: 0,
+      width: self.width,
+      height: self.height,
+    }
+  }
+  pub fn region_iter(&self) -> BoardIterator {
+    let (width, height) = self.region_dimensions();
+    BoardIterator {
+      i: 0,
+      j: 0,
+      width,
+      height,
+    }
+  }
+  pub fn add_displace(&self, pos: Pos, disp: &Displace) -> Result<Pos, GeometryError> {
+    let x = usize::try_from((pos.x as i64) + disp.x).context(DisplacedOutOfBoundsSnafu {
+      pos,
+      d: disp.clone(),
+    })?;
+    let y = usize::try_from((pos.y as i64) + disp.y).context(DisplacedOutOfBoundsSnafu {
+      pos,
+      d: disp.clone(),
+    })?;
+    let new_pos = Pos::new(x, y);
+    ensure!(
+      self.contains(new_pos),
+      DisplaceOutOfBoundsLargeSnafu {
+        pos,
+        d: disp.clone()
+      }
+    );
+    Ok(new_pos)
   }
 }
 
-pub fn board_iterator() -> BoardIterator {
-  BoardIterator { i: 0, j: 0 }
-}
-
-pub struct HalfBoardIterator {
+// iterates row by row over a board, or over the region of the first team
+pub struct BoardIterator {
   i: usize,
   j: usize,
+  width: usize,
+  height: usize,
 }
 
-impl Iterator for HalfBoardIterator {
+impl Iterator for BoardIterator {
   type Item = Pos;
 
   fn next(&mut self) -> Option<Self::Item> {
     let (i, j) = (self.i, self.j);
-    if i < WIDTH - 1 {
+    if i < self.width - 1 {
       self.i += 1;
     } else {
       self.i = 0;
       self.j += 1;
     }
 
-    if j == HEIGHT / 2 {
+    if j == self.height {
       return None;
     };
     return Some(Pos::new(i, j));
   }
 }
 
-pub fn half_board_iterator() -> HalfBoardIterator {
-  HalfBoardIterator { i: 0, j: 0 }
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum Direction {
   North,
@@ -89,6 +256,25 @@ impl Direction {
       Direction::West => Direction::East,
     }
   }
+  pub fn turn_left(&self) -> Self {
+    match self {
+      Direction::North => Direction::West,
+      Direction::West => Direction::South,
+      Direction::South => Direction::East,
+      Direction::East => Direction::North,
+    }
+  }
+}
+
+impl From<Direction> for Neighbor {
+  fn from(d: Direction) -> Self {
+    match d {
+      Direction::North => Neighbor::North,
+      Direction::East => Neighbor::East,
+      Direction::South => Neighbor::South,
+      Direction::West => Neighbor::West,
+    }
+  }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -116,18 +302,6 @@ impl Pos {
   pub fn new(x: usize, y: usize) -> Self {
     Pos { x, y }
   }
-  pub fn to_index(&self) -> usize {
-    self.x + self.y * WIDTH
-  }
-  pub fn is_bottom(&self) -> bool {
-    self.y < HEIGHT / 2
-  }
-  pub fn is_withing_bounds(&self) -> bool {
-    self.y < HEIGHT && self.x < WIDTH
-  }
-  pub fn is_withing_half(&self) -> bool {
-    self.y < HEIGHT / 2 && self.x < WIDTH
-  }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -186,38 +360,10 @@ pub enum GeometryError {
   DisplaceOutOfBoundsLarge { pos: Pos, d: Displace },
 }
 
-pub fn add_displace(pos: Pos, disp: &Displace) -> Result<Pos, GeometryError> {
-  let x = usize::try_from((pos.x as i64) + disp.x).context(DisplacedOutOfBoundsSnafu {
-    pos,
-    d: disp.clone(),
-  })?;
-  let y = usize::try_from((pos.y as i64) + disp.y).context(DisplacedOutOfBoundsSnafu {
-    pos,
-    d: disp.clone(),
-  })?;
-  let new_pos = Pos::new(x, y);
-  ensure!(
-    is_within_bounds(new_pos),
-    DisplaceOutOfBoundsLargeSnafu {
-      pos,
-      d: disp.clone()
-    }
-  );
-  Ok(new_pos)
-}
-
 pub fn difference(p1: Pos, p2: Pos) -> Displace {
   return Displace::new((p2.x as i64) - (p1.x as i64), (p2.y as i64) - (p1.y as i64));
 }
 
-pub fn is_within_bounds(pos: Pos) -> bool {
-  return (pos.x < WIDTH) & (pos.y < HEIGHT);
-}
-
-pub fn is_within_bounds_signed(x: i64, y: i64) -> bool {
-  return (x >= 0) & (x < WIDTH as i64) & (y >= 0) & (y < HEIGHT as i64);
-}
-
 pub fn are_neighbors(p1: Pos, p2: Pos) -> bool {
   return difference(p1, p2).square_norm() == 1;
 }
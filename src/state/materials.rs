@@ -104,7 +104,54 @@ impl Materials {
       copper,
     }
   }
+  pub fn saturating_add(&self, other: &Materials) -> Materials {
+    Materials {
+      carbon: self.carbon.saturating_add(other.carbon),
+      silicon: self.silicon.saturating_add(other.silicon),
+      plutonium: self.plutonium.saturating_add(other.plutonium),
+      copper: self.copper.saturating_add(other.copper),
+    }
+  }
+  // each material goes down to zero at most
+  pub fn saturating_sub(&self, other: &Materials) -> Materials {
+    Materials {
+      carbon: self.carbon.saturating_sub(other.carbon),
+      silicon: self.silicon.saturating_sub(other.silicon),
+      plutonium: self.plutonium.saturating_sub(other.plutonium),
+      copper: self.copper.saturating_sub(other.copper),
+    }
+  }
+  pub fn saturating_mul(&self, factor: usize) -> Materials {
+    Materials {
+      carbon: self.carbon.saturating_mul(factor),
+      silicon: self.silicon.saturating_mul(factor),
+      plutonium: self.plutonium.saturating_mul(factor),
+      copper: self.copper.saturating_mul(factor),
+    }
+  }
   pub fn volume(&self) -> usize {
     self.carbon + self.silicon + self.plutonium + self.copper
   }
+  // the given percentage of each material, rounded down
+  pub fn percent(&self, percent: usize) -> Materials {
+    Materials {
+      carbon: self.carbon * percent / 100,
+      silicon: self.silicon * percent / 100,
+      plutonium: self.plutonium * percent / 100,
+      copper: self.copper * percent / 100,
+    }
+  }
+  // splits the materials in `parts` piles as even as possible, the first
+  // ones getting the remainder
+  pub fn split(&self, parts: usize) -> Vec<Materials> {
+    let share = |amount: usize, i: usize| amount / parts + ((i < amount % parts) as usize);
+    (0..parts)
+      .map(|i| Materials {
+        carbon: share(self.carbon, i),
+        silicon: share(self.silicon, i),
+        plutonium: share(self.plutonium, i),
+        copper: share(self.copper, i),
+      })
+      .collect()
+  }
 }
@@ -1,10 +1,14 @@
 pub mod bf;
+pub mod binary;
 pub mod brain;
+pub mod bundle;
+pub mod config;
 pub mod constants;
 pub mod encoder;
 pub mod entity;
 pub mod geometry;
 pub mod materials;
+pub mod neutral;
 pub mod run;
 pub mod squad;
 pub mod state;
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use super::entity::{Full, MovementType, TemplateEntity};
+use super::materials::Materials;
+use tools::encoder::encode_verb;
+use tools::host::Bot;
+
+const TURRET_RANGE: usize = 4;
+
+// Entities that belong to no team. Level designers place them on the board
+// and their brains are built into the engine.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Neutral {
+  // shoots at any bot in range
+  Turret,
+  // does nothing, but carries plenty of materials
+  Rock,
+  // wanders around, drilling the bots it bumps into
+  Creature,
+}
+
+impl Neutral {
+  pub fn template(&self) -> TemplateEntity {
+    match self {
+      Neutral::Turret => TemplateEntity {
+        hp: 20,
+        inventory_size: 10,
+        materials: Materials::new(0, 0, 0, 10),
+        movement_type: MovementType::Still,
+        steps: 1,
+        rest: 0,
+        gun_damage: 2,
+        drill_damage: 0,
+        sight: TURRET_RANGE,
+        armour: 0,
+        can_repair: false,
+        can_upgrade: false,
+        message: None,
+        brain: Some(builtin("turret")),
+      },
+      Neutral::Rock => TemplateEntity {
+        hp: 30,
+        inventory_size: 30,
+        materials: Materials::new(10, 10, 5, 5),
+        movement_type: MovementType::Still,
+        steps: 1,
+        rest: 0,
+        gun_damage: 0,
+        drill_damage: 0,
+        sight: 0,
+        armour: 0,
+        can_repair: false,
+        can_upgrade: false,
+        message: None,
+        brain: None,
+      },
+      Neutral::Creature => TemplateEntity {
+        hp: 10,
+        inventory_size: 0,
+        materials: Materials::new(0, 0, 0, 0),
+        movement_type: MovementType::Walk,
+        steps: 1,
+        rest: 0,
+        gun_damage: 0,
+        drill_damage: 1,
+        sight: 2,
+        armour: 0,
+        can_repair: false,
+        can_upgrade: false,
+        message: None,
+        brain: Some(builtin("creature")),
+      },
+    }
+  }
+}
+
+fn builtin(name: &str) -> Full {
+  Full {
+    half: [0, 0],
+    code_name: format!("builtin/{}", name),
+    gas: 0,
+  }
+}
+
+struct Turret;
+
+impl Bot for Turret {
+  fn execute(&mut self) -> i64 {
+    encode_verb(tools::neutral::turret(TURRET_RANGE as i64))
+  }
+}
+
+struct Creature;
+
+impl Bot for Creature {
+  fn execute(&mut self) -> i64 {
+    encode_verb(tools::neutral::creature())
+  }
+}
+
+// the brain of a neutral entity, by the code name of its template
+pub fn builtin_brain(code_name: &str) -> Option<fn() -> Box<dyn Bot>> {
+  match code_name {
+    "builtin/turret" => Some(|| Box::new(Turret)),
+    "builtin/creature" => Some(|| Box::new(Creature)),
+    _ => None,
+  }
+}
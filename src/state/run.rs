@@ -1,48 +1,235 @@
 extern crate rand;
 extern crate rand_chacha;
 
+use snafu::prelude::*;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 
-use crate::state::bf::{build_state, BFState};
-use crate::state::brain::Brains;
-use crate::state::state::{Frame, Script};
+use crate::state::bf::{build_state, BFState, BuildError};
+use crate::state::binary::{self, BinaryError};
+use crate::state::brain::{BrainError, Brains, ExecutionError, FaultPolicy, NativeBrains};
+use crate::state::config::Config;
+use crate::state::state::{diff, Event, Fault, Frame, Id, Log, Script, State, StateDiff};
+use crate::state::utils::get_next_file_number;
 
+#[derive(Debug, Snafu)]
+pub enum RunError {
+  #[snafu(display("{}", source))]
+  Build { source: BuildError },
+  #[snafu(display("Could not load the brains: {}", source))]
+  LoadBrains { source: BrainError },
+  #[snafu(display("Turn {}, entity {}: {}", turn, id, source))]
+  Execution {
+    source: ExecutionError,
+    id: Id,
+    turn: usize,
+  },
+}
+
+// plays the squads against each other, one per team in the order of the
+// regions of the board
 pub fn run_match(
+  config: &Config,
   level: &BFState,
-  blue_squad: &BFState,
-  red_squad: &BFState,
+  squads: &[&BFState],
   turns: usize,
-) -> Script {
-  // run match
-  let initial_state = build_state(&level, &blue_squad, &red_squad);
-  let state = Arc::new(Mutex::new(initial_state.clone())).clone();
+) -> Result<Script, RunError> {
+  run_match_with_natives(config, level, squads, turns, &NativeBrains::new())
+}
+
+// same as run_match, but the bots in `natives` run natively instead of
+// being loaded from their wasm files
+pub fn run_match_with_natives(
+  config: &Config,
+  level: &BFState,
+  squads: &[&BFState],
+  turns: usize,
+  natives: &NativeBrains,
+) -> Result<Script, RunError> {
+  let genesis = build_state(level, squads).context(BuildSnafu)?;
+  Ok(play(config, genesis, natives, FaultPolicy::default(), turns)?.script)
+}
+
+// a played match, with what the script leaves out
+pub struct Played {
+  pub script: Script,
+  // the state after the last turn
+  pub state: State,
+  // every command the bots returned, including those the state rejected
+  pub emitted: Vec<Frame>,
+}
 
-  let mut brains: Brains = Brains::new(state.clone()).unwrap();
+// plays a built state for a number of turns, every entity acting once per
+// turn in the order they were made
+pub fn play(
+  config: &Config,
+  genesis: State,
+  natives: &NativeBrains,
+  policy: FaultPolicy,
+  turns: usize,
+) -> Result<Played, RunError> {
+  let state = Arc::new(Mutex::new(genesis.clone()));
+  let mut brains =
+    Brains::new_with_natives(config, state.clone(), natives).context(LoadBrainsSnafu)?;
+  brains.set_fault_policy(policy);
   let mut frames: Vec<Frame> = vec![];
+  let mut emitted: Vec<Frame> = vec![];
+  let mut logs: Vec<Vec<Log>> = vec![];
+  let mut faults: Vec<Vec<Fault>> = vec![];
+  let mut events: Vec<Vec<Event>> = vec![];
+  let mut hashes: Vec<u64> = vec![];
 
-  for _ in 1..turns {
+  for turn in 0..turns {
     let mut frame = vec![];
+    let mut emitted_frame = vec![];
     let id_vec = state.lock().unwrap().get_entities_ids();
     for id in id_vec {
-      let exists = matches!(state.lock().unwrap().get_entity_by_id(id), Ok(_));
-      if exists {
-        match brains.get_command(id) {
-          Ok(command) => {
-            if let Ok(_) = state.lock().unwrap().execute_command(command.clone()) {
-              frame.push(command.clone());
-            }
-          }
-          Err(e) => {
-            println!("{:}", e);
-          }
-        };
+      if state.lock().unwrap().get_entity_by_id(id).is_err() {
+        continue;
+      }
+      let command = brains
+        .get_command(id)
+        .context(ExecutionSnafu { id, turn })?;
+      emitted_frame.push(command.clone());
+      if state
+        .lock()
+        .unwrap()
+        .execute_command(command.clone())
+        .is_ok()
+      {
+        frame.push(command);
       }
     }
+    let frame_faults = brains.take_faults();
+    state.lock().unwrap().apply_faults(&frame_faults);
     frames.push(frame);
+    emitted.push(emitted_frame);
+    logs.push(brains.take_logs());
+    faults.push(frame_faults);
+    events.push(state.lock().unwrap().take_events());
+    hashes.push(state.lock().unwrap().hash());
+  }
+  let state = state.lock().unwrap().clone();
+  Ok(Played {
+    script: Script {
+      genesis,
+      frames,
+      logs,
+      faults,
+      events,
+      hashes,
+    },
+    state,
+    emitted,
+  })
+}
+
+// plays a frame of a script on the state, as the match did
+pub fn replay_frame(state: &mut State, script: &Script, frame: usize) {
+  for command in script.frames[frame].iter() {
+    let _ = state.execute_command(command.clone());
   }
-  Script {
-    genesis: initial_state,
-    frames,
+  if let Some(faults) = script.faults.get(frame) {
+    state.apply_faults(faults);
   }
+  // the script already has the events of this frame
+  state.take_events();
+}
+
+// the state after the first `frames` frames of a script
+pub fn replay(script: &Script, frames: usize) -> State {
+  let mut state = script.genesis.clone();
+  for frame in 0..frames.min(script.frames.len()) {
+    replay_frame(&mut state, script, frame);
+  }
+  state
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(display(
+  "Replay diverges on frame {}: hash {:x} instead of {:x}",
+  frame,
+  found,
+  expected
+))]
+pub struct Desync {
+  pub frame: usize,
+  pub expected: u64,
+  pub found: u64,
+}
+
+// plays the script again and checks the state after every frame against
+// the hash the match recorded. Scripts without hashes pass
+pub fn verify_replay(script: &Script) -> Result<(), Desync> {
+  let mut state = script.genesis.clone();
+  for (frame, expected) in script.hashes.iter().enumerate().take(script.frames.len()) {
+    replay_frame(&mut state, script, frame);
+    let found = state.hash();
+    ensure!(
+      found == *expected,
+      DesyncSnafu {
+        frame,
+        expected: *expected,
+        found
+      }
+    );
+  }
+  Ok(())
+}
+
+// the first frame after which two scripts of the same match have different
+// states, with what differs between them
+pub fn first_divergence(a: &Script, b: &Script) -> Option<(usize, StateDiff)> {
+  let frame = a
+    .hashes
+    .iter()
+    .zip(b.hashes.iter())
+    .position(|(x, y)| x != y)?;
+  Some((frame, diff(&replay(a, frame + 1), &replay(b, frame + 1))))
+}
+
+// saves a script in the replays of the data directory, in the format of
+// the config, returning its number
+pub fn save_replay(config: &Config, script: &Script) -> io::Result<usize> {
+  let dir = config.replays_dir();
+  fs::create_dir_all(&dir)?;
+  let n = get_next_file_number(&dir, "rpl".to_string());
+  let mut dest = dir.join(format!("{:05}", n));
+  dest.set_extension("rpl");
+  fs::write(dest, binary::to_bytes(script, config.format))?;
+  Ok(n)
+}
+
+#[derive(Debug, Snafu)]
+pub enum ReplayError {
+  #[snafu(display("Could not read {}: {}", path.display(), source))]
+  UnreadableReplay { source: io::Error, path: PathBuf },
+  #[snafu(display("{} is malformed: {}", path.display(), source))]
+  MalformedReplay { source: BinaryError, path: PathBuf },
+  #[snafu(display("{}: {}", path.display(), source))]
+  DesyncedReplay { source: Desync, path: PathBuf },
+}
+
+fn replay_path(config: &Config, n: usize) -> PathBuf {
+  let mut path = config.replays_dir().join(format!("{:05}", n));
+  path.set_extension("rpl");
+  path
+}
+
+pub fn load_replay(config: &Config, n: usize) -> Result<Script, ReplayError> {
+  let path = replay_path(config, n);
+  let bytes = fs::read(&path).context(UnreadableReplaySnafu { path: &path })?;
+  binary::from_bytes(&bytes).context(MalformedReplaySnafu { path })
+}
+
+// loads a saved replay and plays it again against its hashes
+pub fn check_replay(config: &Config, n: usize) -> Result<Script, ReplayError> {
+  let script = load_replay(config, n)?;
+  verify_replay(&script).context(DesyncedReplaySnafu {
+    path: replay_path(config, n),
+  })?;
+  Ok(script)
 }
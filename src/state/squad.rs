@@ -2,11 +2,12 @@ use serde::{Deserialize, Serialize};
 use snafu::prelude::*;
 use std::collections::HashMap;
 
-use super::constants::{HEIGHT, NUM_CODES, NUM_TEMPLATES, WIDTH};
-use super::entity::{Code, Team, TemplateEntity};
-use super::geometry::Pos;
+use super::constants::{NUM_CODES, NUM_TEMPLATES};
+use super::entity::{Code, EconomyRules, Team, TemplateEntity};
+use super::geometry::{Board, Pos};
 use super::materials::Materials;
-use super::state::{State, StateError, Tile};
+use super::neutral::Neutral;
+use super::state::{ActionCosts, CombatRules, LootRules, State, StateError, Terrain, Tile};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Placement {
@@ -46,12 +47,31 @@ pub enum SquadError {
     template: usize,
     pos: Pos,
   },
+  #[snafu(display("Placing neutral {:?} in pos: {:?}", neutral, pos))]
+  BuildNeutralError {
+    source: StateError,
+    neutral: Neutral,
+    pos: Pos,
+  },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Settings {
+  #[serde(default)]
+  pub board: Board,
   pub min_tokens: usize,
   pub tiles: Vec<Tile>,
+  // entities of no team, anywhere on the board
+  #[serde(default)]
+  pub neutrals: Vec<(Pos, Neutral)>,
+  #[serde(default)]
+  pub loot: LootRules,
+  #[serde(default)]
+  pub costs: ActionCosts,
+  #[serde(default)]
+  pub combat: CombatRules,
+  #[serde(default)]
+  pub economy: EconomyRules,
 }
 // implement comparison between Blueprints. It is used to
 // we require:
@@ -61,24 +81,20 @@ pub struct Settings {
 // - entities will give rise to certain amounts of: full, half, bodies and materials.
 //   all these values should be bigger or equal in b than in a.
 // - perhaps key should be an asset as well, distributed by the level designer and the players
-// - think abou how to create NPC entities.
 
-// implement conversion from two blueprints to one state
-pub fn build_state(
-  blue_squad: Squad,
-  red_squad: Squad,
-  settings: Settings,
-) -> Result<State, SquadError> {
+// implement conversion from the blueprints of the teams to one state
+pub fn build_state(squads: Vec<Squad>, settings: Settings) -> Result<State, SquadError> {
+  let board = settings.board;
   let mut state = State::new(
+    board,
     settings.min_tokens,
-    blue_squad.codes,
-    red_squad.codes,
+    squads.iter().map(|s| s.codes.clone()).collect(),
     HashMap::new(),
-    blue_squad.templates,
-    red_squad.templates,
-    (0..(WIDTH * HEIGHT))
+    squads.iter().map(|s| s.templates.clone()).collect(),
+    (0..board.size())
       .map(|_| Tile {
         entity_id: None,
+        terrain: Terrain::Floor,
         materials: Materials {
           carbon: 0,
           silicon: 0,
@@ -89,47 +105,38 @@ pub fn build_state(
       .collect(),
   );
   state.tiles = settings.tiles;
-  for placement in blue_squad.placements {
-    ensure!(
-      placement.pos.y < HEIGHT / 2,
-      WrongCourtSideSnafu {
-        team: Team::Blue,
-        pos: placement.pos
-      }
-    );
-    state
-      .build_entity_from_template(
-        Team::Blue,
-        if placement.grayed { 0 } else { 1 },
-        placement.template,
-        placement.pos,
-      )
-      .context(BuildEntitySnafu {
-        team: Team::Blue,
-        template: placement.template,
-        pos: placement.pos,
-      })?;
+  state.loot = settings.loot;
+  state.costs = settings.costs;
+  state.combat = settings.combat;
+  state.economy = settings.economy;
+  for (team, squad) in squads.into_iter().enumerate() {
+    let team = Team(team);
+    for placement in squad.placements {
+      ensure!(
+        board.team_at(placement.pos) == team,
+        WrongCourtSideSnafu {
+          team,
+          pos: placement.pos
+        }
+      );
+      state
+        .build_entity_from_template(
+          team,
+          if placement.grayed { 0 } else { 1 },
+          placement.template,
+          placement.pos,
+        )
+        .context(BuildEntitySnafu {
+          team,
+          template: placement.template,
+          pos: placement.pos,
+        })?;
+    }
   }
-  for placement in red_squad.placements {
-    ensure!(
-      placement.pos.y >= HEIGHT / 2,
-      WrongCourtSideSnafu {
-        team: Team::Red,
-        pos: placement.pos
-      }
-    );
+  for (pos, neutral) in settings.neutrals {
     state
-      .build_entity_from_template(
-        Team::Red,
-        if placement.grayed { 0 } else { 1 },
-        placement.template,
-        placement.pos,
-      )
-      .context(BuildEntitySnafu {
-        team: Team::Red,
-        template: placement.template,
-        pos: placement.pos,
-      })?;
+      .build_neutral(neutral, pos)
+      .context(BuildNeutralSnafu { neutral, pos })?;
   }
   Ok(state)
 }
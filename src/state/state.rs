@@ -3,46 +3,162 @@ use cfg_log::*;
 use line_drawing::Bresenham;
 use serde::{Deserialize, Serialize};
 use snafu::prelude::*;
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::collections::HashMap;
 
-use super::constants::{HEIGHT, NUM_CODES, NUM_TEMPLATES, WIDTH};
-use super::entity::{cost, Action, ActiveEntity, Code, Message, Team, TemplateEntity};
+use super::constants::{NUM_CODES, NUM_TEMPLATES, REPAIR_HP};
+use super::entity::{
+  cost, Action, ActiveEntity, Code, EconomyRules, Message, MovementType, Stat, Team, TemplateEntity,
+};
 use super::geometry::{
-  add_displace, is_within_bounds_signed, Direction, Displace, GeometryError, Neighbor, Pos,
+  difference, Board, Direction, Displace, GeometryError, Neighbor, Pos, Symmetry,
 };
 use super::materials::Materials;
+use super::neutral::Neutral;
 
 // https://wowpedia.fandom.com/wiki/Warcraft:_Orcs_%26_Humans_missions?file=WarCraft-Orcs%26amp%3BHumans-Orcs-Scenario9-SouthernElwynnForest.png
 
 pub type Id = usize;
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Terrain {
+  #[default]
+  Floor,
+  // blocks walking and line of sight
+  Wall,
+  // blocks walking, but can be shot across
+  Water,
+}
+
+impl Terrain {
+  pub fn is_walkable(&self) -> bool {
+    *self == Terrain::Floor
+  }
+  pub fn blocks_sight(&self) -> bool {
+    *self == Terrain::Wall
+  }
+  // whether an entity moving this way can enter the tile
+  pub fn can_cross(&self, movement: &MovementType) -> bool {
+    match movement {
+      MovementType::Fly => *self != Terrain::Wall,
+      _ => self.is_walkable(),
+    }
+  }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Tile {
   pub materials: Materials,
   pub entity_id: Option<Id>,
+  // levels saved before terrain existed are open fields
+  #[serde(default)]
+  pub terrain: Terrain,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum GameStatus {
   Running,
-  BlueWon,
-  RedWon,
+  // the last team standing
+  Won(Team),
+}
+
+// percentages of its inventory and of the cost of its body that an entity
+// drops on the floor when it dies
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LootRules {
+  pub inventory: usize,
+  pub cost: usize,
+}
+
+impl Default for LootRules {
+  fn default() -> Self {
+    LootRules {
+      inventory: 50,
+      cost: 25,
+    }
+  }
+}
+
+// materials that entities spend from their inventory to act
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ActionCosts {
+  // copper per shot
+  pub shot: usize,
+  // one plutonium every this many moves, walking is free when it is zero
+  pub moves_per_plutonium: usize,
+  // carbon per repair
+  pub repair: usize,
+}
+
+impl Default for ActionCosts {
+  fn default() -> Self {
+    ActionCosts {
+      shot: 1,
+      moves_per_plutonium: 0,
+      repair: 1,
+    }
+  }
+}
+
+// how shots hit and hurt
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CombatRules {
+  // whether shots can hit bots of the shooter's team
+  pub friendly_fire: bool,
+  // whether shots fly past bots of the shooter's team
+  pub pass_through_allies: bool,
+  // damage lost for each tile the shot flies after the first
+  pub falloff: usize,
+}
+
+impl Default for CombatRules {
+  fn default() -> Self {
+    CombatRules {
+      friendly_fire: true,
+      pass_through_allies: false,
+      falloff: 0,
+    }
+  }
+}
+
+// what happened during a turn, besides the commands of the bots
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Event {
+  // an entity died, spreading its loot on the floor around its tile
+  Death {
+    entity_id: Id,
+    pos: Pos,
+    loot: Vec<(Pos, Materials)>,
+  },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct State {
+  // scripts saved before boards had a size were played on the default one
+  #[serde(default)]
+  pub board: Board,
   pub game_status: GameStatus,
   pub min_tokens: usize,
-  pub blue_tokens: usize,
-  pub red_tokens: usize,
-  blue_codes: [Option<Code>; NUM_CODES],
-  red_codes: [Option<Code>; NUM_CODES],
+  // indexed by team
+  pub tokens: Vec<usize>,
+  codes: Vec<[Option<Code>; NUM_CODES]>,
   pub entities: HashMap<Id, ActiveEntity>,
   next_unique_id: usize,
-  pub blue_templates: [Option<TemplateEntity>; NUM_TEMPLATES],
-  pub red_templates: [Option<TemplateEntity>; NUM_TEMPLATES],
+  pub templates: Vec<[Option<TemplateEntity>; NUM_TEMPLATES]>,
   pub tiles: Vec<Tile>,
+  // scripts saved before entities dropped loot use the default rules
+  #[serde(default)]
+  pub loot: LootRules,
+  // scripts saved before actions had a cost use the default ones
+  #[serde(default)]
+  pub costs: ActionCosts,
+  #[serde(default)]
+  pub combat: CombatRules,
+  #[serde(default)]
+  pub economy: EconomyRules,
+  // events since the last call to take_events
+  #[serde(skip)]
+  events: Vec<Event>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -61,17 +177,28 @@ pub enum Verb {
   Drill(Direction),
   Construct(usize, Direction),
   SetMessage(Message),
+  Repair(Neighbor),
+  Upgrade(Stat),
+  Give(Neighbor, Materials),
 }
 
 impl Verb {
-  pub fn invert(&self) -> Self {
+  // the verb of a bot of `team` on the board, as bots see the board
+  // transformed by the symmetry
+  pub fn from_team(&self, symmetry: Symmetry, team: Team) -> Self {
     match self {
-      Verb::AttemptMove(dir) => Verb::AttemptMove(dir.invert()),
-      Verb::GetMaterials(n, m) => Verb::GetMaterials(n.invert().clone(), m.clone()),
-      Verb::DropMaterials(n, m) => Verb::DropMaterials(n.invert().clone(), m.clone()),
-      Verb::Shoot(d) => Verb::Shoot(d.invert().clone()),
-      Verb::Drill(d) => Verb::Drill(d.invert().clone()),
-      Verb::Construct(t, d) => Verb::Construct(*t, d.invert().clone()),
+      Verb::AttemptMove(dir) => Verb::AttemptMove(symmetry.direction_from_team(team, *dir)),
+      Verb::GetMaterials(n, m) => {
+        Verb::GetMaterials(symmetry.neighbor_from_team(team, *n), m.clone())
+      }
+      Verb::DropMaterials(n, m) => {
+        Verb::DropMaterials(symmetry.neighbor_from_team(team, *n), m.clone())
+      }
+      Verb::Shoot(d) => Verb::Shoot(symmetry.displace_from_team(team, d)),
+      Verb::Drill(d) => Verb::Drill(symmetry.direction_from_team(team, *d)),
+      Verb::Construct(t, d) => Verb::Construct(*t, symmetry.direction_from_team(team, *d)),
+      Verb::Repair(n) => Verb::Repair(symmetry.neighbor_from_team(team, *n)),
+      Verb::Give(n, m) => Verb::Give(symmetry.neighbor_from_team(team, *n), m.clone()),
       _ => self.clone(),
     }
   }
@@ -89,6 +216,8 @@ pub enum StateError {
   EmptyTile { pos: Pos },
   #[snafu(display("Occupied tile {:?}", pos))]
   OccupiedTile { pos: Pos },
+  #[snafu(display("Tile {:?} cannot be walked on", pos))]
+  Impassable { pos: Pos },
   #[snafu(display("Floor at {pos:?} does not have {load:?}"))]
   NoMaterialFloor { pos: Pos, load: Materials },
   #[snafu(display("Entity at {pos:?} does not fit {load:?}"))]
@@ -105,8 +234,24 @@ pub enum StateError {
   NoCopper { pos: Pos },
   #[snafu(display("Entity in {pos} cannot walk"))]
   NoWalk { pos: Pos },
+  #[snafu(display("Entity in {pos} has no plutonium to walk"))]
+  NoFuel { pos: Pos },
+  #[snafu(display("Entity in {pos} cannot repair"))]
+  NoRepair { pos: Pos },
+  #[snafu(display("Entity in {pos} cannot upgrade"))]
+  NoUpgrade { pos: Pos },
+  #[snafu(display("Entity in {pos} is not damaged"))]
+  Undamaged { pos: Pos },
+  #[snafu(display("Entity in {pos} is not a friend"))]
+  NotFriendly { pos: Pos },
+  #[snafu(display("Entity in {pos} cannot give to itself"))]
+  GiveToSelf { pos: Pos },
+  #[snafu(display("Shot at friendly entity in {pos}"))]
+  FriendlyFire { pos: Pos },
   #[snafu(display("Displacement {:?} too far", disp))]
   DisplaceTooFar { disp: Displace },
+  #[snafu(display("Entity in {pos} belongs to no team"))]
+  NoTeam { pos: Pos },
   #[snafu(display("No entity in {team:?} with template{template}"))]
   NoTemplate { team: Team, template: usize },
   // #[snafu(display("Error implementing effect {:?}", effect))]
@@ -119,53 +264,59 @@ pub enum StateError {
 
 impl State {
   pub fn new(
+    board: Board,
     min_tokens: usize,
-    blue_codes: [Option<Code>; NUM_CODES],
-    red_codes: [Option<Code>; NUM_CODES],
+    codes: Vec<[Option<Code>; NUM_CODES]>,
     entities: HashMap<Id, ActiveEntity>,
-    blue_templates: [Option<TemplateEntity>; NUM_TEMPLATES],
-    red_templates: [Option<TemplateEntity>; NUM_TEMPLATES],
+    templates: Vec<[Option<TemplateEntity>; NUM_TEMPLATES]>,
     tiles: Vec<Tile>,
   ) -> Self {
-    assert!(tiles.len() == WIDTH * HEIGHT);
+    assert!(tiles.len() == board.size());
+    assert!(codes.len() == templates.len() && templates.len() <= board.teams());
     debug!("Creating new state...");
     let next_unique_id = entities.iter().fold(0, |a, (id, _)| max(a, *id));
     State {
+      board,
       game_status: GameStatus::Running,
       min_tokens,
-      blue_tokens: 0,
-      red_tokens: 0,
-      blue_codes,
-      red_codes,
+      tokens: vec![0; templates.len()],
+      codes,
       entities,
       next_unique_id,
-      blue_templates,
-      red_templates,
+      templates,
       tiles,
+      loot: LootRules::default(),
+      costs: ActionCosts::default(),
+      combat: CombatRules::default(),
+      economy: EconomyRules::default(),
+      events: vec![],
     }
   }
   pub fn has_entity(&self, pos: Pos) -> bool {
-    self.tiles[pos.to_index()].entity_id.is_some()
+    self.tiles[self.board.index(pos)].entity_id.is_some()
   }
   pub fn get_tile(&self, pos: Pos) -> &Tile {
-    &self.tiles[pos.to_index()]
+    &self.tiles[self.board.index(pos)]
   }
   pub fn get_mut_tile(&mut self, pos: Pos) -> &mut Tile {
-    &mut self.tiles[pos.to_index()]
+    &mut self.tiles[self.board.index(pos)]
+  }
+  pub fn get_terrain(&self, pos: Pos) -> Terrain {
+    self.tiles[self.board.index(pos)].terrain
   }
   pub fn get_floor_mat(&self, pos: Pos) -> &Materials {
-    &self.tiles[pos.to_index()].materials
+    &self.tiles[self.board.index(pos)].materials
   }
   pub fn get_creature(&self, team: Team, template: usize) -> Result<TemplateEntity, StateError> {
     ensure!(
       template < NUM_TEMPLATES,
       TemplateOutOfBoundsSnafu { template }
     );
-    match team {
-      Team::Blue => self.blue_templates[template].clone(),
-      Team::Red => self.red_templates[template].clone(),
-    }
-    .ok_or(StateError::NoTemplate { team, template })
+    self
+      .templates
+      .get(team.0)
+      .and_then(|t| t[template].clone())
+      .ok_or(StateError::NoTemplate { team, template })
   }
   pub fn build_entity_from_template(
     &mut self,
@@ -179,15 +330,23 @@ impl State {
       team, pos, template
     );
     ensure!(!self.has_entity(pos), OccupiedTileSnafu { pos });
+    ensure!(self.get_terrain(pos).is_walkable(), ImpassableSnafu { pos });
     let entity = self
       .get_creature(team, template)
-      .map(|t| t.upgrade(tokens, team, pos))?;
-    match team {
-      Team::Blue => self.blue_tokens += tokens,
-      Team::Red => self.red_tokens += tokens,
-    };
+      .map(|t| t.upgrade(tokens, Some(team), pos))?;
+    self.tokens[team.0] += tokens;
     self.entities.insert(self.next_unique_id, entity);
-    self.tiles[pos.to_index()].entity_id = Some(self.next_unique_id);
+    self.tiles[self.board.index(pos)].entity_id = Some(self.next_unique_id);
+    self.next_unique_id += 1;
+    Ok(())
+  }
+  pub fn build_neutral(&mut self, neutral: Neutral, pos: Pos) -> Result<(), StateError> {
+    debug!("Building neutral {:?} at {:?}", neutral, pos);
+    ensure!(!self.has_entity(pos), OccupiedTileSnafu { pos });
+    ensure!(self.get_terrain(pos).is_walkable(), ImpassableSnafu { pos });
+    let entity = neutral.template().upgrade(0, None, pos);
+    self.entities.insert(self.next_unique_id, entity);
+    self.tiles[self.board.index(pos)].entity_id = Some(self.next_unique_id);
     self.next_unique_id += 1;
     Ok(())
   }
@@ -203,8 +362,9 @@ impl State {
       entity_id, template, pos
     );
     ensure!(!self.has_entity(pos), OccupiedTileSnafu { pos: pos });
+    ensure!(self.get_terrain(pos).is_walkable(), ImpassableSnafu { pos });
+    let constr_cost = cost(&creature, &self.economy);
     let entity = self.get_mut_entity_by_id(entity_id)?;
-    let constr_cost = cost(&creature);
     ensure!(
       entity.materials >= constr_cost,
       NoMaterialEntitySnafu {
@@ -212,30 +372,104 @@ impl State {
         load: constr_cost,
       }
     );
+    let team = entity.team.ok_or(StateError::NoTeam { pos: entity.pos })?;
     entity.materials -= constr_cost;
-    let team = entity.team;
     self.build_entity_from_template(team, 0, template, pos)
   }
   pub fn remove_entity(&mut self, pos: Pos) -> Result<(), StateError> {
     debug!("Removing entity at {:?}", pos);
-    let id = self.tiles[pos.to_index()]
+    let id = self.tiles[self.board.index(pos)]
       .entity_id
       .ok_or(StateError::EmptyTile { pos })?;
     let entity = self.get_entity_by_id(id)?;
-    match self.get_entity_by_id(id)?.team {
-      Team::Blue => self.blue_tokens -= entity.tokens,
-      Team::Red => self.red_tokens -= entity.tokens,
-    };
-    if self.blue_tokens < self.min_tokens {
-      self.game_status = GameStatus::RedWon
-    };
-    if self.red_tokens < self.min_tokens {
-      self.game_status = GameStatus::BlueWon
-    };
+    let (team, tokens) = (entity.team, entity.tokens);
+    let loot = entity.materials.percent(self.loot.inventory)
+      + cost(&entity.body(), &self.economy).percent(self.loot.cost);
     self.entities.remove(&id);
-    self.tiles[pos.to_index()].entity_id = None;
+    self.tiles[self.board.index(pos)].entity_id = None;
+    self.drop_loot(id, pos, loot);
+    // neutral entities do not count for any team
+    if let Some(team) = team {
+      let was_standing = self.is_standing(team);
+      self.tokens[team.0] -= tokens;
+      if was_standing && !self.is_standing(team) {
+        self.eliminate(team);
+      }
+    }
     Ok(())
   }
+  // spreads the loot of a dead entity over its tile and the walkable ones
+  // next to it
+  fn drop_loot(&mut self, entity_id: Id, pos: Pos, loot: Materials) {
+    let mut targets = vec![pos];
+    for dir in [
+      Direction::North,
+      Direction::East,
+      Direction::South,
+      Direction::West,
+    ] {
+      if let Ok(neighbor) = self.board.add_displace(pos, &dir.into()) {
+        if self.get_terrain(neighbor).is_walkable() {
+          targets.push(neighbor);
+        }
+      }
+    }
+    let piles = loot.split(targets.len());
+    let loot: Vec<(Pos, Materials)> = targets
+      .into_iter()
+      .zip(piles)
+      .filter(|(_, m)| m.volume() > 0)
+      .collect();
+    for (target, materials) in loot.iter() {
+      self.tiles[self.board.index(*target)].materials += materials.clone();
+    }
+    self.events.push(Event::Death {
+      entity_id,
+      pos,
+      loot,
+    });
+  }
+  // events since the last call, to be recorded in the script
+  pub fn take_events(&mut self) -> Vec<Event> {
+    std::mem::take(&mut self.events)
+  }
+  pub fn teams(&self) -> usize {
+    self.templates.len()
+  }
+  pub fn is_standing(&self, team: Team) -> bool {
+    self.tokens[team.0] >= self.min_tokens
+  }
+  // a team that falls below the minimum of tokens is out: the last one
+  // standing wins, otherwise its entities leave the board
+  fn eliminate(&mut self, team: Team) {
+    let standing: Vec<Team> = (0..self.teams())
+      .map(Team)
+      .filter(|t| self.is_standing(*t))
+      .collect();
+    if let [winner] = standing[..] {
+      self.game_status = GameStatus::Won(winner);
+      return;
+    }
+    let ids: Vec<Id> = self
+      .entities
+      .iter()
+      .filter(|(_, e)| e.team == Some(team))
+      .map(|(id, _)| *id)
+      .collect();
+    for id in ids {
+      let pos = self.entities[&id].pos;
+      self.entities.remove(&id);
+      self.tiles[self.board.index(pos)].entity_id = None;
+    }
+  }
+  // removes the entities killed by the fault policy, at the end of a turn
+  pub fn apply_faults(&mut self, faults: &[Fault]) {
+    for fault in faults.iter().filter(|f| f.action == FaultAction::Kill) {
+      if let Ok(entity) = self.get_entity_by_id(fault.entity_id) {
+        let _ = self.remove_entity(entity.pos);
+      }
+    }
+  }
   pub fn get_entity(&self, pos: Pos) -> Result<&ActiveEntity, StateError> {
     let id = self
       .get_tile(pos)
@@ -269,8 +503,12 @@ impl State {
       .ok_or(StateError::EmptyTile { pos })?;
     Ok(self.entities.get_mut(&id).unwrap())
   }
+  // in the order they were made, so that every run of a match moves them
+  // in the same order
   pub fn get_entities_ids(&self) -> Vec<Id> {
-    self.entities.keys().map(|x| *x).collect()
+    let mut ids: Vec<Id> = self.entities.keys().copied().collect();
+    ids.sort();
+    ids
   }
   pub fn set_entity_action(&mut self, id: Id, action: Action) -> Result<(), StateError> {
     self
@@ -284,26 +522,68 @@ impl State {
     debug!("Moving entity from {:?} to {:?}", from, to);
     ensure!(self.has_entity(from), EmptyTileSnafu { pos: from });
     ensure!(!self.has_entity(to), OccupiedTileSnafu { pos: to });
-    let id = self.tiles[from.to_index()].entity_id.unwrap();
+    let movement = &self.get_entity(from)?.movement_type;
+    ensure!(
+      self.get_terrain(to).can_cross(movement),
+      ImpassableSnafu { pos: to }
+    );
+    let id = self.tiles[self.board.index(from)].entity_id.unwrap();
     let entity = self.get_mut_entity(from).unwrap();
     entity.pos = to;
-    self.tiles[from.to_index()].entity_id = None;
-    self.tiles[to.to_index()].entity_id = Some(id);
+    self.tiles[self.board.index(from)].entity_id = None;
+    self.tiles[self.board.index(to)].entity_id = Some(id);
     Ok(())
   }
   pub fn get_visible(&self, from: Pos, disp: &Displace) -> Option<Pos> {
+    self.get_line_of_fire(from, disp, None)
+  }
+  // like get_visible, but looking past the entities of `allies`
+  pub fn get_line_of_fire(&self, from: Pos, disp: &Displace, allies: Option<Team>) -> Option<Pos> {
     let point_from = (from.x as i64, from.y as i64);
     let point_to = (from.x as i64 + disp.x, from.y as i64 + disp.y);
     for (x, y) in Bresenham::new(point_from, point_to).skip(1) {
-      if !is_within_bounds_signed(x, y) {
+      if !self.board.contains_signed(x, y) {
         return None;
       }
-      if self.has_entity(Pos::new(x as usize, y as usize)) {
-        return Some(Pos::new(x as usize, y as usize));
+      let pos = Pos::new(x as usize, y as usize);
+      if let Some(entity) = self.get_tile(pos).entity_id {
+        let team = self.get_entity_by_id(entity).ok().and_then(|e| e.team);
+        if allies.is_none() || team != allies {
+          return Some(pos);
+        }
+      }
+      if self
+        .get_terrain(Pos::new(x as usize, y as usize))
+        .blocks_sight()
+      {
+        return None;
       }
     }
     Some(Pos::new(point_to.0 as usize, point_to.1 as usize))
   }
+  // whether an entity with the given sight at `from` can see the tile at
+  // `from + disp`: it has to be within the sight radius, on the board and
+  // no other entity or wall may stand between them
+  pub fn is_visible(&self, from: Pos, disp: &Displace, sight: usize) -> bool {
+    if disp.square_norm() > sight.saturating_mul(sight).min(i64::MAX as usize) as i64 {
+      return false;
+    }
+    let point_from = (from.x as i64, from.y as i64);
+    let point_to = (from.x as i64 + disp.x, from.y as i64 + disp.y);
+    if !self.board.contains_signed(point_to.0, point_to.1) {
+      return false;
+    }
+    for (x, y) in Bresenham::new(point_from, point_to).skip(1) {
+      if (x, y) == point_to {
+        break;
+      }
+      let pos = Pos::new(x as usize, y as usize);
+      if self.has_entity(pos) || self.get_terrain(pos).blocks_sight() {
+        return false;
+      }
+    }
+    true
+  }
   pub fn move_material_to_entity(
     &mut self,
     from: Pos,
@@ -328,7 +608,7 @@ impl State {
       }
     );
     entity.materials += load.clone();
-    self.tiles[from.to_index()].materials -= load.clone();
+    self.tiles[self.board.index(from)].materials -= load.clone();
     Ok(())
   }
   pub fn move_material_to_floor(
@@ -348,12 +628,46 @@ impl State {
       }
     );
     entity.materials -= load.clone();
-    self.tiles[to.to_index()].materials += load.clone();
+    self.tiles[self.board.index(to)].materials += load.clone();
+    Ok(())
+  }
+  pub fn move_material_between_entities(
+    &mut self,
+    from: Pos,
+    to: Pos,
+    load: &Materials,
+  ) -> Result<(), StateError> {
+    debug!("Moving material from {:?} to entity {:?}", from, to);
+    ensure!(from != to, GiveToSelfSnafu { pos: from });
+    let team = self.get_entity(from)?.team;
+    ensure!(
+      self.get_entity(from)?.materials >= *load,
+      NoMaterialEntitySnafu {
+        pos: from,
+        load: load.clone()
+      }
+    );
+    let receiver = self.get_mut_entity(to)?;
+    // only bots of the same team trade, neutral ones have no friends
+    ensure!(
+      team.is_some() && receiver.team == team,
+      NotFriendlySnafu { pos: to }
+    );
+    ensure!(
+      receiver.inventory_size >= receiver.materials.volume() + load.volume(),
+      NoSpaceSnafu {
+        pos: to,
+        load: load.clone()
+      }
+    );
+    receiver.materials += load.clone();
+    self.get_mut_entity(from)?.materials -= load.clone();
     Ok(())
   }
   pub fn attack(&mut self, pos: Pos, damage: usize) -> Result<(), StateError> {
     debug!("Attacking pos {:?} for damage {:?}", pos, damage);
     let entity = self.get_mut_entity(pos)?;
+    let damage = damage.saturating_sub(entity.armour);
     if entity.hp > damage {
       debug!("Hp from {:} to {:}", entity.hp, entity.hp - damage);
       entity.hp -= damage;
@@ -362,11 +676,44 @@ impl State {
     }
     Ok(())
   }
-  pub fn add_displace(pos: Pos, disp: &Displace) -> Result<Pos, StateError> {
-    add_displace(pos, disp).context(DisplaceOutOfBoundsSnafu {
-      pos,
-      disp: disp.clone(),
-    })
+  // restores hp up to the maximum the entity was built or upgraded with
+  pub fn repair(&mut self, pos: Pos, amount: usize) -> Result<(), StateError> {
+    debug!("Repairing pos {:?} for {:?}", pos, amount);
+    let entity = self.get_mut_entity(pos)?;
+    let max_hp = max(entity.max_hp, entity.hp);
+    ensure!(entity.hp < max_hp, UndamagedSnafu { pos });
+    entity.hp = min(entity.hp + amount, max_hp);
+    Ok(())
+  }
+  // moves an entity one tile, spending plutonium on the first move of
+  // each stint
+  fn step(&mut self, id: Id, dir: Direction, costs: ActionCosts) -> Result<(), StateError> {
+    let entity = self.get_entity_by_id(id)?;
+    let from = entity.pos;
+    let to = State::add_displace(self.board, from, &Displace::from(dir))?;
+    let per_plutonium = costs.moves_per_plutonium;
+    let refuel = per_plutonium > 0 && entity.moves == 0;
+    ensure!(
+      !refuel || entity.materials.plutonium > 0,
+      NoFuelSnafu { pos: from }
+    );
+    self.move_entity(from, to)?;
+    let entity = self.get_mut_entity_by_id(id)?;
+    if refuel {
+      entity.materials.plutonium -= 1;
+    }
+    if per_plutonium > 0 {
+      entity.moves = (entity.moves + 1) % per_plutonium;
+    }
+    Ok(())
+  }
+  pub fn add_displace(board: Board, pos: Pos, disp: &Displace) -> Result<Pos, StateError> {
+    board
+      .add_displace(pos, disp)
+      .context(DisplaceOutOfBoundsSnafu {
+        pos,
+        disp: disp.clone(),
+      })
   }
 
   pub fn execute_command(&mut self, command: Command) -> Result<(), StateError> {
@@ -374,58 +721,109 @@ impl State {
     if self.game_status != GameStatus::Running {
       return Ok(());
     }
+    let board = self.board;
+    let costs = self.costs;
+    let combat = self.combat;
+    let economy = self.economy;
     let entity = self.get_mut_entity_by_id(command.entity_id)?;
     match command.verb {
       Verb::Wait => {
         self.set_entity_action(command.entity_id, Action::Wait)?;
       }
       Verb::AttemptMove(dir) => {
-        let from = entity.pos.clone();
-        let to = State::add_displace(entity.pos, &Displace::from(dir))?;
         ensure!(entity.can_move(), NoWalkSnafu { pos: entity.pos },);
-        self.move_entity(from, to)?;
+        // slow entities spend their attempts resting after each move
+        if entity.resting > 0 {
+          entity.resting -= 1;
+          self.set_entity_action(command.entity_id, Action::Wait)?;
+          return Ok(());
+        }
+        let steps = entity.steps;
+        for step in 0..steps {
+          // only the first tile has to be reachable, fast entities stop
+          // short of obstacles
+          match self.step(command.entity_id, dir, costs) {
+            Ok(()) => {}
+            Err(e) if step == 0 => return Err(e),
+            Err(_) => break,
+          }
+        }
+        let entity = self.get_mut_entity_by_id(command.entity_id)?;
+        entity.resting = entity.rest;
         self.set_entity_action(command.entity_id, Action::Move(dir))?;
       }
       Verb::GetMaterials(neigh, load) => {
         let to = entity.pos.clone();
-        let from = State::add_displace(entity.pos, &neigh.into())?;
+        let from = State::add_displace(board, entity.pos, &neigh.into())?;
         self.move_material_to_entity(from, to, &load)?;
         self.set_entity_action(command.entity_id, Action::GetMaterials(neigh, load))?;
       }
       Verb::DropMaterials(neigh, load) => {
         let from = entity.pos.clone();
-        let to = State::add_displace(entity.pos, &neigh.into())?;
+        let to = State::add_displace(board, entity.pos, &neigh.into())?;
         self.move_material_to_floor(from, to, &load)?;
         self.set_entity_action(command.entity_id, Action::DropMaterials(neigh, load))?;
       }
       Verb::Shoot(disp) => {
         let from = entity.pos.clone();
         ensure!(entity.can_shoot(), NoShootSnafu { pos: entity.pos });
-        ensure!(entity.has_copper(), NoCopperSnafu { pos: entity.pos });
+        let shot = costs.shot;
+        ensure!(
+          entity.materials.copper >= shot,
+          NoCopperSnafu { pos: entity.pos }
+        );
         let damage = entity.get_gun_damage();
+        let team = entity.team;
         ensure!(disp.square_norm() <= 25, DisplaceTooFarSnafu { disp: disp });
+        let allies = if combat.pass_through_allies {
+          team
+        } else {
+          None
+        };
         let to = self
-          .get_visible(from, &disp)
+          .get_line_of_fire(from, &disp, allies)
           .ok_or(StateError::NotVisible {
             pos: from,
             disp: disp.clone(),
           })?;
+        // a shot that would hit nothing fails before anything is paid
+        let target = self.get_entity(to)?;
+        ensure!(
+          combat.friendly_fire || team.is_none() || target.team != team,
+          FriendlyFireSnafu { pos: to }
+        );
+        let flight = difference(from, to);
+        let tiles = max(flight.x.unsigned_abs(), flight.y.unsigned_abs()) as usize;
+        let damage = damage.saturating_sub(combat.falloff * tiles.saturating_sub(1));
+        // paid first, the shot may take the shooter off the board along
+        // with the rest of its team
+        self
+          .get_mut_entity_by_id(command.entity_id)?
+          .materials
+          .copper -= shot;
         self.attack(to, damage)?;
-        self.set_entity_action(command.entity_id, Action::Shoot(disp))?;
+        if self.get_entity_by_id(command.entity_id).is_ok() {
+          self.set_entity_action(command.entity_id, Action::Shoot(disp))?;
+        }
       }
       Verb::Drill(dir) => {
         let damage = entity.get_drill_damage();
-        let to = add_displace(entity.pos, &dir.into()).context(DisplaceOutOfBoundsSnafu {
-          pos: entity.pos,
-          disp: dir.clone(),
-        })?;
+        let to = board
+          .add_displace(entity.pos, &dir.into())
+          .context(DisplaceOutOfBoundsSnafu {
+            pos: entity.pos,
+            disp: dir.clone(),
+          })?;
         self.attack(to, damage)?;
-        self.set_entity_action(command.entity_id, Action::Drill(dir))?;
+        // drilling a friend may take the driller's team off the board
+        if self.get_entity_by_id(command.entity_id).is_ok() {
+          self.set_entity_action(command.entity_id, Action::Drill(dir))?;
+        }
       }
       Verb::Construct(template, dir) => {
         let from = entity.pos.clone();
-        let to = State::add_displace(from, &Displace::from(dir))?;
-        let team = entity.team.clone();
+        let to = State::add_displace(board, from, &Displace::from(dir))?;
+        let team = entity.team.ok_or(StateError::NoTeam { pos: entity.pos })?;
         let creature = self.get_creature(team, template)?;
         self.construct_entity(command.entity_id, creature, template, to)?;
         self.set_entity_action(command.entity_id, Action::Construct(template, dir))?;
@@ -433,15 +831,286 @@ impl State {
       Verb::SetMessage(m) => {
         self.set_entity_action(command.entity_id, Action::SetMessage(m))?;
       }
+      Verb::Repair(neigh) => {
+        ensure!(entity.can_repair, NoRepairSnafu { pos: entity.pos });
+        let carbon = costs.repair;
+        ensure!(
+          entity.materials.carbon >= carbon,
+          NoMaterialEntitySnafu {
+            pos: entity.pos,
+            load: Materials::new(carbon, 0, 0, 0),
+          }
+        );
+        let to = State::add_displace(board, entity.pos, &neigh.into())?;
+        self.repair(to, REPAIR_HP)?;
+        self
+          .get_mut_entity_by_id(command.entity_id)?
+          .materials
+          .carbon -= carbon;
+        self.set_entity_action(command.entity_id, Action::Repair(neigh))?;
+      }
+      Verb::Upgrade(stat) => {
+        ensure!(entity.can_upgrade, NoUpgradeSnafu { pos: entity.pos });
+        // the price is what the upgrade adds to the cost of the body
+        let mut raised = entity.clone();
+        raised.raise(stat);
+        let price = cost(&raised.body(), &economy).saturating_sub(&cost(&entity.body(), &economy));
+        ensure!(
+          entity.materials >= price,
+          NoMaterialEntitySnafu {
+            pos: entity.pos,
+            load: price,
+          }
+        );
+        entity.materials -= price;
+        entity.raise(stat);
+        self.set_entity_action(command.entity_id, Action::Upgrade(stat))?;
+      }
+      Verb::Give(neigh, load) => {
+        let from = entity.pos;
+        let to = State::add_displace(board, entity.pos, &neigh.into())?;
+        self.move_material_between_entities(from, to, &load)?;
+        self.set_entity_action(command.entity_id, Action::Give(neigh, load))?;
+      }
     };
     return Ok(());
   }
+  // hash of the tiles, entities and tokens, which is the same on every
+  // machine and build, so that replays can tell where a run of a match
+  // went its own way. It is taken every turn, so everything is hashed field
+  // by field rather than through serde
+  pub fn hash(&self) -> u64 {
+    let mut hasher = StableHasher::default();
+    for tile in self.tiles.iter() {
+      hasher.write_materials(&tile.materials);
+      hasher.write(tile.entity_id.map_or(0, |id| id as u64 + 1));
+      hasher.write(tile.terrain as u64);
+    }
+    for id in self.get_entities_ids() {
+      hasher.write(id as u64);
+      hasher.write_entity(&self.entities[&id]);
+    }
+    for tokens in self.tokens.iter() {
+      hasher.write(*tokens as u64);
+    }
+    hasher.0
+  }
+}
+
+// 64 bit FNV-1a over words instead of bytes. The hashers of std may change
+// between releases
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+  fn default() -> Self {
+    StableHasher(0xcbf29ce484222325)
+  }
+}
+
+impl StableHasher {
+  fn write(&mut self, n: u64) {
+    self.0 = (self.0 ^ n).wrapping_mul(0x100000001b3);
+  }
+  fn write_bytes(&mut self, bytes: &[u8]) {
+    self.write(bytes.len() as u64);
+    for chunk in bytes.chunks(8) {
+      let mut word = [0; 8];
+      word[..chunk.len()].copy_from_slice(chunk);
+      self.write(u64::from_le_bytes(word));
+    }
+  }
+  fn write_materials(&mut self, m: &Materials) {
+    for n in [m.carbon, m.silicon, m.plutonium, m.copper] {
+      self.write(n as u64);
+    }
+  }
+  // the fields in the order they are declared
+  fn write_entity(&mut self, e: &ActiveEntity) {
+    self.write(e.tokens as u64);
+    self.write(e.team.map_or(0, |t| t.0 as u64 + 1));
+    self.write(e.pos.x as u64);
+    self.write(e.pos.y as u64);
+    self.write(e.hp as u64);
+    self.write(e.max_hp as u64);
+    self.write(e.inventory_size as u64);
+    self.write_materials(&e.materials);
+    self.write(e.movement_type.clone() as u64);
+    for n in [
+      e.steps,
+      e.rest,
+      e.gun_damage,
+      e.drill_damage,
+      e.sight,
+      e.armour,
+    ] {
+      self.write(n as u64);
+    }
+    self.write(e.can_repair as u64);
+    self.write(e.can_upgrade as u64);
+    self.write_action(&e.last_action);
+    match &e.brain {
+      None => self.write(0),
+      Some(full) => {
+        self.write(1);
+        self.write_bytes(&full.half);
+        self.write_bytes(full.code_name.as_bytes());
+        self.write(full.gas as u64);
+      }
+    }
+    self.write(e.moves as u64);
+    self.write(e.resting as u64);
+  }
+  // variants are numbered in the order they are declared, then come their
+  // fields
+  fn write_action(&mut self, action: &Action) {
+    match action {
+      Action::Wait => self.write(0),
+      Action::Move(dir) => {
+        self.write(1);
+        self.write(*dir as u64);
+      }
+      Action::GetMaterials(neigh, m) => {
+        self.write(2);
+        self.write(*neigh as u64);
+        self.write_materials(m);
+      }
+      Action::DropMaterials(neigh, m) => {
+        self.write(3);
+        self.write(*neigh as u64);
+        self.write_materials(m);
+      }
+      Action::Shoot(disp) => {
+        self.write(4);
+        self.write(disp.x as u64);
+        self.write(disp.y as u64);
+      }
+      Action::Drill(dir) => {
+        self.write(5);
+        self.write(*dir as u64);
+      }
+      Action::Construct(template, dir) => {
+        self.write(6);
+        self.write(*template as u64);
+        self.write(*dir as u64);
+      }
+      Action::SetMessage(message) => {
+        self.write(7);
+        self.write(message.emotion as u64);
+        self.write(message.pos.x as u64);
+        self.write(message.pos.y as u64);
+      }
+      Action::Repair(neigh) => {
+        self.write(8);
+        self.write(*neigh as u64);
+      }
+      Action::Upgrade(stat) => {
+        self.write(9);
+        self.write(*stat as u64);
+      }
+      Action::Give(neigh, m) => {
+        self.write(10);
+        self.write(*neigh as u64);
+        self.write_materials(m);
+      }
+    }
+  }
+}
+
+// what differs between two states of the same board
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StateDiff {
+  pub tiles: Vec<Pos>,
+  // entities that are in only one of them or differ in any field
+  pub entities: Vec<Id>,
+  // teams whose tokens differ
+  pub tokens: Vec<usize>,
+}
+
+impl StateDiff {
+  pub fn is_empty(&self) -> bool {
+    self.tiles.is_empty() && self.entities.is_empty() && self.tokens.is_empty()
+  }
+}
+
+pub fn diff(a: &State, b: &State) -> StateDiff {
+  let tiles = a
+    .board
+    .iter()
+    .filter(|pos| a.tiles.get(a.board.index(*pos)) != b.tiles.get(b.board.index(*pos)))
+    .collect();
+  let mut entities: Vec<Id> = a
+    .entities
+    .keys()
+    .chain(b.entities.keys().filter(|id| !a.entities.contains_key(id)))
+    .copied()
+    .filter(|id| {
+      // entities have no PartialEq, their json is compared instead
+      let a = a.entities.get(id).map(|e| serde_json::to_value(e).unwrap());
+      let b = b.entities.get(id).map(|e| serde_json::to_value(e).unwrap());
+      a != b
+    })
+    .collect();
+  entities.sort();
+  let teams = max(a.tokens.len(), b.tokens.len());
+  let tokens = (0..teams)
+    .filter(|t| a.tokens.get(*t) != b.tokens.get(*t))
+    .collect();
+  StateDiff {
+    tiles,
+    entities,
+    tokens,
+  }
 }
 
 pub type Frame = Vec<Command>;
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Log {
+  pub entity_id: Id,
+  pub message: String,
+}
+
+// what happens to a bot once it reaches the maximum number of faults
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FaultAction {
+  Nothing,
+  // the entity stays on the board, but only waits from then on
+  Disable,
+  // the entity is removed at the end of the turn
+  Kill,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FaultKind {
+  NoExecute,
+  Trap { message: String },
+  InvalidReturn,
+  InvalidOpcode { opcode: i64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Fault {
+  pub entity_id: Id,
+  pub kind: FaultKind,
+  // faults of this entity so far, including this one
+  pub count: usize,
+  pub action: FaultAction,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Script {
   pub genesis: State,
   pub frames: Vec<Frame>,
+  // debug messages printed by the bots, one vector per frame
+  #[serde(default)]
+  pub logs: Vec<Vec<Log>>,
+  // bot faults, one vector per frame
+  #[serde(default)]
+  pub faults: Vec<Vec<Fault>>,
+  // events, one vector per frame
+  #[serde(default)]
+  pub events: Vec<Vec<Event>>,
+  // State::hash after each frame, scripts saved before hashing have none
+  #[serde(default)]
+  pub hashes: Vec<u64>,
 }
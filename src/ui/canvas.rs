@@ -2,21 +2,22 @@ use ::rand::prelude::*;
 use macroquad::prelude::*;
 use rand_chacha::ChaCha8Rng;
 
-use crate::state::constants::{HEIGHT, WIDTH};
 use crate::state::entity::{Action, ActiveEntity, MixTemplate, MovementType, Team};
-use crate::state::geometry::{board_iterator, Pos};
+use crate::state::geometry::{Board, Pos};
 use crate::state::materials::Materials;
-use crate::state::state::{State, Tile};
+use crate::state::neutral::Neutral;
+use crate::state::state::{State, Terrain, Tile};
 
 // TODO: Factor this code
 pub async fn draw_materials(
   mat: Materials,
   h_displace: f32,
   v_displace: f32,
+  board: Board,
   pos: Pos,
   tileset: &Texture2D,
 ) {
-  let mut rng = ChaCha8Rng::seed_from_u64((pos.x * HEIGHT + pos.y).try_into().unwrap());
+  let mut rng = ChaCha8Rng::seed_from_u64((pos.x * board.height + pos.y).try_into().unwrap());
   for _ in 0..mat.carbon {
     let draw_params = DrawTextureParams {
       source: Some(Rect {
@@ -30,7 +31,7 @@ pub async fn draw_materials(
     draw_texture_ex(
       *tileset,
       h_displace + ((16 * pos.x) + rng.gen_range(0..13)) as f32,
-      v_displace + ((16 * (HEIGHT.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
+      v_displace + ((16 * (board.height.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
       WHITE,
       draw_params,
     );
@@ -48,7 +49,7 @@ pub async fn draw_materials(
     draw_texture_ex(
       *tileset,
       h_displace + ((16 * pos.x) + rng.gen_range(0..13)) as f32,
-      v_displace + ((16 * (HEIGHT.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
+      v_displace + ((16 * (board.height.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
       WHITE,
       draw_params,
     );
@@ -66,7 +67,7 @@ pub async fn draw_materials(
     draw_texture_ex(
       *tileset,
       h_displace + ((16 * pos.x) + rng.gen_range(0..13)) as f32,
-      v_displace + ((16 * (HEIGHT.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
+      v_displace + ((16 * (board.height.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
       WHITE,
       draw_params,
     );
@@ -84,18 +85,18 @@ pub async fn draw_materials(
     draw_texture_ex(
       *tileset,
       h_displace + ((16 * pos.x) + rng.gen_range(0..13)) as f32,
-      v_displace + ((16 * (HEIGHT.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
+      v_displace + ((16 * (board.height.saturating_sub(pos.y + 1))) + rng.gen_range(0..13)) as f32,
       WHITE,
       draw_params,
     );
   }
 }
 
-async fn draw_command(entity: &ActiveEntity, h_displace: f32, v_displace: f32) {
+async fn draw_command(entity: &ActiveEntity, h_displace: f32, v_displace: f32, board: Board) {
   match entity.last_action.clone() {
     Action::Shoot(disp) => {
       let from = entity.pos;
-      let to = State::add_displace(from, &disp).unwrap();
+      let to = State::add_displace(board, from, &disp).unwrap();
       let damage = entity.get_gun_damage();
       draw_line(
         h_displace + (16 * from.x) as f32 + 8.0,
@@ -114,7 +115,7 @@ async fn draw_command(entity: &ActiveEntity, h_displace: f32, v_displace: f32) {
     }
     Action::Drill(dir) => {
       let from = entity.pos;
-      let to = State::add_displace(from, &dir.into()).unwrap();
+      let to = State::add_displace(board, from, &dir.into()).unwrap();
       let damage = entity.get_gun_damage();
       draw_line(
         h_displace + (16 * from.x) as f32 + 8.0,
@@ -131,23 +132,80 @@ async fn draw_command(entity: &ActiveEntity, h_displace: f32, v_displace: f32) {
         BLUE,
       );
     }
+    Action::Repair(neigh) => {
+      let from = entity.pos;
+      let to = State::add_displace(board, from, &neigh.into()).unwrap();
+      draw_line(
+        h_displace + (16 * from.x) as f32 + 8.0,
+        v_displace + (16 * from.y) as f32 + 8.0,
+        h_displace + (16 * to.x) as f32 + 8.0,
+        v_displace + (16 * to.y) as f32 + 8.0,
+        3.0,
+        GREEN,
+      );
+      draw_circle(
+        h_displace + (16 * to.x) as f32 + 8.0,
+        v_displace + (16 * to.y) as f32 + 8.0,
+        6.0,
+        GREEN,
+      );
+    }
+    Action::Give(neigh, _) => {
+      let from = entity.pos;
+      let to = State::add_displace(board, from, &neigh.into()).unwrap();
+      draw_line(
+        h_displace + (16 * from.x) as f32 + 8.0,
+        v_displace + (16 * from.y) as f32 + 8.0,
+        h_displace + (16 * to.x) as f32 + 8.0,
+        v_displace + (16 * to.y) as f32 + 8.0,
+        3.0,
+        ORANGE,
+      );
+    }
+    Action::Upgrade(_) => {
+      draw_circle_lines(
+        h_displace + (16 * entity.pos.x) as f32 + 8.0,
+        v_displace + (16 * entity.pos.y) as f32 + 8.0,
+        10.0,
+        2.0,
+        GOLD,
+      );
+    }
     _ => {}
   }
 }
 
+// row of the tileset and tint for the bots of a team, neutral ones are grey
+fn team_sprite(team: Option<Team>) -> (f32, Color) {
+  match team.map(|t| t.0) {
+    None => (0.0, GRAY),
+    Some(0) => (16.0, WHITE),
+    Some(1) => (32.0, WHITE),
+    Some(2) => (0.0, GREEN),
+    Some(_) => (0.0, YELLOW),
+  }
+}
+
+pub fn team_name(team: Team) -> &'static str {
+  match team.0 {
+    0 => "Blue",
+    1 => "Red",
+    2 => "Green",
+    _ => "Yellow",
+  }
+}
+
 pub async fn draw_entity(
   entity: Option<&ActiveEntity>,
   h_displace: f32,
   v_displace: f32,
+  board: Board,
   pos: Pos,
   tileset: &Texture2D,
 ) {
   if let Some(e) = entity {
     let x = get_texture_x(&e);
-    let y = match e.team {
-      Team::Blue => 16.0,
-      Team::Red => 32.0,
-    };
+    let (y, tint) = team_sprite(e.team);
     let draw_params = DrawTextureParams {
       source: Some(Rect {
         x,
@@ -160,20 +218,20 @@ pub async fn draw_entity(
     draw_texture_ex(
       *tileset,
       h_displace + (pos.x as f32) * 16.,
-      v_displace + ((HEIGHT.saturating_sub(pos.y + 1)) as f32) * 16.,
-      WHITE,
+      v_displace + ((board.height.saturating_sub(pos.y + 1)) as f32) * 16.,
+      tint,
       draw_params,
     );
     if e.tokens > 0 {
       draw_rectangle(
         h_displace + (pos.x as f32) * 16.,
-        v_displace + ((HEIGHT.saturating_sub(pos.y + 1)) as f32) * 16.,
+        v_displace + ((board.height.saturating_sub(pos.y + 1)) as f32) * 16.,
         2.0,
         2.0,
         LIGHTGRAY,
       );
     }
-    draw_command(e, h_displace, v_displace).await;
+    draw_command(e, h_displace, v_displace, board).await;
   }
 }
 
@@ -181,15 +239,13 @@ pub async fn draw_template_at(
   entity: &MixTemplate,
   h_displace: f32,
   v_displace: f32,
+  board: Board,
   pos: Pos,
   team: Team,
   tileset: &Texture2D,
 ) {
   let x = get_texture_template(&entity);
-  let y = match team {
-    Team::Blue => 16.0,
-    Team::Red => 32.0,
-  };
+  let (y, tint) = team_sprite(Some(team));
   let draw_params = DrawTextureParams {
     source: Some(Rect {
       x,
@@ -202,14 +258,14 @@ pub async fn draw_template_at(
   draw_texture_ex(
     *tileset,
     h_displace + (pos.x as f32) * 16.,
-    v_displace + ((HEIGHT.saturating_sub(pos.y + 1)) as f32) * 16.,
-    WHITE,
+    v_displace + ((board.height.saturating_sub(pos.y + 1)) as f32) * 16.,
+    tint,
     draw_params,
   );
   if entity.tokens > 0 {
     draw_rectangle(
       h_displace + (pos.x as f32) * 16.,
-      v_displace + ((HEIGHT.saturating_sub(pos.y + 1)) as f32) * 16.,
+      v_displace + ((board.height.saturating_sub(pos.y + 1)) as f32) * 16.,
       2.0,
       2.0,
       LIGHTGRAY,
@@ -221,15 +277,13 @@ pub async fn draw_active_entity(
   entity: Option<&ActiveEntity>,
   h_displace: f32,
   v_displace: f32,
+  board: Board,
   pos: Pos,
   tileset: &Texture2D,
 ) {
   if let Some(e) = entity {
     let x = get_texture_active_x(&e);
-    let y = match e.team {
-      Team::Blue => 16.0,
-      Team::Red => 32.0,
-    };
+    let (y, tint) = team_sprite(e.team);
     let draw_params = DrawTextureParams {
       source: Some(Rect {
         x,
@@ -242,14 +296,14 @@ pub async fn draw_active_entity(
     draw_texture_ex(
       *tileset,
       h_displace + (pos.x as f32) * 16.,
-      v_displace + ((HEIGHT.saturating_sub(pos.y + 1)) as f32) * 16.,
-      WHITE,
+      v_displace + ((board.height.saturating_sub(pos.y + 1)) as f32) * 16.,
+      tint,
       draw_params,
     );
     if e.tokens > 0 {
       draw_rectangle(
         h_displace + (pos.x as f32) * 16.,
-        v_displace + ((HEIGHT.saturating_sub(pos.y + 1)) as f32) * 16.,
+        v_displace + ((board.height.saturating_sub(pos.y + 1)) as f32) * 16.,
         2.0,
         2.0,
         LIGHTGRAY,
@@ -258,9 +312,35 @@ pub async fn draw_active_entity(
   }
 }
 
+// draws the neutral entities of a level, which are placed in the first
+// region, in every region of the board
+pub async fn draw_neutrals(
+  neutrals: &[(Pos, Neutral)],
+  h_displace: f32,
+  v_displace: f32,
+  board: Board,
+  tileset: &Texture2D,
+) {
+  for (pos, neutral) in neutrals.iter() {
+    for team in (0..board.teams()).map(Team) {
+      let board_pos = board.from_team(team, *pos);
+      let entity = neutral.template().upgrade(0, None, board_pos);
+      draw_active_entity(
+        Some(&entity),
+        h_displace,
+        v_displace,
+        board,
+        board_pos,
+        tileset,
+      )
+      .await;
+    }
+  }
+}
+
 fn get_texture_x(e: &ActiveEntity) -> f32 {
   let inventory = e.inventory_size;
-  let can_walk = e.movement_type == MovementType::Walk;
+  let can_walk = e.movement_type != MovementType::Still;
   let can_drill = e.drill_damage > 0;
   let can_shoot = e.gun_damage > 0;
   match (inventory, can_walk, can_drill, can_shoot) {
@@ -276,7 +356,7 @@ fn get_texture_x(e: &ActiveEntity) -> f32 {
 
 fn get_texture_template(e: &MixTemplate) -> f32 {
   let inventory = e.inventory_size;
-  let can_walk = e.movement_type == MovementType::Walk;
+  let can_walk = e.movement_type != MovementType::Still;
   let can_drill = e.drill_damage > 0;
   let can_shoot = e.gun_damage > 0;
   match (inventory, can_walk, can_drill, can_shoot) {
@@ -292,7 +372,7 @@ fn get_texture_template(e: &MixTemplate) -> f32 {
 
 fn get_texture_active_x(e: &ActiveEntity) -> f32 {
   let inventory = e.inventory_size;
-  let can_walk = e.movement_type == MovementType::Walk;
+  let can_walk = e.movement_type != MovementType::Still;
   let can_drill = e.drill_damage > 0;
   let can_shoot = e.gun_damage > 0;
   match (inventory, can_walk, can_drill, can_shoot) {
@@ -306,15 +386,22 @@ fn get_texture_active_x(e: &ActiveEntity) -> f32 {
   }
 }
 
+// random floor textures for a board
+pub fn build_floor(board: Board) -> Vec<usize> {
+  let mut rng: ChaCha8Rng = ChaCha8Rng::seed_from_u64(25);
+  (0..board.size()).map(|_| rng.gen_range(0..7)).collect()
+}
+
 pub async fn draw_floor(
   h_displace: f32,
   v_displace: f32,
   tileset: &Texture2D,
-  floor: &[usize; WIDTH * HEIGHT],
+  board: Board,
+  floor: &Vec<usize>,
 ) {
-  for i in 0..HEIGHT {
-    for j in 0..WIDTH {
-      let f = floor[i * WIDTH + j];
+  for i in 0..board.height {
+    for j in 0..board.width {
+      let f = floor[i * board.width + j];
       let draw_params = DrawTextureParams {
         source: Some(Rect {
           x: 16.0 * (f as f32),
@@ -335,17 +422,56 @@ pub async fn draw_floor(
   }
 }
 
+pub async fn draw_terrain(
+  terrain: Terrain,
+  h_displace: f32,
+  v_displace: f32,
+  board: Board,
+  pos: Pos,
+  wall: &Texture2D,
+) {
+  let x = h_displace + (16 * pos.x) as f32;
+  let y = v_displace + (16 * (board.height - pos.y - 1)) as f32;
+  match terrain {
+    Terrain::Floor => {}
+    Terrain::Wall => draw_texture(*wall, x, y, WHITE),
+    Terrain::Water => draw_rectangle(x, y, 16.0, 16.0, Color::new(0.2, 0.4, 0.9, 0.7)),
+  }
+}
+
+pub async fn draw_terrain_map(
+  tiles: &Vec<Tile>,
+  h_displace: f32,
+  v_displace: f32,
+  board: Board,
+  wall: &Texture2D,
+) {
+  for pos in board.iter() {
+    draw_terrain(
+      tiles[board.index(pos)].terrain,
+      h_displace,
+      v_displace,
+      board,
+      pos,
+      wall,
+    )
+    .await;
+  }
+}
+
 pub async fn draw_mat_map(
   tiles: &Vec<Tile>,
   h_displace: f32,
   v_displace: f32,
+  board: Board,
   tileset: &Texture2D,
 ) {
-  for pos in board_iterator() {
+  for pos in board.iter() {
     draw_materials(
-      tiles[pos.to_index()].materials.clone(),
+      tiles[board.index(pos)].materials.clone(),
       h_displace,
       v_displace,
+      board,
       pos,
       &tileset,
     )
@@ -354,14 +480,38 @@ pub async fn draw_mat_map(
 }
 
 pub async fn draw_entity_map(state: &State, h_displace: f32, v_displace: f32, tileset: &Texture2D) {
-  for pos in board_iterator() {
+  for pos in state.board.iter() {
     draw_active_entity(
       state.get_entity_option(pos),
       h_displace,
       v_displace,
+      state.board,
       pos,
       &tileset,
     )
     .await;
   }
 }
+
+pub fn draw_grid(h_displace: f32, v_displace: f32, board: Board, color: Color) {
+  for i in 0..=board.width {
+    draw_line(
+      h_displace + (i as f32) * 16.0,
+      v_displace,
+      h_displace + (i as f32) * 16.0,
+      v_displace + (board.height as f32) * 16.0,
+      1.0,
+      color,
+    );
+  }
+  for j in 0..=board.height {
+    draw_line(
+      h_displace,
+      v_displace + (j as f32) * 16.0,
+      h_displace + (board.width as f32) * 16.0,
+      v_displace + (j as f32) * 16.0,
+      1.0,
+      color,
+    );
+  }
+}
@@ -12,6 +12,7 @@ use super::ui::{
   build_incrementer, plus_minus, split, trim_margins, Button, ButtonPanel, Input, Rect, Sign, Ui,
 };
 use crate::state::bf::EntityState;
+use crate::state::config::{bot_file_name, config};
 //use crate::state::brain::get_code_vec;
 use crate::state::constants::NUM_TEMPLATES;
 use crate::state::entity::{Full, Mix, MixTemplate, MovementType};
@@ -29,8 +30,12 @@ macro_rules! skip_fail {
 }
 
 pub fn get_code_vec() -> HashMap<String, usize> {
-  let wasm_dir = Path::new("./target/wasm32-unknown-unknown/release");
-  assert!(wasm_dir.is_dir());
+  let wasm_dir = &config().bots_dir();
+  assert!(
+    wasm_dir.is_dir(),
+    "no bots in {}, run compile_bots.sh",
+    wasm_dir.display()
+  );
   let mut code_vec = HashMap::<String, usize>::new();
   let mut i = 0;
   for entry in fs::read_dir(wasm_dir).unwrap() {
@@ -42,7 +47,7 @@ pub fn get_code_vec() -> HashMap<String, usize> {
       let extension = Path::new(&path).extension();
       if let Some(ext) = extension {
         if ext == "wasm" {
-          code_vec.insert(path.into_os_string().into_string().unwrap(), i);
+          code_vec.insert(bot_file_name(&path.to_string_lossy()).to_string(), i);
           i += 1;
         }
       }
@@ -76,7 +81,13 @@ pub enum Attribute {
   Gas,
   GunDamage,
   DrillDamage,
-  Speed,
+  Movement,
+  Steps,
+  Rest,
+  Sight,
+  Repair,
+  Upgrade,
+  Armour,
 }
 
 #[derive(Clone, Debug)]
@@ -126,9 +137,14 @@ impl EntityEdit {
       vec![0.0, 1.0],
       vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0],
     );
+    let fifth_row_rects: Vec<Rect> =
+      split(&rects[4], vec![0.0, 0.25, 0.5, 0.75, 1.0], vec![0.0, 1.0])
+        .into_iter()
+        .map(|r| trim_margins(r, 0.1, 0.1, 0.1, 0.1))
+        .collect();
     let mut panel = ButtonPanel::new(self.rect.clone(), (vec![], vec![], vec![], vec![], vec![]));
     panel.push(Button::<Command>::new(
-      trim_margins(rects[4].clone(), 0.1, 0.1, 0.1, 0.1),
+      fifth_row_rects[3].clone(),
       ("Exit".to_string(), Command::Exit, true, false),
     ));
     match &self.entity {
@@ -194,35 +210,81 @@ impl EntityEdit {
           Command::PM(Attribute::Copper, Sign::Minus),
         ));
 
-        let third_row_rects: Vec<Rect> =
-          split(&rects[2], vec![0.0, 0.25, 0.5, 0.75, 1.0], vec![0.0, 1.0])
-            .into_iter()
-            .map(|r| trim_margins(r, 0.1, 0.1, 0.1, 0.1))
-            .collect();
+        let third_row_rects: Vec<Rect> = split(
+          &rects[2],
+          vec![0.0, 1.0 / 6.0, 2.0 / 6.0, 0.5, 4.0 / 6.0, 5.0 / 6.0, 1.0],
+          vec![0.0, 1.0],
+        )
+        .into_iter()
+        .map(|r| trim_margins(r, 0.1, 0.1, 0.1, 0.1))
+        .collect();
         panel.append(&mut build_incrementer::<Command>(
           &third_row_rects[0],
-          "Speed".to_string(),
+          "Movement".to_string(),
           match e.movement_type {
             MovementType::Still => 0,
             MovementType::Walk => 1,
+            MovementType::Fly => 2,
           },
-          Command::PM(Attribute::Speed, Sign::Plus),
-          Command::PM(Attribute::Speed, Sign::Minus),
+          Command::PM(Attribute::Movement, Sign::Plus),
+          Command::PM(Attribute::Movement, Sign::Minus),
         ));
         panel.append(&mut build_incrementer::<Command>(
           &third_row_rects[1],
+          "Steps".to_string(),
+          e.steps,
+          Command::PM(Attribute::Steps, Sign::Plus),
+          Command::PM(Attribute::Steps, Sign::Minus),
+        ));
+        panel.append(&mut build_incrementer::<Command>(
+          &third_row_rects[2],
+          "Rest".to_string(),
+          e.rest,
+          Command::PM(Attribute::Rest, Sign::Plus),
+          Command::PM(Attribute::Rest, Sign::Minus),
+        ));
+        panel.append(&mut build_incrementer::<Command>(
+          &third_row_rects[3],
           "Gun damage".to_string(),
           e.gun_damage,
           Command::PM(Attribute::GunDamage, Sign::Plus),
           Command::PM(Attribute::GunDamage, Sign::Minus),
         ));
         panel.append(&mut build_incrementer::<Command>(
-          &third_row_rects[2],
+          &third_row_rects[4],
           "Drill damage".to_string(),
           e.drill_damage,
           Command::PM(Attribute::DrillDamage, Sign::Plus),
           Command::PM(Attribute::DrillDamage, Sign::Minus),
         ));
+        panel.append(&mut build_incrementer::<Command>(
+          &third_row_rects[5],
+          "Sight".to_string(),
+          e.sight,
+          Command::PM(Attribute::Sight, Sign::Plus),
+          Command::PM(Attribute::Sight, Sign::Minus),
+        ));
+        panel.append(&mut build_incrementer::<Command>(
+          &fifth_row_rects[0],
+          "Repair".to_string(),
+          e.can_repair as usize,
+          Command::PM(Attribute::Repair, Sign::Plus),
+          Command::PM(Attribute::Repair, Sign::Minus),
+        ));
+        panel.append(&mut build_incrementer::<Command>(
+          &fifth_row_rects[1],
+          "Upgrade".to_string(),
+          e.can_upgrade as usize,
+          Command::PM(Attribute::Upgrade, Sign::Plus),
+          Command::PM(Attribute::Upgrade, Sign::Minus),
+        ));
+        panel.append(&mut build_incrementer::<Command>(
+          &fifth_row_rects[2],
+          "Armour".to_string(),
+          e.armour,
+          Command::PM(Attribute::Armour, Sign::Plus),
+          Command::PM(Attribute::Armour, Sign::Minus),
+        ));
         let fourth_row_rects: Vec<Rect> =
           split(&rects[3], vec![0.0, 0.25, 0.5, 0.75, 1.0], vec![0.0, 1.0])
             .into_iter()
@@ -280,7 +342,7 @@ impl EntityEdit {
             panel.append(&mut build_incrementer::<Command>(
               &fourth_row_rects[2],
               "Code".to_string(),
-              *self.code_index.get(&n.clone()).unwrap(),
+              *self.code_index.get(bot_file_name(n)).unwrap(),
               Command::PM(Attribute::CodeID, Sign::Plus),
               Command::PM(Attribute::CodeID, Sign::Minus),
             ));
@@ -384,7 +446,7 @@ impl Ui for EntityEdit {
             }
             Attribute::CodeID => {
               if let Mix::Full(Full { code_name: n, .. }) = &mut mix.brain {
-                let code_index = *self.code_index.get(&n.clone()).unwrap();
+                let code_index = *self.code_index.get(bot_file_name(n)).unwrap();
                 let new_code = plus_minus(&input, code_index, sign);
                 let inverted_code_index: HashMap<usize, String> = self
                   .code_index
@@ -411,16 +473,41 @@ impl Ui for EntityEdit {
                 *g = plus_minus(&input, *g, sign);
               }
             }
-            Attribute::Speed => match sign {
-              Sign::Minus => mix.movement_type = MovementType::Still,
-              Sign::Plus => mix.movement_type = MovementType::Walk,
-            },
+            Attribute::Movement => {
+              mix.movement_type = match (&mix.movement_type, sign) {
+                (MovementType::Still, Sign::Plus) => MovementType::Walk,
+                (MovementType::Walk, Sign::Plus) => MovementType::Fly,
+                (MovementType::Fly, Sign::Minus) => MovementType::Walk,
+                (MovementType::Walk, Sign::Minus) => MovementType::Still,
+                (movement, _) => movement.clone(),
+              }
+            }
+            Attribute::Steps => {
+              mix.steps = max(plus_minus(&input, mix.steps, sign), 1);
+            }
+            Attribute::Rest => {
+              mix.rest = plus_minus(&input, mix.rest, sign);
+            }
             Attribute::GunDamage => {
               mix.gun_damage = plus_minus(&input, mix.gun_damage, sign);
             }
             Attribute::DrillDamage => {
               mix.drill_damage = plus_minus(&input, mix.drill_damage, sign);
             }
+            Attribute::Sight => {
+              mix.sight = plus_minus(&input, mix.sight, sign);
+            }
+            Attribute::Armour => {
+              mix.armour = plus_minus(&input, mix.armour, sign);
+            }
+            Attribute::Repair => match sign {
+              Sign::Minus => mix.can_repair = false,
+              Sign::Plus => mix.can_repair = true,
+            },
+            Attribute::Upgrade => match sign {
+              Sign::Minus => mix.can_upgrade = false,
+              Sign::Plus => mix.can_upgrade = true,
+            },
           };
         }
       }
@@ -4,19 +4,23 @@ extern crate rand_chacha;
 use async_trait::async_trait;
 use futures::executor::block_on;
 use macroquad::prelude::*;
-use rand::prelude::*;
-use rand_chacha::ChaCha8Rng;
 
-use super::canvas::{draw_floor, draw_mat_map, draw_materials, draw_template_at};
+use super::canvas::{
+  build_floor, draw_floor, draw_grid, draw_mat_map, draw_materials, draw_neutrals,
+  draw_template_at, draw_terrain_map, team_name,
+};
 use super::new_bf::NewBF;
 use super::ui::{
   build_incrementer, plus_minus, split, trim_margins, Button, ButtonPanel, Input, Rect, Sign, Ui,
 };
 use super::view::{View, ViewState};
-use crate::state::bf::{join_tiles, load_level_file, load_squad_file, BFState, EntityState};
-use crate::state::constants::{HEIGHT, WIDTH};
+use crate::state::bf::{
+  join_tiles, load_level_file, load_squad_file, validate, BFState, EntityState, Metadata,
+};
+use crate::state::config::config;
+use crate::state::constants::MAX_BOARD_SIZE;
 use crate::state::entity::Team;
-use crate::state::geometry::{board_iterator, half_board_iterator};
+use crate::state::geometry::Board;
 use crate::state::state::Tile;
 
 const SMOKE: macroquad::color::Color = Color::new(0.0, 0.0, 0.0, 0.3);
@@ -37,10 +41,14 @@ pub enum Command {
 #[derive(Clone, Debug)]
 pub struct BattleParams {
   level: usize,
-  blue_index: usize,
-  red_index: usize,
-  blue_squad: BFState,
-  red_squad: BFState,
+  // indexed by team
+  indices: Vec<usize>,
+  squads: Vec<BFState>,
+  // why the squad at the index could not be loaded, the previous one is
+  // still shown
+  broken: Vec<Option<String>>,
+  // why the last battle could not start, one line each
+  problems: Vec<String>,
   joined_tiles: Vec<Tile>,
 }
 
@@ -56,6 +64,8 @@ pub struct ShowingDetails {
 pub enum LoadBFState {
   NoFiles,
   Showing(ShowingDetails),
+  // a level file that exists but could not be loaded
+  Broken { level: usize, reason: String },
   SelectingSquads(BattleParams),
   NewSquad(NewBF),
   View(View),
@@ -64,11 +74,42 @@ pub enum LoadBFState {
 pub struct LoadBF {
   rect: Rect,
   state: LoadBFState,
-  floor: [usize; WIDTH * HEIGHT],
+  floor: Vec<usize>,
   tileset: Texture2D,
+  wall: Texture2D,
   panel: ButtonPanel<Command>,
 }
 
+// what the browser shows for level n, None if there is no such file
+fn browse_level(n: usize) -> Option<LoadBFState> {
+  match load_level_file(config(), n) {
+    Ok(state) => Some(LoadBFState::Showing(ShowingDetails {
+      level: n,
+      level_state: state.clone(),
+      has_squads: load_squad_file(config(), n, 0).map_or_else(|e| !e.is_missing(), |_| true),
+      joined_tiles: state.mirrored_tiles(),
+    })),
+    Err(e) if e.is_missing() => None,
+    Err(e) => Some(LoadBFState::Broken {
+      level: n,
+      reason: e.to_string(),
+    }),
+  }
+}
+
+// the details of a level or squad, one line each, starting at y
+fn draw_metadata(metadata: &Metadata, x: f32, y: f32) {
+  let mut lines = vec![metadata.headline()];
+  lines.extend(metadata.created_date());
+  if !metadata.tags.is_empty() {
+    lines.push(format!("Tags: {}", metadata.tags.join(", ")));
+  }
+  lines.extend(metadata.description.lines().map(|l| l.to_string()));
+  for (i, line) in lines.iter().enumerate() {
+    draw_text(line, x, y + 24.0 * (i as f32), 24.0, DARKGREEN);
+  }
+}
+
 impl LoadBF {
   fn build_panel(&self, rect: &Rect) -> ButtonPanel<Command> {
     let mut panel: ButtonPanel<Command> =
@@ -85,6 +126,20 @@ impl LoadBF {
           ("No levels".to_string(), Command::Exit, false, false),
         ));
       }
+      LoadBFState::Broken { level, .. } => {
+        let rects: Vec<Rect> = split(rect, vec![0.0, 0.5], vec![0.0, 0.3, 0.45]);
+        panel.append(&mut build_incrementer::<Command>(
+          &rects[0],
+          "Level".to_string(),
+          *level,
+          Command::ChangeBF(Sign::Plus),
+          Command::ChangeBF(Sign::Minus),
+        ));
+        panel.push(Button::<Command>::new(
+          trim_margins(rects[1].clone(), 0.1, 0.1, 0.1, 0.1),
+          ("Broken".to_string(), Command::Exit, false, false),
+        ));
+      }
       LoadBFState::Showing(ShowingDetails {
         level: s,
         level_state: _,
@@ -128,26 +183,17 @@ impl LoadBF {
           ),
         ));
       }
-      LoadBFState::SelectingSquads(BattleParams {
-        blue_index,
-        red_index,
-        ..
-      }) => {
-        let rects: Vec<Rect> = split(rect, vec![0.0, 0.5, 1.0], vec![0.0, 0.3]);
-        panel.append(&mut build_incrementer::<Command>(
-          &rects[0],
-          "Blue Squad".to_string(),
-          *blue_index,
-          Command::ChangeSquad(Team::Blue, Sign::Plus),
-          Command::ChangeSquad(Team::Blue, Sign::Minus),
-        ));
-        panel.append(&mut build_incrementer::<Command>(
-          &rects[1],
-          "Red Squad".to_string(),
-          *red_index,
-          Command::ChangeSquad(Team::Red, Sign::Plus),
-          Command::ChangeSquad(Team::Red, Sign::Minus),
-        ));
+      LoadBFState::SelectingSquads(BattleParams { indices, .. }) => {
+        let rects: Vec<Rect> = split(rect, vec![0.0, 0.5, 1.0], vec![0.0, 0.3, 0.6]);
+        for (team, index) in indices.iter().enumerate() {
+          panel.append(&mut build_incrementer::<Command>(
+            &rects[team],
+            format!("{} Squad", team_name(Team(team))),
+            *index,
+            Command::ChangeSquad(Team(team), Sign::Plus),
+            Command::ChangeSquad(Team(team), Sign::Minus),
+          ));
+        }
         panel.push(Button::<Command>::new(
           trim_margins(self.rect.clone(), 0.6, 0.3, 0.1, 0.7),
           ("Start Battle".to_string(), Command::Start, true, false),
@@ -173,13 +219,12 @@ impl LoadBF {
     );
     let rects: Vec<Rect> = split(&left_rect, vec![0.0, 0.6, 1.0], vec![0.0, 0.8, 1.0]);
     if let LoadBFState::SelectingSquads(BattleParams {
-      blue_squad,
-      red_squad,
+      squads,
       joined_tiles,
       ..
     }) = &mut self.state
     {
-      *joined_tiles = join_tiles(blue_squad, red_squad);
+      *joined_tiles = join_tiles(&squads.iter().collect::<Vec<&BFState>>());
     }
     self.panel = self.build_panel(&rects[0]);
   }
@@ -191,24 +236,14 @@ impl Ui for LoadBF {
   type Builder = ();
 
   fn new(rect: Rect, _: ()) -> Self {
-    let tileset = block_on(load_texture("assets/tileset.png")).unwrap();
-    let mut rng: ChaCha8Rng = ChaCha8Rng::seed_from_u64(25).try_into().unwrap();
-    let mut floor = [0; WIDTH * HEIGHT];
-    for i in 0..(WIDTH * HEIGHT) {
-      floor[i] = rng.gen_range(0..7);
-    }
+    let tileset = block_on(load_texture(&config().asset("tileset.png"))).unwrap();
+    let wall = block_on(load_texture(&config().asset("wall.png"))).unwrap();
+    // large enough for the board of any level
+    let floor = build_floor(Board::new(MAX_BOARD_SIZE, MAX_BOARD_SIZE));
     // find out if there exists file zero
     let mut load_bf = LoadBF {
       rect: rect.clone(),
-      state: match load_level_file(0) {
-        Some(state) => LoadBFState::Showing(ShowingDetails {
-          level: 0,
-          level_state: state.clone(),
-          has_squads: load_squad_file(0, 0).is_some(),
-          joined_tiles: join_tiles(&state, &state),
-        }),
-        None => LoadBFState::NoFiles,
-      },
+      state: browse_level(0).unwrap_or(LoadBFState::NoFiles),
       // state: LoadBFState::View(View::new(
       //   rect.clone(),
       //   ViewState {
@@ -223,6 +258,7 @@ impl Ui for LoadBF {
       // )),
       floor,
       tileset,
+      wall,
       panel: ButtonPanel::new(rect, (vec![], vec![], vec![], vec![], vec![])),
     };
     load_bf.update_main_panel();
@@ -236,108 +272,145 @@ impl Ui for LoadBF {
         joined_tiles: jt,
         ..
       }) => {
-        draw_floor(XDISPL, YDISPL, &self.tileset, &self.floor).await;
-        draw_mat_map(&jt, XDISPL, YDISPL, &self.tileset).await;
-        for pos in half_board_iterator() {
-          if let Some(id) = &bf_state.get_tiles()[pos.to_index()].entity_id {
+        let board = bf_state.get_board();
+        draw_floor(XDISPL, YDISPL, &self.tileset, board, &self.floor).await;
+        draw_terrain_map(jt, XDISPL, YDISPL, board, &self.wall).await;
+        draw_mat_map(&jt, XDISPL, YDISPL, board, &self.tileset).await;
+        for pos in board.region_iter() {
+          if let Some(id) = &bf_state.get_tiles()[board.region_index(pos)].entity_id {
             if let EntityState::Entity(e, _) = &bf_state.get_entities()[*id] {
               draw_template_at(
                 &e.clone().try_into().unwrap(),
                 XDISPL,
                 YDISPL,
+                board,
                 pos,
-                Team::Blue,
-                &self.tileset,
-              )
-              .await;
-              let f = e.clone();
-              draw_template_at(
-                &f.try_into().unwrap(),
-                XDISPL,
-                YDISPL,
-                pos.invert(),
-                Team::Red,
+                Team::BLUE,
                 &self.tileset,
               )
               .await;
+              for team in (1..board.teams()).map(Team) {
+                draw_template_at(
+                  &e.clone().try_into().unwrap(),
+                  XDISPL,
+                  YDISPL,
+                  board,
+                  board.from_team(team, pos),
+                  team,
+                  &self.tileset,
+                )
+                .await;
+              }
             }
           }
         }
-        for i in 0..=WIDTH {
-          draw_line(
-            XDISPL + (i as f32) * 16.0,
-            YDISPL,
-            XDISPL + (i as f32) * 16.0,
-            YDISPL + (60.0 * 16.0),
-            1.0,
-            SMOKE,
-          );
-          draw_line(
-            XDISPL,
-            YDISPL + (i as f32) * 16.0,
-            XDISPL + (60.0 * 16.0),
-            YDISPL + (i as f32) * 16.0,
-            1.0,
-            SMOKE,
-          );
-        }
+        draw_neutrals(
+          bf_state.get_neutrals(),
+          XDISPL,
+          YDISPL,
+          board,
+          &self.tileset,
+        )
+        .await;
+        draw_grid(XDISPL, YDISPL, board, SMOKE);
         self.panel.draw().await;
+        draw_metadata(
+          bf_state.get_metadata(),
+          self.rect.x + 0.05 * self.rect.w,
+          self.rect.y + 0.55 * self.rect.h,
+        );
       }
       LoadBFState::NoFiles => {
         self.panel.draw().await;
       }
+      LoadBFState::Broken { reason, .. } => {
+        self.panel.draw().await;
+        draw_text(reason, 20.0, screen_height() - 20.0, 20.0, RED);
+      }
       LoadBFState::SelectingSquads(BattleParams {
-        blue_squad,
-        red_squad,
+        indices,
+        squads,
+        broken,
+        problems,
         joined_tiles,
         ..
       }) => {
-        draw_floor(XDISPL, YDISPL, &self.tileset, &self.floor).await;
-        for pos in board_iterator() {
-          let tile = joined_tiles[pos.to_index()].clone();
+        let board = squads[0].get_board();
+        draw_floor(XDISPL, YDISPL, &self.tileset, board, &self.floor).await;
+        draw_terrain_map(joined_tiles, XDISPL, YDISPL, board, &self.wall).await;
+        for pos in board.iter() {
+          let tile = joined_tiles[board.index(pos)].clone();
           if let Some(id) = tile.entity_id {
-            let mut entity = if pos.y < HEIGHT / 2 {
-              red_squad.get_entities()[id].clone()
-            } else {
-              blue_squad.get_entities()[id].clone()
-            };
-            draw_materials(tile.materials.clone(), XDISPL, YDISPL, pos, &self.tileset).await;
+            let team = board.team_at(pos);
+            let mut entity = squads[team.0].get_entities()[id].clone();
+            draw_materials(
+              tile.materials.clone(),
+              XDISPL,
+              YDISPL,
+              board,
+              pos,
+              &self.tileset,
+            )
+            .await;
             if let EntityState::Entity(e, _) = &mut entity {
               draw_template_at(
                 &e.clone().try_into().unwrap(),
                 XDISPL,
                 YDISPL,
+                board,
                 pos,
-                if pos.is_bottom() {
-                  Team::Blue
-                } else {
-                  Team::Red
-                },
+                team,
                 &self.tileset,
               )
               .await;
             }
           }
         }
-        for i in 0..=WIDTH {
-          draw_line(
-            XDISPL + (i as f32) * 16.0,
-            YDISPL,
-            XDISPL + (i as f32) * 16.0,
-            YDISPL + (60.0 * 16.0),
-            1.0,
-            SMOKE,
-          );
-          draw_line(
-            XDISPL,
-            YDISPL + (i as f32) * 16.0,
-            XDISPL + (60.0 * 16.0),
-            YDISPL + (i as f32) * 16.0,
-            1.0,
-            SMOKE,
+        draw_neutrals(
+          squads[0].get_neutrals(),
+          XDISPL,
+          YDISPL,
+          board,
+          &self.tileset,
+        )
+        .await;
+        draw_grid(XDISPL, YDISPL, board, SMOKE);
+        self.panel.draw().await;
+        for (team, squad) in squads.iter().enumerate() {
+          // broken squads are explained below instead
+          if broken[team].is_some() {
+            continue;
+          }
+          draw_text(
+            &format!(
+              "{} Squad {:05}: {}",
+              team_name(Team(team)),
+              indices[team],
+              squad.get_metadata().headline()
+            ),
+            self.rect.x + 0.05 * self.rect.w,
+            self.rect.y + 0.75 * self.rect.h + 24.0 * (team as f32),
+            24.0,
+            DARKGREEN,
           );
         }
-        self.panel.draw().await;
+        for (team, reason) in broken.iter().enumerate() {
+          if let Some(reason) = reason {
+            let line = (broken.len() - team) as f32;
+            draw_text(
+              &format!("{} Squad: {}", team_name(Team(team)), reason),
+              20.0,
+              screen_height() - 20.0 * line,
+              20.0,
+              RED,
+            );
+          }
+        }
+        // above the broken squads, which keep the battle from starting
+        for (i, problem) in problems.iter().enumerate() {
+          let line = (problems.len() + broken.len() - i) as f32;
+          draw_text(problem, 20.0, screen_height() - 20.0 * line, 20.0, RED);
+        }
       }
       LoadBFState::View(v) => v.draw().await,
       LoadBFState::NewSquad(n) => {
@@ -352,24 +425,19 @@ impl Ui for LoadBF {
       LoadBFState::Showing(ShowingDetails {
         level: s,
         level_state: bf_state,
-        has_squads,
         ..
       }) => match command {
         Some(Command::NewSquadForBF(level)) => {
           self.state = LoadBFState::NewSquad(NewBF::new(
             self.rect.clone(),
-            load_level_file(*level).map(|bf| (bf, *level)),
+            load_level_file(config(), *level)
+              .ok()
+              .map(|bf| (bf, *level)),
           ));
         }
         Some(Command::ChangeBF(sign)) => {
-          let s_prime = plus_minus(&input, *s, *sign);
-          match load_level_file(s_prime) {
-            Some(state) => {
-              *bf_state = state;
-              *s = s_prime;
-              *has_squads = load_squad_file(s_prime, 0).is_some();
-            }
-            _ => {}
+          if let Some(state) = browse_level(plus_minus(&input, *s, *sign)) {
+            self.state = state;
           }
         }
         Some(Command::ChangeSquad(_, _)) => {}
@@ -377,20 +445,36 @@ impl Ui for LoadBF {
           return Some(());
         }
         Some(Command::BuildBattle(level)) => {
-          if let Some(sqd) = load_squad_file(*level, 0) {
-            self.state = LoadBFState::SelectingSquads(BattleParams {
-              level: *level,
-              blue_index: 0,
-              red_index: 0,
-              blue_squad: sqd.clone(),
-              red_squad: sqd.clone(),
-              joined_tiles: join_tiles(&sqd, &sqd),
-            })
-          }
+          let teams = bf_state.get_board().teams();
+          // a broken first squad is replaced by the bare level until
+          // another one is chosen
+          let (sqd, broken) = match load_squad_file(config(), *level, 0) {
+            Ok(sqd) => (sqd, None),
+            Err(e) => (bf_state.clone(), Some(e.to_string())),
+          };
+          self.state = LoadBFState::SelectingSquads(BattleParams {
+            level: *level,
+            indices: vec![0; teams],
+            squads: vec![sqd.clone(); teams],
+            broken: vec![broken; teams],
+            problems: vec![],
+            joined_tiles: sqd.mirrored_tiles(),
+          })
         }
         Some(_) => {}
         None => {}
       },
+      LoadBFState::Broken { level, .. } => match command {
+        Some(Command::ChangeBF(sign)) => {
+          if let Some(state) = browse_level(plus_minus(&input, *level, *sign)) {
+            self.state = state;
+          }
+        }
+        Some(Command::Exit) => {
+          return Some(());
+        }
+        _ => {}
+      },
       LoadBFState::NoFiles => {
         if let Some(Command::Exit) = command {
           return Some(());
@@ -400,23 +484,30 @@ impl Ui for LoadBF {
         Some(Command::ChangeSquad(team, sign)) => {
           let BattleParams {
             level,
-            blue_index,
-            red_index,
-            blue_squad,
-            red_squad,
+            indices,
+            squads,
+            broken,
+            problems,
             ..
           } = battle_params;
-          let (relevant_squad, relevant_index) = match team {
-            Team::Blue => (blue_squad, blue_index),
-            Team::Red => (red_squad, red_index),
-          };
+          problems.clear();
+          let (relevant_squad, relevant_index, relevant_broken) = (
+            &mut squads[team.0],
+            &mut indices[team.0],
+            &mut broken[team.0],
+          );
           let s_prime = plus_minus(&input, *relevant_index, *sign);
-          match load_squad_file(*level, s_prime) {
-            Some(state) => {
+          match load_squad_file(config(), *level, s_prime) {
+            Ok(state) => {
               *relevant_squad = state;
               *relevant_index = s_prime;
+              *relevant_broken = None;
+            }
+            Err(e) if e.is_missing() => {}
+            Err(e) => {
+              *relevant_index = s_prime;
+              *relevant_broken = Some(e.to_string());
             }
-            _ => {}
           }
           self.state = LoadBFState::SelectingSquads(battle_params.clone());
           if let Some(Command::Exit) = command {
@@ -424,19 +515,41 @@ impl Ui for LoadBF {
           }
         }
         Some(Command::Start) => {
-          if let LoadBFState::SelectingSquads(battle) = &self.state {
-            self.state = LoadBFState::View(View::new(
-              self.rect.clone(),
-              ViewState {
-                level: battle.level,
-                blue_squad_number: battle.blue_index,
-                red_squad_number: battle.red_index,
-                current_frame: 0,
-                finished: false,
-                seconds: 0.0,
-                speed: 0,
-              },
-            ));
+          if battle_params.broken.iter().any(|b| b.is_some()) {
+            return None;
+          }
+          // squads edited by hand may no longer fit their level
+          battle_params.problems = match load_level_file(config(), battle_params.level) {
+            Ok(level) => battle_params
+              .squads
+              .iter()
+              .enumerate()
+              .flat_map(|(team, squad)| {
+                let index = battle_params.indices[team];
+                validate(&level, squad)
+                  .into_iter()
+                  .map(move |d| format!("{} Squad {:05}: {}", team_name(Team(team)), index, d))
+              })
+              .collect(),
+            Err(e) => vec![format!("Level: {}", e)],
+          };
+          if !battle_params.problems.is_empty() {
+            return None;
+          }
+          let view = View::new(
+            self.rect.clone(),
+            ViewState {
+              level: battle_params.level,
+              squad_numbers: battle_params.indices.clone(),
+              current_frame: 0,
+              finished: false,
+              seconds: 0.0,
+              speed: 0,
+            },
+          );
+          match view {
+            Ok(view) => self.state = LoadBFState::View(view),
+            Err(e) => battle_params.problems = vec![e.to_string()],
           }
         }
         Some(Command::Exit) => {
@@ -446,26 +559,16 @@ impl Ui for LoadBF {
       },
       LoadBFState::NewSquad(n) => match n.process_input(input.clone()) {
         Some(()) => {
-          if let Some(state) = load_level_file(0) {
-            self.state = LoadBFState::Showing(ShowingDetails {
-              level: 0,
-              level_state: state.clone(),
-              has_squads: load_squad_file(0, 0).is_some(),
-              joined_tiles: join_tiles(&state, &state),
-            });
+          if let Some(state) = browse_level(0) {
+            self.state = state;
           }
         }
         _ => {}
       },
       LoadBFState::View(v) => match v.process_input(input.clone()) {
         Some(()) => {
-          if let Some(state) = load_level_file(0) {
-            self.state = LoadBFState::Showing(ShowingDetails {
-              level: 0,
-              level_state: state.clone(),
-              has_squads: load_squad_file(0, 0).is_some(),
-              joined_tiles: join_tiles(&state, &state),
-            });
+          if let Some(state) = browse_level(0) {
+            self.state = state;
             self.update_main_panel();
             return None;
           }
@@ -474,6 +577,10 @@ impl Ui for LoadBF {
       },
     };
     self.update_main_panel();
+    // the squad editor handles its own keys, and q may be typed in a title
+    if let LoadBFState::NewSquad(_) = self.state {
+      return None;
+    }
     if let Input::Key(KeyCode::Escape) | Input::Key(KeyCode::Q) = input {
       Some(())
     } else {
@@ -4,24 +4,26 @@ extern crate rand_chacha;
 use async_trait::async_trait;
 use futures::executor::block_on;
 use macroquad::prelude::*;
-use rand::prelude::*;
-use rand_chacha::ChaCha8Rng;
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::Path;
+use std::time::SystemTime;
 
-use super::canvas::{draw_floor, draw_mat_map, draw_template_at};
+use super::canvas::{
+  build_floor, draw_floor, draw_grid, draw_mat_map, draw_neutrals, draw_template_at,
+  draw_terrain_map,
+};
 use super::entity_edit::{EntityEdit, EntityEditCommand};
 use super::ui::{
   build_incrementer, one_or_ten, split, trim_margins, Button, ButtonPanel, Input, Rect, Ui,
 };
-use crate::state::bf::{join_tiles, BFState, EntityState, MatName, ValidationError};
+use crate::state::bf::{
+  save_level_file, save_squad_file, validate, BFState, EntityState, MatName, Metadata,
+};
+use crate::state::config::config;
 use crate::state::constants::{HEIGHT, NUM_TEMPLATES, WIDTH};
 use crate::state::entity::Team;
-use crate::state::geometry::{half_board_iterator, Pos};
+use crate::state::geometry::{Board, Pos, Symmetry};
 use crate::state::materials::Materials;
-use crate::state::state::Tile;
-use crate::state::utils::get_next_file_number;
+use crate::state::neutral::Neutral;
+use crate::state::state::{Terrain, Tile};
 
 const XDISPL: f32 = 800.0;
 const YDISPL: f32 = 30.0;
@@ -36,6 +38,8 @@ pub enum Brush {
   Plutonium,
   Copper,
   Bot(usize),
+  Terrain(Terrain),
+  Neutral(Neutral),
 }
 
 #[derive(Clone, Debug)]
@@ -50,16 +54,30 @@ pub enum TknButton {
   MinTkns,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+  Title,
+  Author,
+  Description,
+  Tags,
+}
+
 #[derive(Clone, Debug)]
 pub enum Command {
   Finish,
+  EditField(Field),
+  Details,
   MatPM(MatName, Sign),
   MatBrush(MatName),
   Token(TknButton, Sign),
+  BoardSize(Sign),
+  NextSymmetry,
   MapClk(Pos),
   BotNumber(usize, Sign),
   BotBrush(usize),
   EraserBrush,
+  TerrainBrush(Terrain),
+  NeutralBrush(Neutral),
   BotEdit(usize),
   BotAddSubs(usize),
   BotDelete(usize),
@@ -74,6 +92,8 @@ enum Screen {
   Map,
   Entity(EntityEdit, usize),
   SaveDialogue(ButtonPanel<Command>),
+  // typing into one field of the metadata before saving
+  Details(ButtonPanel<Command>, Field),
   DisplayFileNumber(ButtonPanel<Command>),
 }
 
@@ -88,11 +108,17 @@ pub struct NewBF {
   state: BFState,
   old_state: BFState,
   message: String,
+  // every problem of the last edit that was refused
+  problems: Vec<String>,
+  metadata: Metadata,
+  // the tags as typed, separated by commas
+  tags: String,
   brush: Brush,
   screen: Screen,
   rect: Rect,
-  floor: [usize; WIDTH * HEIGHT],
+  floor: Vec<usize>,
   tileset: Texture2D,
+  wall: Texture2D,
   joined_tiles: Vec<Tile>,
   panel: ButtonPanel<Command>,
   new_type: NewBFType,
@@ -157,6 +183,8 @@ impl NewBF {
       Brush::Copper => alerts[3] = true,
       Brush::Bot(_) => {}
       Brush::Eraser => {}
+      Brush::Terrain(_) => {}
+      Brush::Neutral(_) => {}
     }
     let mut material_brush_buttons = ButtonPanel::<Command>::new(
       Rect::new(0.0, 0.0, 1000.0, 1000.0),
@@ -167,23 +195,39 @@ impl NewBF {
   }
 
   fn build_token_panel(&self, rect: &Rect) -> ButtonPanel<Command> {
-    let rects = split(&rect.clone(), vec![0.25, 0.5, 0.75, 1.0], vec![0.0, 0.75]);
-    let mut panel: ButtonPanel<Command> = build_incrementer::<Command>(
-      &rects[0],
+    let rects = split(
+      &rect.clone(),
+      vec![0.0, 0.25, 0.5, 0.75, 1.0],
+      vec![0.0, 0.75],
+    );
+    let mut panel: ButtonPanel<Command> =
+      ButtonPanel::new(rect.clone(), (vec![], vec![], vec![], vec![], vec![]));
+    // squads play on the board of their level
+    if let NewBFType::BrandNew = self.new_type {
+      panel.append(&mut build_incrementer::<Command>(
+        &rects[0],
+        "Board".to_string(),
+        self.state.get_board().width,
+        Command::BoardSize(Sign::Plus),
+        Command::BoardSize(Sign::Minus),
+      ));
+    }
+    panel.append(&mut build_incrementer::<Command>(
+      &rects[1],
       "Tokens".to_string(),
       self.state.get_tokens(),
       Command::Token(TknButton::Tokens, Sign::Plus),
       Command::Token(TknButton::Tokens, Sign::Minus),
-    );
+    ));
     panel.append(&mut build_incrementer(
-      &rects[1],
+      &rects[2],
       "Min Tks".to_string(),
       self.state.get_min_tokens(),
       Command::Token(TknButton::MinTkns, Sign::Plus),
       Command::Token(TknButton::MinTkns, Sign::Minus),
     ));
     panel.push(Button::<Command>::new(
-      rects[2].clone(),
+      rects[3].clone(),
       (
         "Erase".to_string(),
         Command::EraserBrush,
@@ -255,27 +299,35 @@ impl NewBF {
   }
 
   fn validate_state(&mut self) {
-    match self.is_valid() {
-      Ok(true) => self.old_state = self.state.clone(),
-      Ok(false) => self.revert_from(&self.old_state.clone()),
-      Err(e) => {
-        self.message = format!("{}", e);
+    let problems = self.problems();
+    match problems.first() {
+      None => self.old_state = self.state.clone(),
+      Some(problem) => {
+        self.message = problem.clone();
         self.revert_from(&self.old_state.clone());
       }
     };
+    self.problems = problems;
   }
 
-  fn is_valid(&self) -> Result<bool, ValidationError> {
-    self.state.check_validity()?;
-    match self.new_type.clone() {
-      NewBFType::BrandNew => Ok(true),
-      NewBFType::Derived(reference, _) => self.state.is_compatible(&reference),
+  fn problems(&self) -> Vec<String> {
+    let mut problems = vec![];
+    if let Err(e) = self.state.check_validity() {
+      problems.push(format!("{}", e));
     }
+    if let NewBFType::Derived(reference, _) = &self.new_type {
+      problems.extend(
+        validate(reference, &self.state)
+          .iter()
+          .map(|d| format!("{}", d)),
+      );
+    }
+    problems
   }
 
   fn update_main_panel(&mut self) {
     self.validate_state();
-    self.joined_tiles = join_tiles(&self.state, &self.state);
+    self.joined_tiles = self.state.mirrored_tiles();
     let left_rect = trim_margins(
       split(&self.rect, vec![0.0, 0.45, 1.0], vec![0.0, 1.0])[0].clone(),
       0.05,
@@ -283,19 +335,59 @@ impl NewBF {
       0.05,
       0.05,
     );
-    let rects: Vec<Rect> = split(&left_rect, vec![0.0, 1.0], vec![0.0, 0.25, 0.5, 0.9, 1.0]);
+    let rects: Vec<Rect> = split(&left_rect, vec![0.0, 1.0], vec![0.0, 0.25, 0.5, 0.8, 1.0]);
     let mut button_panel = self.build_material_panel(&rects[0]);
     button_panel.append(&mut self.build_token_panel(&rects[1]));
     button_panel.append(&mut self.build_bot_panels(&rects[2]));
+    let bottom_rects: Vec<Rect> = split(
+      &rects[3],
+      vec![0.0, 0.25, 0.5, 0.75, 1.0],
+      vec![0.0, 0.5, 1.0],
+    );
+    if let NewBFType::BrandNew = self.new_type {
+      button_panel.push(Button::<Command>::new(
+        trim_margins(bottom_rects[0].clone(), 0.1, 0.1, 0.3, 0.3),
+        (
+          format!("{:?}", self.state.get_board().symmetry),
+          Command::NextSymmetry,
+          true,
+          false,
+        ),
+      ));
+      for (i, terrain) in [Terrain::Wall, Terrain::Water].into_iter().enumerate() {
+        button_panel.push(Button::<Command>::new(
+          trim_margins(bottom_rects[i + 1].clone(), 0.1, 0.1, 0.3, 0.3),
+          (
+            format!("{:?}", terrain),
+            Command::TerrainBrush(terrain),
+            true,
+            matches!(&self.brush, Brush::Terrain(t) if *t == terrain),
+          ),
+        ));
+      }
+      let neutrals = [Neutral::Turret, Neutral::Rock, Neutral::Creature];
+      for (i, neutral) in neutrals.into_iter().enumerate() {
+        button_panel.push(Button::<Command>::new(
+          trim_margins(bottom_rects[i + 4].clone(), 0.1, 0.1, 0.3, 0.3),
+          (
+            format!("{:?}", neutral),
+            Command::NeutralBrush(neutral),
+            true,
+            matches!(&self.brush, Brush::Neutral(n) if *n == neutral),
+          ),
+        ));
+      }
+    }
     button_panel.push(Button::<Command>::new(
-      trim_margins(rects[3].clone(), 0.3, 0.3, 0.3, 0.3),
+      trim_margins(bottom_rects[3].clone(), 0.1, 0.1, 0.3, 0.3),
       ("Save & Exit".to_string(), Command::Finish, true, false),
     ));
-    for pos in half_board_iterator().into_iter() {
+    let board = self.state.get_board();
+    for pos in board.region_iter() {
       button_panel.push(Button::<Command>::new(
         Rect::new(
           XDISPL + 16.0 * (pos.x as f32),
-          YDISPL + 16.0 * (HEIGHT.saturating_sub(pos.y + 1) as f32),
+          YDISPL + 16.0 * (board.height.saturating_sub(pos.y + 1) as f32),
           16.0,
           16.0,
         ),
@@ -305,33 +397,24 @@ impl NewBF {
     self.panel = button_panel;
   }
 
-  fn save_nf(&self) -> usize {
-    match self.new_type {
-      NewBFType::BrandNew => {
-        let path = Path::new("./levels");
-        let next_file_number = get_next_file_number(path, "lvl".to_string());
-        let dest_filename = format!("{:05}", next_file_number);
-        let mut dest = path.join(dest_filename);
-        dest.set_extension("lvl");
-        let mut file = File::create(dest).unwrap();
-        let serialized = serde_json::to_string(&self.state).unwrap();
-        file.write_all(serialized.as_bytes()).unwrap();
-        next_file_number
-      }
-      NewBFType::Derived(_, level) => {
-        let path = &Path::new("./squads/").join(format!("{:05}", level));
-        if !path.exists() {
-          fs::create_dir_all(path.clone()).unwrap();
-        }
-        let next_file_number = get_next_file_number(&path, "sqd".to_string());
-        let dest_filename = format!("{:05}", next_file_number);
-        let mut dest = path.join(dest_filename);
-        dest.set_extension("sqd");
-        let mut file = File::create(dest).unwrap();
-        let serialized = serde_json::to_string(&self.state).unwrap();
-        file.write_all(serialized.as_bytes()).unwrap();
-        next_file_number
-      }
+  fn save_nf(&self) -> std::io::Result<usize> {
+    let mut state = self.state.clone();
+    let created = SystemTime::now()
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .map_or(0, |d| d.as_secs());
+    state.set_metadata(Metadata {
+      created,
+      tags: self
+        .tags
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect(),
+      ..self.metadata.clone()
+    });
+    match &self.new_type {
+      NewBFType::BrandNew => save_level_file(config(), &state),
+      NewBFType::Derived(reference, level) => save_squad_file(config(), *level, reference, &state),
     }
   }
 
@@ -357,7 +440,7 @@ impl NewBF {
     ));
     panel.push(Button::<Command>::new(
       rects[2].clone(),
-      ("Save".to_string(), Command::Save, true, false),
+      ("Save".to_string(), Command::Details, true, false),
     ));
     panel.push(Button::<Command>::new(
       rects[3].clone(),
@@ -370,33 +453,91 @@ impl NewBF {
     ));
     panel
   }
+
+  // a row for each field, with its name on the left and the text on the
+  // right, and a last one for the buttons
+  fn details_rects(&self) -> Vec<Rect> {
+    split(
+      &trim_margins(self.rect.clone(), 0.25, 0.25, 0.2, 0.2),
+      vec![0.0, 0.3, 1.0],
+      vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0],
+    )
+  }
+
+  fn field_text(&self, field: Field) -> &String {
+    match field {
+      Field::Title => &self.metadata.title,
+      Field::Author => &self.metadata.author,
+      Field::Description => &self.metadata.description,
+      Field::Tags => &self.tags,
+    }
+  }
+
+  fn field_text_mut(&mut self, field: Field) -> &mut String {
+    match field {
+      Field::Title => &mut self.metadata.title,
+      Field::Author => &mut self.metadata.author,
+      Field::Description => &mut self.metadata.description,
+      Field::Tags => &mut self.tags,
+    }
+  }
+
+  fn build_details_dialogue(&self, selected: Field) -> ButtonPanel<Command> {
+    let rects = self.details_rects();
+    let mut panel = ButtonPanel::new(self.rect.clone(), (vec![], vec![], vec![], vec![], vec![]));
+    for (i, field) in FIELDS.into_iter().enumerate() {
+      panel.push(Button::<Command>::new(
+        trim_margins(rects[2 * i].clone(), 0.1, 0.1, 0.05, 0.05),
+        (
+          format!("{:?}", field),
+          Command::EditField(field),
+          true,
+          field == selected,
+        ),
+      ));
+    }
+    panel.push(Button::<Command>::new(
+      trim_margins(rects[8].clone(), 0.1, 0.1, 0.05, 0.05),
+      ("Back".to_string(), Command::BackToEdit, true, false),
+    ));
+    panel.push(Button::<Command>::new(
+      trim_margins(rects[9].clone(), 0.1, 0.1, 0.3, 0.3),
+      ("Save".to_string(), Command::Save, true, false),
+    ));
+    panel
+  }
 }
 
+const FIELDS: [Field; 4] = [Field::Title, Field::Author, Field::Description, Field::Tags];
+
 #[async_trait]
 impl Ui for NewBF {
   type Command = ();
   type Builder = Option<(BFState, usize)>;
 
   fn new(rect: Rect, builder: Option<(BFState, usize)>) -> Self {
-    let tileset = block_on(load_texture("assets/tileset.png")).unwrap();
-    let mut rng: ChaCha8Rng = ChaCha8Rng::seed_from_u64(25).try_into().unwrap();
-    let mut floor = [0; WIDTH * HEIGHT];
-    for i in 0..(WIDTH * HEIGHT) {
-      floor[i] = rng.gen_range(0..7);
-    }
+    let tileset = block_on(load_texture(&config().asset("tileset.png"))).unwrap();
+    let wall = block_on(load_texture(&config().asset("wall.png"))).unwrap();
+    // boards in the editor are at most as large as the default one
+    let floor = build_floor(Board::default());
     let new_bf_state = match &builder {
-      None => BFState::new(),
+      None => BFState::new(Board::default()),
       Some((state, _)) => state.clone(),
     };
     let mut new_bf = NewBF {
       screen: Screen::Map,
       message: "Editing field".to_string(),
+      problems: vec![],
+      // squads start blank rather than with the details of their level
+      metadata: Metadata::default(),
+      tags: String::new(),
       brush: Brush::Carbon,
       rect: rect.clone(),
       state: new_bf_state.clone(),
       old_state: new_bf_state.clone(),
       floor,
       tileset,
+      wall,
       joined_tiles: vec![],
       panel: ButtonPanel::new(rect, (vec![], vec![], vec![], vec![], vec![])),
       new_type: match builder {
@@ -411,36 +552,61 @@ impl Ui for NewBF {
   async fn draw(&self) {
     self.panel.draw().await;
     draw_text(&self.message, 20.0, 40.0, 40.0, DARKBLUE);
-    draw_floor(XDISPL, YDISPL, &self.tileset, &self.floor).await;
-    draw_mat_map(&self.joined_tiles, XDISPL, YDISPL, &self.tileset).await;
-    draw_rectangle(XDISPL, YDISPL, 16.0 * 60.0, 16.0 * 30.0, SMOKE);
-    for pos in half_board_iterator() {
-      if let Some(id) = &self.state.get_tiles()[pos.to_index()].entity_id {
-        if let EntityState::Entity(e, _) = &self.state.get_entities()[*id] {
-          draw_template_at(&e.clone(), XDISPL, YDISPL, pos, Team::Blue, &self.tileset).await;
-          let f = e.clone();
-          draw_template_at(&f, XDISPL, YDISPL, pos.invert(), Team::Red, &self.tileset).await;
-        }
-      }
+    for (i, problem) in self.problems.iter().enumerate() {
+      let line = (self.problems.len() - i) as f32;
+      draw_text(problem, 20.0, screen_height() - 20.0 * line, 20.0, RED);
     }
-    for i in 0..=WIDTH {
-      draw_line(
-        XDISPL + (i as f32) * 16.0,
-        YDISPL,
-        XDISPL + (i as f32) * 16.0,
-        YDISPL + (60.0 * 16.0),
-        1.0,
-        SMOKE,
-      );
-      draw_line(
-        XDISPL,
-        YDISPL + (i as f32) * 16.0,
-        XDISPL + (60.0 * 16.0),
-        YDISPL + (i as f32) * 16.0,
-        1.0,
+    let board = self.state.get_board();
+    draw_floor(XDISPL, YDISPL, &self.tileset, board, &self.floor).await;
+    draw_terrain_map(&self.joined_tiles, XDISPL, YDISPL, board, &self.wall).await;
+    draw_mat_map(&self.joined_tiles, XDISPL, YDISPL, board, &self.tileset).await;
+    // smoke over the regions of the other teams
+    for pos in board.iter().filter(|p| !board.contains_region(*p)) {
+      draw_rectangle(
+        XDISPL + 16.0 * (pos.x as f32),
+        YDISPL + 16.0 * (board.height.saturating_sub(pos.y + 1) as f32),
+        16.0,
+        16.0,
         SMOKE,
       );
     }
+    for pos in board.region_iter() {
+      if let Some(id) = &self.state.get_tiles()[board.region_index(pos)].entity_id {
+        if let EntityState::Entity(e, _) = &self.state.get_entities()[*id] {
+          draw_template_at(
+            &e.clone(),
+            XDISPL,
+            YDISPL,
+            board,
+            pos,
+            Team::BLUE,
+            &self.tileset,
+          )
+          .await;
+          for team in (1..board.teams()).map(Team) {
+            draw_template_at(
+              &e.clone(),
+              XDISPL,
+              YDISPL,
+              board,
+              board.from_team(team, pos),
+              team,
+              &self.tileset,
+            )
+            .await;
+          }
+        }
+      }
+    }
+    draw_neutrals(
+      self.state.get_neutrals(),
+      XDISPL,
+      YDISPL,
+      board,
+      &self.tileset,
+    )
+    .await;
+    draw_grid(XDISPL, YDISPL, board, SMOKE);
     match &self.screen {
       Screen::Map => {}
       Screen::Entity(ee, _) => {
@@ -451,6 +617,32 @@ impl Ui for NewBF {
         draw_rectangle(self.rect.x, self.rect.y, self.rect.w, self.rect.h, SMOKE);
         panel.draw().await;
       }
+      Screen::Details(panel, selected) => {
+        draw_rectangle(self.rect.x, self.rect.y, self.rect.w, self.rect.h, SMOKE);
+        panel.draw().await;
+        let rects = self.details_rects();
+        for (i, field) in FIELDS.into_iter().enumerate() {
+          let rect = &rects[2 * i + 1];
+          let cursor = if field == *selected { "_" } else { "" };
+          draw_text(
+            &format!("{}{}", self.field_text(field), cursor),
+            rect.x + 10.0,
+            rect.y + rect.h / 2.0 + 10.0,
+            30.0,
+            DARKGREEN,
+          );
+        }
+        if let Field::Tags = selected {
+          let rect = &rects[9];
+          draw_text(
+            "separated by commas",
+            rect.x + 10.0,
+            rect.y + rect.h + 20.0,
+            20.0,
+            DARKGREEN,
+          );
+        }
+      }
       Screen::DisplayFileNumber(panel) => {
         draw_rectangle(self.rect.x, self.rect.y, self.rect.w, self.rect.h, SMOKE);
         panel.draw().await;
@@ -516,6 +708,34 @@ impl Ui for NewBF {
               }
             },
           },
+          Some(Command::BoardSize(sign)) => {
+            let board = self.state.get_board();
+            // square boards, growing by two so that the height stays even
+            let size = match sign {
+              Sign::Plus => board.width + 2,
+              Sign::Minus => board.width.saturating_sub(2),
+            };
+            if size > WIDTH.min(HEIGHT) {
+              self.message = "Larger boards do not fit the screen".to_string();
+            } else if let Err(e) = self
+              .state
+              .resize(Board::new(size, size).with_symmetry(board.symmetry))
+            {
+              self.message = format!("{}", e);
+            }
+          }
+          Some(Command::NextSymmetry) => {
+            let board = self.state.get_board();
+            let symmetry = match board.symmetry {
+              Symmetry::Rotation => Symmetry::MirrorHorizontal,
+              Symmetry::MirrorHorizontal => Symmetry::MirrorVertical,
+              Symmetry::MirrorVertical => Symmetry::FourfoldRotation,
+              Symmetry::FourfoldRotation => Symmetry::Rotation,
+            };
+            if let Err(e) = self.state.resize(board.with_symmetry(symmetry)) {
+              self.message = format!("{}", e);
+            }
+          }
           Some(Command::MapClk(pos)) => match self.brush {
             Brush::Carbon => {
               if let Err(e) =
@@ -558,6 +778,16 @@ impl Ui for NewBF {
                 self.message = format!("{}", e);
               }
             }
+            Brush::Terrain(terrain) => {
+              if let Err(e) = self.state.set_terrain(pos, terrain) {
+                self.message = format!("{}", e);
+              }
+            }
+            Brush::Neutral(neutral) => {
+              if let Err(e) = self.state.add_neutral(neutral, pos) {
+                self.message = format!("{}", e);
+              }
+            }
             Brush::Eraser => match &self.new_type {
               NewBFType::BrandNew => {
                 if let Err(e) = self.state.erase_bot_from_board(pos) {
@@ -572,9 +802,13 @@ impl Ui for NewBF {
                 if let Err(e) = self.state.erase_material_tile(pos, remainder) {
                   self.message = format!("{}", e);
                 };
+                self.state.erase_neutral(pos);
+                if let Err(e) = self.state.set_terrain(pos, Terrain::Floor) {
+                  self.message = format!("{}", e);
+                };
               }
               NewBFType::Derived(reference, _) => {
-                let ref_tile = &reference.get_tiles()[pos.to_index()];
+                let ref_tile = &reference.get_tiles()[reference.get_board().region_index(pos)];
                 if ref_tile.entity_id.is_some() {
                   self.message = format!("Cannot remove level bot {:?}", pos);
                 } else {
@@ -628,6 +862,12 @@ impl Ui for NewBF {
           Some(Command::EraserBrush) => {
             self.brush = Brush::Eraser;
           }
+          Some(Command::TerrainBrush(terrain)) => {
+            self.brush = Brush::Terrain(terrain);
+          }
+          Some(Command::NeutralBrush(neutral)) => {
+            self.brush = Brush::Neutral(neutral);
+          }
           _ => {}
         };
         self.update_main_panel();
@@ -682,10 +922,49 @@ impl Ui for NewBF {
       Screen::SaveDialogue(panel) => {
         let command = panel.process_input(input.clone());
         match command {
+          Some(Command::Details) => {
+            self.screen = Screen::Details(self.build_details_dialogue(Field::Title), Field::Title);
+          }
+          Some(Command::BackToEdit) => {
+            self.screen = Screen::Map;
+            self.update_main_panel();
+          }
+          Some(Command::ExitWithoutSaving) => return Some(()),
+          _ => {}
+        }
+        None
+      }
+      Screen::Details(panel, selected) => {
+        let selected = *selected;
+        let command = panel.process_input(input.clone());
+        match input {
+          Input::Char(c) => self.field_text_mut(selected).push(c),
+          Input::Key(KeyCode::Backspace) => {
+            self.field_text_mut(selected).pop();
+          }
+          Input::Key(KeyCode::Tab) | Input::Key(KeyCode::Enter) => {
+            let i = FIELDS.iter().position(|f| *f == selected).unwrap();
+            let next = FIELDS[(i + 1) % FIELDS.len()];
+            self.screen = Screen::Details(self.build_details_dialogue(next), next);
+          }
+          _ => {}
+        }
+        match command {
+          Some(Command::EditField(field)) => {
+            self.screen = Screen::Details(self.build_details_dialogue(field), field);
+          }
           Some(Command::Save) => {
             // TODO probably we should just change the state and build the
             // panel in update_main_panel().
-            let file_number = self.save_nf();
+            let file_number = match self.save_nf() {
+              Ok(n) => n,
+              Err(e) => {
+                self.message = format!("Could not save: {}", e);
+                self.screen = Screen::Map;
+                self.update_main_panel();
+                return None;
+              }
+            };
             let rects: Vec<Rect> = split(
               &trim_margins(self.rect.clone(), 0.4, 0.4, 0.4, 0.4),
               vec![0.0, 1.0],
@@ -712,7 +991,6 @@ impl Ui for NewBF {
             self.screen = Screen::Map;
             self.update_main_panel();
           }
-          Some(Command::ExitWithoutSaving) => return Some(()),
           _ => {}
         }
         None
@@ -69,6 +69,8 @@ pub async fn draw_centered_text(rect: &Rect, text: &str) {
 #[derive(Debug, Clone)]
 pub enum Input {
   Key(KeyCode),
+  // a typed character, sent after the key that produced it
+  Char(char),
   Click(MouseButton, (f32, f32)),
   Tick,
 }
@@ -81,6 +83,9 @@ pub fn get_input() -> Option<Input> {
       return Some(Input::Key(k));
     }
   };
+  if let Some(c) = get_char_pressed().filter(|c| !c.is_control()) {
+    return Some(Input::Char(c));
+  }
   if is_mouse_button_pressed(MouseButton::Left) {
     return Some(Input::Click(MouseButton::Left, mouse_position()));
   }
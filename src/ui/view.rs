@@ -1,43 +1,68 @@
 extern crate rand;
 extern crate rand_chacha;
 
-use async_trait::async_trait;
 use macroquad::prelude::*;
+use snafu::prelude::*;
 
 use futures::executor::block_on;
-use rand::prelude::*;
-use rand_chacha::ChaCha8Rng;
 
 use super::ui::{build_incrementer, split, trim_margins, Button, ButtonPanel, Input, Rect, Ui};
-use crate::state::bf::{load_level_file, load_squad_file, BFState};
-use crate::state::constants::{HEIGHT, NUMBER_TURNS, WIDTH};
-use crate::state::run::run_match;
-use crate::state::state::{Frame, GameStatus, State};
-use crate::ui::canvas::{draw_entity_map, draw_floor, draw_mat_map};
+use crate::state::bf::{load_level_file, load_squad_file, BFState, LoadError};
+use crate::state::config::config;
+use crate::state::constants::NUMBER_TURNS;
+use crate::state::entity::Team;
+use crate::state::run::{run_match, save_replay, RunError};
+use crate::state::state::{Event, Fault, Frame, GameStatus, Log, State};
+use crate::ui::canvas::{
+  build_floor, draw_entity_map, draw_floor, draw_mat_map, draw_terrain_map, team_name,
+};
 
 const XDISPL: f32 = 800.0;
 const YDISPL: f32 = 30.0;
+// number of bot messages shown below the status
+const LOG_LINES: usize = 12;
 
 #[derive(Clone, Debug)]
 pub struct ViewState {
   pub level: usize,
-  pub blue_squad_number: usize,
-  pub red_squad_number: usize,
+  // indexed by team
+  pub squad_numbers: Vec<usize>,
   pub current_frame: usize,
   pub finished: bool,
   pub seconds: f64,
   pub speed: usize,
 }
 
+// why a match could not be shown, the files may have changed since they
+// were chosen
+#[derive(Debug, Snafu)]
+pub enum ViewError {
+  #[snafu(display("Could not load the level: {}", source))]
+  Level { source: LoadError },
+  #[snafu(display("Could not load squad {:05}: {}", squad, source))]
+  Squad { source: LoadError, squad: usize },
+  #[snafu(display("Could not run the match: {}", source))]
+  Match {
+    #[snafu(source(from(RunError, Box::new)))]
+    source: Box<RunError>,
+  },
+}
+
 #[derive(Debug)]
 pub struct View {
   rect: Rect,
   view_state: ViewState,
+  // a problem that does not stop the match, like an unsaved replay
+  notice: Option<String>,
   state: State,
   panel: ButtonPanel<Command>,
-  floor: [usize; WIDTH * HEIGHT],
+  floor: Vec<usize>,
   tileset: Texture2D,
+  wall: Texture2D,
   frames: Vec<Frame>,
+  logs: Vec<Vec<Log>>,
+  faults: Vec<Vec<Fault>>,
+  events: Vec<Vec<Event>>,
 }
 
 #[derive(Clone, Debug)]
@@ -82,80 +107,92 @@ impl View {
     );
     self.panel = self.build_panel(&left_rect);
   }
-}
-
-#[async_trait]
-impl Ui for View {
-  type Command = ();
-  type Builder = ViewState;
 
-  fn new(rect: Rect, v: ViewState) -> Self {
-    let level: BFState = match load_level_file(v.level) {
-      Some(level_state) => level_state,
-      None => unreachable!(),
-    };
-
-    let blue_squad: BFState = match load_squad_file(v.level, v.blue_squad_number) {
-      Some(blue_squad_state) => blue_squad_state,
-      None => unreachable!(),
-    };
+  // loads the level and squads of `v` and runs their match
+  pub fn new(rect: Rect, v: ViewState) -> Result<Self, ViewError> {
+    let level: BFState = load_level_file(config(), v.level).context(LevelSnafu)?;
 
-    let red_squad: BFState = match load_squad_file(v.level, v.red_squad_number) {
-      Some(red_squad_state) => red_squad_state,
-      None => unreachable!(),
-    };
+    let squads: Vec<BFState> = v
+      .squad_numbers
+      .iter()
+      .map(|n| load_squad_file(config(), v.level, *n).context(SquadSnafu { squad: *n }))
+      .collect::<Result<_, _>>()?;
 
-    let script = run_match(&level, &blue_squad, &red_squad, NUMBER_TURNS);
+    let script = run_match(
+      config(),
+      &level,
+      &squads.iter().collect::<Vec<&BFState>>(),
+      NUMBER_TURNS,
+    )
+    .context(MatchSnafu)?;
+    let notice = save_replay(config(), &script)
+      .err()
+      .map(|e| format!("Could not save the replay: {}", e));
 
     let state = script.genesis;
     let frames = script.frames;
+    let logs = script.logs;
+    let faults = script.faults;
+    let events = script.events;
     // time constants
 
-    let tileset = block_on(load_texture("assets/tileset.png")).unwrap();
-    let mut rng: ChaCha8Rng = ChaCha8Rng::seed_from_u64(25).try_into().unwrap();
-    let mut floor = [0; WIDTH * HEIGHT];
-    for i in 0..(WIDTH * HEIGHT) {
-      floor[i] = rng.gen_range(0..7);
-    }
+    let tileset = block_on(load_texture(&config().asset("tileset.png"))).unwrap();
+    let wall = block_on(load_texture(&config().asset("wall.png"))).unwrap();
+    let floor = build_floor(state.board);
 
     let mut view: View = View {
       rect: rect.clone(),
       view_state: v,
+      notice,
       frames,
+      logs,
+      faults,
+      events,
       state,
       panel: ButtonPanel::new(rect, (vec![], vec![], vec![], vec![], vec![])),
       tileset,
+      wall,
       floor,
     };
     view.update_main_panel();
-    view
+    Ok(view)
   }
 
-  async fn draw(&self) {
+  pub async fn draw(&self) {
     //match &self.view_state.play_state {
     //  PlayState::Paused => {
     self.panel.draw().await;
     //  }
     //}
 
-    draw_floor(XDISPL, YDISPL, &self.tileset, &self.floor).await;
-    draw_mat_map(&self.state.tiles, XDISPL, YDISPL, &self.tileset).await;
+    draw_floor(XDISPL, YDISPL, &self.tileset, self.state.board, &self.floor).await;
+    draw_terrain_map(
+      &self.state.tiles,
+      XDISPL,
+      YDISPL,
+      self.state.board,
+      &self.wall,
+    )
+    .await;
+    draw_mat_map(
+      &self.state.tiles,
+      XDISPL,
+      YDISPL,
+      self.state.board,
+      &self.tileset,
+    )
+    .await;
     draw_entity_map(&self.state, XDISPL, YDISPL, &self.tileset).await;
     draw_text(format!("FPS: {}", get_fps()).as_str(), 0., 16., 32., WHITE);
-    draw_text(
-      format!("Blue Tokens: {}", &self.state.blue_tokens).as_str(),
-      200.,
-      36.,
-      32.,
-      WHITE,
-    );
-    draw_text(
-      format!("Red_Tokens: {}", &self.state.red_tokens).as_str(),
-      200.,
-      96.,
-      32.,
-      WHITE,
-    );
+    for (team, tokens) in self.state.tokens.iter().enumerate() {
+      draw_text(
+        format!("{} Tokens: {}", team_name(Team(team)), tokens).as_str(),
+        200.,
+        36. + 30. * (team as f32),
+        32.,
+        WHITE,
+      );
+    }
     draw_text(
       format!(
         "Game status: {:?}, min tokens {}",
@@ -167,8 +204,42 @@ impl Ui for View {
       32.,
       WHITE,
     );
+    let recent: Vec<String> = (0..self.view_state.current_frame)
+      .flat_map(|turn| {
+        let logs = self.logs.get(turn).into_iter().flatten();
+        let faults = self.faults.get(turn).into_iter().flatten();
+        let events = self.events.get(turn).into_iter().flatten();
+        logs
+          .map(move |l| format!("{} #{}: {}", turn, l.entity_id, l.message))
+          .chain(faults.map(move |f| {
+            format!(
+              "{} #{}: fault {:?} ({}), {:?}",
+              turn, f.entity_id, f.kind, f.count, f.action
+            )
+          }))
+          .chain(events.map(move |e| match e {
+            Event::Death {
+              entity_id, loot, ..
+            } => format!(
+              "{} #{}: died, dropping loot on {} tiles",
+              turn,
+              entity_id,
+              loot.len()
+            ),
+          }))
+      })
+      .rev()
+      .take(LOG_LINES)
+      .collect();
+    for (i, line) in recent.iter().rev().enumerate() {
+      draw_text(line.as_str(), 20., 500. + 20. * i as f32, 20., LIGHTGRAY);
+    }
+    if let Some(notice) = &self.notice {
+      draw_text(notice, 20.0, screen_height() - 20.0, 20.0, RED);
+    }
   }
-  fn process_input(&mut self, input: Input) -> Option<()> {
+
+  pub fn process_input(&mut self, input: Input) -> Option<()> {
     if let Input::Key(KeyCode::Escape) | Input::Key(KeyCode::Q) = input {
       return Some(());
     }
@@ -200,6 +271,11 @@ impl Ui for View {
                 //let _ = draw_command(&self.state, command).await;
               }
             }
+            if let Some(faults) = self.faults.get(self.view_state.current_frame - 1) {
+              self.state.apply_faults(faults);
+            }
+            // the script already has the events of this frame
+            self.state.take_events();
           } else {
             self.view_state.finished = true;
 
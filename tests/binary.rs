@@ -0,0 +1,71 @@
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use shipped::state::binary::{
+  to_value, BinaryError, BINARY_VERSION, MAGIC, MAX_DEPTH, MAX_INFLATED,
+};
+use std::io::Write;
+
+// the tags of arrays, numbers and repeats
+const ARRAY: u8 = 7;
+const UINT: u8 = 3;
+const REPEAT: u8 = 9;
+
+fn binary(payload: &[u8]) -> Vec<u8> {
+  let mut encoder = DeflateEncoder::new(MAGIC.to_vec(), Compression::default());
+  encoder.write_all(&[BINARY_VERSION]).unwrap();
+  encoder.write_all(payload).unwrap();
+  encoder.finish().unwrap()
+}
+
+#[test]
+fn deeply_nested_data_is_refused() {
+  // arrays of one array each, ending in a zero
+  let mut payload = [ARRAY, 1].repeat(100_000);
+  payload.extend([UINT, 0]);
+  assert!(matches!(
+    to_value(&binary(&payload)),
+    Err(BinaryError::TooDeep {})
+  ));
+
+  let mut payload = [ARRAY, 1].repeat(MAX_DEPTH - 1);
+  payload.extend([UINT, 0]);
+  assert!(to_value(&binary(&payload)).is_ok());
+}
+
+#[test]
+fn repeats_beyond_the_size_of_the_data_are_refused() {
+  // a zero and then 2^62 more of them
+  let mut payload = vec![ARRAY, 2, UINT, 0, REPEAT];
+  payload.extend([0x80; 8]);
+  payload.push(0x40);
+  assert!(matches!(
+    to_value(&binary(&payload)),
+    Err(BinaryError::TooManyItems { .. })
+  ));
+
+  let payload = [ARRAY, 2, UINT, 0, REPEAT, 0x80, 0x40];
+  assert_eq!(
+    to_value(&binary(&payload))
+      .unwrap()
+      .as_array()
+      .unwrap()
+      .len(),
+    8193
+  );
+}
+
+#[test]
+fn data_that_inflates_beyond_the_limit_is_refused() {
+  // a string of zeros one byte too long, which deflates to almost nothing
+  let mut payload = vec![6];
+  let mut len = MAX_INFLATED as u64;
+  while len >= 0x80 {
+    payload.push((len as u8) | 0x80);
+    len >>= 7;
+  }
+  payload.push(len as u8);
+  payload.resize(payload.len() + MAX_INFLATED, 0);
+  let bytes = binary(&payload);
+  assert!(bytes.len() < MAX_INFLATED / 100);
+  assert!(matches!(to_value(&bytes), Err(BinaryError::TooLarge {})));
+}
@@ -0,0 +1,153 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use shipped::state::bf::{
+  load_level_file, load_squad_file, save_level_file, save_squad_file, BFState, EntityState,
+};
+use shipped::state::bundle::{Bundle, BundleError, MAX_BUNDLE};
+use shipped::state::config::Config;
+use shipped::state::entity::{Full, Mix, MixTemplate};
+use shipped::state::geometry::Board;
+use std::io::Write;
+
+// gives template `index` of `level` a brain named `code_name`
+fn think_with(level: &mut BFState, index: usize, code_name: &str) {
+  level.initialize_bot(index).unwrap();
+  if let EntityState::Entity(template, _) = level.get_entities()[index].clone() {
+    let brain = Mix::Full(Full {
+      half: [0, 0],
+      code_name: code_name.to_string(),
+      gas: 0,
+    });
+    level
+      .update_bot(index, MixTemplate { brain, ..template })
+      .unwrap();
+  }
+}
+
+#[test]
+fn bundles_carry_squads_and_bots_to_another_data_directory() {
+  let data = std::env::temp_dir().join(format!("shipped-bundle-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&data);
+  let config = Config::resolve(Some(data.clone()), None, None);
+  std::fs::create_dir_all(data.join("bots")).unwrap();
+  std::fs::write(data.join("bots/mover.wasm"), b"ours").unwrap();
+
+  let mut level = BFState::new(Board::default());
+  think_with(&mut level, 0, "mover.wasm");
+  assert_eq!(save_level_file(&config, &level).unwrap(), 0);
+  assert_eq!(save_squad_file(&config, 0, &level, &level).unwrap(), 0);
+  let path = data.join("trade.bundle");
+  Bundle::export(&config, 0, &[0])
+    .unwrap()
+    .write(&path)
+    .unwrap();
+
+  // the other team has a different bot with the same name
+  std::fs::write(data.join("bots/mover.wasm"), b"theirs").unwrap();
+  let imported = Bundle::read(&path).unwrap().import(&config).unwrap();
+  assert_eq!((imported.level, imported.squads.clone()), (1, vec![0]));
+  let renamed = imported.renamed["mover.wasm"].clone();
+  assert_eq!(
+    load_squad_file(&config, 1, 0).unwrap().brain_names(),
+    vec![renamed.clone()]
+  );
+  assert_eq!(
+    std::fs::read(data.join("bots").join(renamed)).unwrap(),
+    b"ours"
+  );
+
+  // importing twice saves nothing new
+  assert_eq!(
+    Bundle::read(&path).unwrap().import(&config).unwrap(),
+    imported
+  );
+
+  let mut corrupt = Bundle::read(&path).unwrap();
+  corrupt
+    .manifest
+    .bots
+    .insert("mover.wasm".to_string(), "0".to_string());
+  assert!(matches!(
+    corrupt.import(&config),
+    Err(BundleError::CorruptBot { .. })
+  ));
+  let mut short = Bundle::read(&path).unwrap();
+  short.squads.clear();
+  assert!(matches!(
+    short.import(&config),
+    Err(BundleError::SquadCount {
+      listed: 1,
+      found: 0
+    })
+  ));
+  std::fs::remove_dir_all(&data).unwrap();
+}
+
+#[test]
+fn different_bots_with_the_same_file_name_are_both_exported() {
+  let data = std::env::temp_dir().join(format!("shipped-homonyms-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&data);
+  let config = Config::resolve(Some(data.join("ours")), None, None);
+  std::fs::create_dir_all(config.bots_dir()).unwrap();
+  std::fs::create_dir_all(data.join("elsewhere")).unwrap();
+  std::fs::write(config.bots_dir().join("mover.wasm"), b"ours").unwrap();
+  let elsewhere = data.join("elsewhere/mover.wasm");
+  std::fs::write(&elsewhere, b"theirs").unwrap();
+
+  let mut level = BFState::new(Board::default());
+  think_with(&mut level, 0, "mover.wasm");
+  think_with(&mut level, 1, elsewhere.to_str().unwrap());
+  save_level_file(&config, &level).unwrap();
+  let bundle = Bundle::export(&config, 0, &[]).unwrap();
+  assert_eq!(bundle.bots.len(), 2);
+
+  let other = Config::resolve(Some(data.join("other")), None, None);
+  let imported = bundle.import(&other).unwrap();
+  let contents: Vec<Vec<u8>> = load_level_file(&other, imported.level)
+    .unwrap()
+    .brain_names()
+    .iter()
+    .map(|name| std::fs::read(other.bot_path(name)).unwrap())
+    .collect();
+  assert_eq!(contents, vec![b"ours".to_vec(), b"theirs".to_vec()]);
+  std::fs::remove_dir_all(&data).unwrap();
+}
+
+#[test]
+fn bundles_that_inflate_beyond_the_limit_are_refused() {
+  let path = std::env::temp_dir().join(format!("shipped-bomb-{}.bundle", std::process::id()));
+  let mut encoder = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::fast());
+  encoder.write_all(&vec![b' '; MAX_BUNDLE + 1]).unwrap();
+  encoder.finish().unwrap();
+  assert!(matches!(
+    Bundle::read(&path),
+    Err(BundleError::TooLarge { .. })
+  ));
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn failed_imports_leave_no_files_behind() {
+  let data = std::env::temp_dir().join(format!("shipped-rollback-{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&data);
+  let config = Config::resolve(Some(data.join("ours")), None, None);
+  std::fs::create_dir_all(config.bots_dir()).unwrap();
+  std::fs::write(config.bots_dir().join("mover.wasm"), b"ours").unwrap();
+  let mut level = BFState::new(Board::default());
+  think_with(&mut level, 0, "mover.wasm");
+  save_level_file(&config, &level).unwrap();
+  save_squad_file(&config, 0, &level, &level).unwrap();
+  let bundle = Bundle::export(&config, 0, &[0]).unwrap();
+
+  // squads cannot be saved where a file stands in for their directory
+  let other = Config::resolve(Some(data.join("other")), None, None);
+  std::fs::create_dir_all(&other.data_dir).unwrap();
+  std::fs::write(other.data_dir.join("squads"), b"").unwrap();
+  assert!(matches!(
+    bundle.import(&other),
+    Err(BundleError::Write { .. })
+  ));
+  assert!(!other.bots_dir().join("mover.wasm").exists());
+  assert!(load_level_file(&other, 0).unwrap_err().is_missing());
+  std::fs::remove_dir_all(&data).unwrap();
+}
@@ -0,0 +1,114 @@
+use harness::{bot, dummy, Scenario};
+use shipped::state::entity::{Team, TemplateEntity};
+use shipped::state::geometry::Symmetry;
+use shipped::state::materials::Materials;
+use shipped::state::run::verify_replay;
+use shipped::state::state::{ActionCosts, Verb};
+use tools::encoder::encode_verb;
+use tools::game::{Direction, Displace, Verb as BotVerb};
+use tools::host::Bot;
+
+// shoots the tile to its east
+struct Gunner;
+
+impl Bot for Gunner {
+  fn execute(&mut self) -> i64 {
+    encode_verb(BotVerb::Shoot(Displace::new(1, 0)))
+  }
+}
+
+// drills the tile to its east
+struct Driller;
+
+impl Bot for Driller {
+  fn execute(&mut self) -> i64 {
+    encode_verb(BotVerb::Drill(Direction::East))
+  }
+}
+
+fn gunner(copper: usize) -> TemplateEntity {
+  TemplateEntity {
+    gun_damage: 20,
+    materials: Materials::new(0, 0, 0, copper),
+    ..bot("gunner")
+  }
+}
+
+#[test]
+fn shots_that_eliminate_the_shooters_team_are_kept_in_the_script() {
+  // blue needs both of its bots to stay in the match, and the other two
+  // teams keep it going once blue is out
+  let run = Scenario::new("01.AA")
+    .symmetry(Symmetry::FourfoldRotation)
+    .min_tokens(2)
+    .blue(0, gunner(1))
+    .native("gunner", || Box::new(Gunner))
+    .blue(1, dummy())
+    .red(0, dummy())
+    .team(Team(2), 0, dummy())
+    .place(Team(2), 0, 10, 10)
+    .place(Team(2), 0, 11, 10)
+    .run(1)
+    .unwrap();
+  let gunner = run.id_at_start(0, 0).unwrap();
+  assert!(run.entity(gunner).is_none());
+  assert!(run.frames[0]
+    .iter()
+    .any(|c| c.entity_id == gunner && matches!(c.verb, Verb::Shoot(_))));
+  assert!(verify_replay(&run.script()).is_ok());
+}
+
+#[test]
+fn drills_that_eliminate_the_drillers_team_are_kept_in_the_script() {
+  let run = Scenario::new("01.AA")
+    .symmetry(Symmetry::FourfoldRotation)
+    .min_tokens(2)
+    .blue(
+      0,
+      TemplateEntity {
+        drill_damage: 20,
+        ..bot("driller")
+      },
+    )
+    .native("driller", || Box::new(Driller))
+    .blue(1, dummy())
+    .red(0, dummy())
+    .team(Team(2), 0, dummy())
+    .place(Team(2), 0, 10, 10)
+    .place(Team(2), 0, 11, 10)
+    .run(1)
+    .unwrap();
+  let driller = run.id_at_start(0, 0).unwrap();
+  assert!(run.entity(driller).is_none());
+  assert!(run.frames[0]
+    .iter()
+    .any(|c| c.entity_id == driller && matches!(c.verb, Verb::Drill(_))));
+  assert!(verify_replay(&run.script()).is_ok());
+}
+
+#[test]
+fn free_shots_need_no_copper() {
+  let run = Scenario::new("0A")
+    .blue(0, gunner(0))
+    .native("gunner", || Box::new(Gunner))
+    .red(0, dummy())
+    .costs(ActionCosts {
+      shot: 0,
+      ..ActionCosts::default()
+    })
+    .run(1)
+    .unwrap();
+  assert!(run.entity_at(1, 0).is_none());
+}
+
+#[test]
+fn shots_at_empty_tiles_cost_nothing_and_replay() {
+  let run = Scenario::new("0.")
+    .blue(0, gunner(1))
+    .native("gunner", || Box::new(Gunner))
+    .run(2)
+    .unwrap();
+  let gunner = run.id_at_start(0, 0).unwrap();
+  assert_eq!(run.entity(gunner).unwrap().materials.copper, 1);
+  assert!(verify_replay(&run.script()).is_ok());
+}
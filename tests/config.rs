@@ -0,0 +1,49 @@
+use shipped::state::config::{installed_assets_dir, Config, ConfigFile};
+use std::path::PathBuf;
+
+#[test]
+fn the_data_directory_comes_from_the_flag_then_the_environment_then_the_file() {
+  let file = || {
+    Some(ConfigFile {
+      data_dir: Some(PathBuf::from("/from/file")),
+      ..ConfigFile::default()
+    })
+  };
+  let flag = Some(PathBuf::from("/from/flag"));
+  let env = Some(PathBuf::from("/from/env"));
+  let data_dir = |c: Config| c.data_dir;
+  assert_eq!(
+    data_dir(Config::resolve(flag, env.clone(), file())),
+    PathBuf::from("/from/flag")
+  );
+  assert_eq!(
+    data_dir(Config::resolve(None, env, file())),
+    PathBuf::from("/from/env")
+  );
+  assert_eq!(
+    data_dir(Config::resolve(None, None, file())),
+    PathBuf::from("/from/file")
+  );
+  assert_eq!(
+    data_dir(Config::resolve(None, None, None)),
+    PathBuf::from(".")
+  );
+
+  // the data directory has no assets, and the sources are not looked at
+  assert_eq!(
+    Config::resolve(Some(PathBuf::from("/data")), None, None).assets_dir,
+    installed_assets_dir()
+  );
+  assert!(installed_assets_dir().starts_with(std::env::current_exe().unwrap().parent().unwrap()));
+
+  // squads saved before store the path their brain was loaded from
+  let config = Config::resolve(Some(PathBuf::from("/data")), None, None);
+  assert_eq!(
+    config.bot_path("./target/wasm32-unknown-unknown/release/mover.wasm"),
+    PathBuf::from("/data/bots/mover.wasm")
+  );
+  assert_eq!(
+    config.bot_path("mover.wasm"),
+    PathBuf::from("/data/bots/mover.wasm")
+  );
+}
@@ -0,0 +1,59 @@
+use harness::{bot, Scenario};
+use shipped::state::bf::{build_state, validate, BFState, BuildError, ValidationError};
+use shipped::state::entity::{cost, EconomyRules, TemplateEntity};
+use shipped::state::geometry::{Board, Displace, Pos};
+
+#[test]
+fn sight_costs_silicon_only_beyond_the_old_range() {
+  let economy = EconomyRules::default();
+  let silicon = |sight| {
+    cost(
+      &TemplateEntity {
+        sight,
+        ..bot("mover")
+      },
+      &economy,
+    )
+    .silicon
+  };
+  assert_eq!(silicon(0), silicon(3));
+  assert!(silicon(5) > silicon(3));
+}
+
+#[test]
+fn sights_too_large_to_square_see_everything_in_view() {
+  let state = Scenario::new(". .").build().unwrap();
+  assert!(state.is_visible(Pos::new(0, 0), &Displace::new(1, 0), usize::MAX));
+}
+
+#[test]
+fn economies_whose_prices_fall_are_refused() {
+  for exponent in [-1.0, f64::NAN, f64::INFINITY] {
+    let economy = EconomyRules {
+      exponent,
+      ..EconomyRules::default()
+    };
+    assert!(economy.validate().is_err());
+    // pricing stays total even before the rules are checked
+    let body = TemplateEntity {
+      gun_damage: 0,
+      steps: usize::MAX,
+      ..bot("mover")
+    };
+    cost(&body, &economy);
+
+    let mut level = BFState::new(Board::default());
+    level.set_economy(economy);
+    assert!(matches!(
+      level.check_validity(),
+      Err(ValidationError::InvalidEconomy { .. })
+    ));
+    assert!(validate(&level, &level)
+      .iter()
+      .any(|d| matches!(d.error, ValidationError::InvalidEconomy { .. })));
+    assert!(matches!(
+      build_state(&level, &[&level, &level]),
+      Err(BuildError::InvalidSquad { squad: 0, .. })
+    ));
+  }
+}
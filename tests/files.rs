@@ -0,0 +1,216 @@
+use sha2::{Digest, Sha256};
+use shipped::state::bf::{
+  build_state, parse_bf, parse_verified, validate, BFState, BuildError, Diagnostic, IntegrityError,
+  LoadError, Metadata, ValidationError, BF_VERSION,
+};
+use shipped::state::geometry::{Board, Pos};
+use shipped::state::neutral::Neutral;
+use shipped::state::state::Terrain;
+use std::path::Path;
+
+#[test]
+fn squads_that_change_terrain_are_reported_on_every_tile() {
+  let level = BFState::new(Board::default());
+  let mut squad = level.clone();
+  squad.set_terrain(Pos::new(1, 1), Terrain::Water).unwrap();
+  squad.set_terrain(Pos::new(2, 3), Terrain::Wall).unwrap();
+  let positions: Vec<Option<Pos>> = validate(&level, &squad).iter().map(|d| d.pos).collect();
+  assert_eq!(positions, vec![Some(Pos::new(1, 1)), Some(Pos::new(2, 3))]);
+  assert!(matches!(
+    build_state(&level, &[&squad, &level]),
+    Err(BuildError::InvalidSquad { squad: 0, .. })
+  ));
+}
+
+#[test]
+fn squads_are_refused_when_tampered_or_moved_to_another_level() {
+  let mut level = BFState::new(Board::default());
+  level.seal(None);
+  assert!(level.verify(None).is_ok());
+  let mut squad = level.clone();
+  squad.seal(Some(&level));
+  assert!(squad.verify(Some(&level)).is_ok());
+
+  let mut other = level.clone();
+  other.set_terrain(Pos::new(1, 1), Terrain::Wall).unwrap();
+  assert!(matches!(
+    squad.verify(Some(&other)),
+    Err(IntegrityError::Orphaned {})
+  ));
+
+  squad.set_terrain(Pos::new(2, 2), Terrain::Water).unwrap();
+  assert!(matches!(
+    squad.verify(Some(&level)),
+    Err(IntegrityError::Tampered {})
+  ));
+}
+
+#[test]
+fn levels_of_every_version_load_and_broken_ones_are_explained() {
+  let path = Path::new("00000.lvl");
+  let mut level = serde_json::to_value(BFState::new(Board::default())).unwrap();
+  level.as_object_mut().unwrap().remove("version");
+  let (_, version) = parse_bf(level.to_string().as_bytes(), path).unwrap();
+  assert_eq!(version, 0);
+
+  level["version"] = (BF_VERSION + 1).into();
+  assert!(matches!(
+    parse_bf(level.to_string().as_bytes(), path),
+    Err(LoadError::TooNew { .. })
+  ));
+  assert!(matches!(
+    parse_bf(b"{\"tokens\": 3", path),
+    Err(LoadError::Malformed { .. })
+  ));
+}
+
+#[test]
+fn older_files_are_checked_against_the_hash_of_their_version() {
+  let path = Path::new("00000.sqd");
+  let level = BFState::new(Board::default());
+  let unsealed = level.clone();
+  let mut squad = level.clone();
+  squad.seal(Some(&level));
+
+  // forging an older version does not skip the check
+  let mut tampered = serde_json::to_value(&squad).unwrap();
+  tampered["tokens"] = 1000.into();
+  tampered.as_object_mut().unwrap().remove("version");
+  assert!(matches!(
+    parse_verified(tampered.to_string().as_bytes(), path, Some(&level)),
+    Err(LoadError::Untrusted { .. })
+  ));
+
+  // files of version 0 hashed everything but the version and metadata
+  let hash = |state: &BFState| {
+    let current = serde_json::to_string(state).unwrap();
+    let metadata = serde_json::to_string(&Metadata::default()).unwrap();
+    let old = current
+      .replace(&format!("\"version\":{},", BF_VERSION), "")
+      .replace(&format!("\"metadata\":{},", metadata), "");
+    format!("{:x}", Sha256::digest(old.as_bytes()))
+  };
+  let mut old = serde_json::to_value(&squad).unwrap();
+  old["manifest"]["content"] = hash(&unsealed).into();
+  old["manifest"]["level"] = hash(&level).into();
+  old.as_object_mut().unwrap().remove("version");
+  let loaded = parse_verified(old.to_string().as_bytes(), path, Some(&level)).unwrap();
+  assert!(loaded.verify(Some(&level)).is_ok());
+}
+
+#[test]
+fn metadata_is_sealed_with_the_level() {
+  let mut level = BFState::new(Board::default());
+  level.set_metadata(Metadata {
+    title: "Moat".to_string(),
+    author: "ana".to_string(),
+    created: 1_700_000_000,
+    ..Metadata::default()
+  });
+  level.seal(None);
+  assert_eq!(level.get_metadata().headline(), "Moat by ana");
+  assert_eq!(
+    level.get_metadata().created_date(),
+    Some("2023-11-14".to_string())
+  );
+
+  let mut renamed = level.get_metadata().clone();
+  renamed.title = "Castle".to_string();
+  level.set_metadata(renamed);
+  assert!(matches!(
+    level.verify(None),
+    Err(IntegrityError::Tampered {})
+  ));
+}
+
+#[test]
+fn neutrals_outside_the_first_region_break_the_symmetry() {
+  let mut level = BFState::new(Board::default());
+  level.add_neutral(Neutral::Rock, Pos::new(1, 1)).unwrap();
+  assert!(level.check_symmetry().is_ok());
+
+  // the editor keeps them in the region, but files can be edited by hand
+  let mut edited = serde_json::to_value(&level).unwrap();
+  edited["neutrals"][0][0]["y"] = (Board::default().height - 2).into();
+  let (edited, _) = parse_bf(edited.to_string().as_bytes(), Path::new("00000.lvl")).unwrap();
+  let outside = Pos::new(1, Board::default().height - 2);
+  assert!(matches!(
+    edited.check_symmetry(),
+    Err(ValidationError::NotSymmetric { pos }) if pos == outside
+  ));
+  assert_eq!(validate(&level, &edited)[0].pos, Some(outside));
+}
+
+#[test]
+fn malformed_levels_and_squads_are_reported_instead_of_indexed() {
+  let level = BFState::new(Board::default());
+  let edit = |change: &dyn Fn(&mut serde_json::Value)| {
+    let mut value = serde_json::to_value(&level).unwrap();
+    change(&mut value);
+    serde_json::from_value::<BFState>(value).unwrap()
+  };
+  let short = edit(&|v| {
+    v["tiles"].as_array_mut().unwrap().pop();
+  });
+  let unknown = edit(&|v| v["tiles"][0]["entity_id"] = 7.into());
+
+  assert!(matches!(
+    validate(&level, &short)[..],
+    [Diagnostic {
+      error: ValidationError::InvalidBoardLevel { .. },
+      ..
+    }]
+  ));
+  assert!(matches!(
+    validate(&short, &level)[..],
+    [Diagnostic {
+      error: ValidationError::InvalidBoardLevel { .. },
+      ..
+    }]
+  ));
+  assert!(matches!(
+    validate(&level, &unknown)[..],
+    [Diagnostic {
+      error: ValidationError::UnknownTemplate { index: 7, .. },
+      ..
+    }]
+  ));
+  assert!(unknown.check_validity().is_err());
+  assert!(matches!(
+    build_state(&level, &[&unknown, &level]),
+    Err(BuildError::InvalidSquad { squad: 0, .. })
+  ));
+}
+
+#[test]
+fn files_that_parse_but_do_not_fit_their_board_are_malformed() {
+  let path = Path::new("00000.lvl");
+  let mut level = serde_json::to_value(BFState::new(Board::default())).unwrap();
+  level["tiles"][3]["entity_id"] = 0.into();
+  assert!(matches!(
+    parse_bf(level.to_string().as_bytes(), path),
+    Err(LoadError::Inconsistent {
+      source: ValidationError::UnknownTemplate { index: 0, .. },
+      ..
+    })
+  ));
+  level["tiles"] = serde_json::Value::Array(vec![]);
+  assert!(matches!(
+    parse_bf(level.to_string().as_bytes(), path),
+    Err(LoadError::Inconsistent { .. })
+  ));
+}
+
+#[test]
+fn squads_from_before_manifests_are_sealed_as_they_load() {
+  let path = Path::new("00000.sqd");
+  let level = BFState::new(Board::default());
+  let mut squad = serde_json::to_value(&level).unwrap();
+  assert!(matches!(
+    parse_verified(squad.to_string().as_bytes(), path, Some(&level)),
+    Err(LoadError::Untrusted { .. })
+  ));
+  squad.as_object_mut().unwrap().remove("version");
+  let loaded = parse_verified(squad.to_string().as_bytes(), path, Some(&level)).unwrap();
+  assert!(loaded.verify(Some(&level)).is_ok());
+}
@@ -0,0 +1,117 @@
+use harness::{bot, Scenario};
+use mover::MoverBot;
+use shipped::state::bf::{parse_bf, BFState};
+use shipped::state::binary::{from_bytes, to_bytes, Format};
+use shipped::state::config::Config;
+use shipped::state::entity::{Action, ActiveEntity, Stat};
+use shipped::state::geometry::{Board, Pos};
+use shipped::state::run::{
+  check_replay, first_divergence, replay, save_replay, verify_replay, ReplayError,
+};
+use shipped::state::state::{diff, Script, State, Terrain};
+use std::path::Path;
+
+#[test]
+fn replays_and_levels_survive_the_binary_format() {
+  let run = Scenario::new("0")
+    .blue(0, bot("mover"))
+    .native("mover", || Box::new(MoverBot::default()))
+    .run(50)
+    .unwrap();
+  let script = run.script();
+  let json = to_bytes(&script, Format::Json);
+  let binary = to_bytes(&script, Format::Binary);
+  assert!(binary.len() * 20 < json.len());
+  let decoded: Script = from_bytes(&binary).unwrap();
+  assert_eq!(
+    serde_json::to_value(&decoded).unwrap(),
+    serde_json::to_value(&script).unwrap()
+  );
+  // the state itself, with its floats and negative numbers
+  let state = from_bytes(&to_bytes(&script.genesis, Format::Binary)).unwrap();
+  assert_eq!(
+    serde_json::to_value(&script.genesis).unwrap(),
+    serde_json::to_value::<State>(state).unwrap()
+  );
+
+  let mut level = BFState::new(Board::default());
+  level.set_terrain(Pos::new(3, 4), Terrain::Water).unwrap();
+  let (parsed, _) = parse_bf(&to_bytes(&level, Format::Binary), Path::new("00000.lvl")).unwrap();
+  assert_eq!(parsed.content_hash(), level.content_hash());
+}
+
+#[test]
+fn replays_check_their_hashes_and_find_where_runs_diverge() {
+  let scenario = Scenario::new(
+    "0 . 0
+     . 0 .",
+  )
+  .blue(0, bot("mover"))
+  .native("mover", || Box::new(MoverBot::default()));
+  let run = scenario.run(20).unwrap();
+  let script = run.script();
+  // the entities move in the same order whatever their map holds
+  assert_eq!(scenario.run(20).unwrap().hashes, script.hashes);
+  assert!(verify_replay(&script).is_ok());
+  assert!(diff(&replay(&script, 20), &run.state).is_empty());
+
+  // one of the bots skips a turn and is a step behind from then on
+  let mut late = scenario.run(20).unwrap().script();
+  late.frames[7].remove(0);
+  assert_eq!(verify_replay(&late).unwrap_err().frame, 7);
+  late.hashes = (1..=20).map(|n| replay(&late, n).hash()).collect();
+  let (frame, changes) = first_divergence(&script, &late).unwrap();
+  assert_eq!(frame, 7);
+  assert_eq!(changes.tiles.len(), 2);
+  assert_eq!(changes.entities, vec![script.frames[7][0].entity_id]);
+  assert!(changes.tokens.is_empty());
+}
+
+#[test]
+fn saved_replays_are_checked_against_their_hashes() {
+  let data = std::env::temp_dir().join(format!("shipped-replays-{}", std::process::id()));
+  let config = Config::resolve(Some(data.clone()), None, None);
+  let mut script = Scenario::new("0 . 0")
+    .blue(0, bot("mover"))
+    .native("mover", || Box::new(MoverBot::default()))
+    .run(10)
+    .unwrap()
+    .script();
+  let n = save_replay(&config, &script).unwrap();
+  assert!(check_replay(&config, n).is_ok());
+
+  script.frames[3].remove(0);
+  let n = save_replay(&config, &script).unwrap();
+  assert!(matches!(
+    check_replay(&config, n),
+    Err(ReplayError::DesyncedReplay { .. })
+  ));
+  assert!(matches!(
+    check_replay(&config, n + 1),
+    Err(ReplayError::UnreadableReplay { .. })
+  ));
+  std::fs::remove_dir_all(&data).unwrap();
+}
+
+#[test]
+fn hashes_tell_apart_entities_that_differ_in_any_field() {
+  let state = Scenario::new("0")
+    .blue(0, bot("mover"))
+    .native("mover", || Box::new(MoverBot::default()))
+    .run(1)
+    .unwrap()
+    .state;
+  let id = *state.entities.keys().next().unwrap();
+  let changes: [fn(&mut ActiveEntity); 4] = [
+    |e| e.last_action = Action::Upgrade(Stat::Sight),
+    |e| e.resting += 1,
+    |e| e.can_repair = !e.can_repair,
+    |e| e.brain.as_mut().unwrap().gas += 1,
+  ];
+  for change in changes {
+    let mut changed = state.clone();
+    change(changed.entities.get_mut(&id).unwrap());
+    assert_ne!(changed.hash(), state.hash());
+  }
+  assert_eq!(state.clone().hash(), state.hash());
+}
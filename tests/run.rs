@@ -0,0 +1,55 @@
+use harness::{bot, Scenario};
+use shipped::state::bf::{BFState, EntityState};
+use shipped::state::brain::BrainError;
+use shipped::state::config::Config;
+use shipped::state::entity::{Full, Mix, MixTemplate};
+use shipped::state::geometry::Board;
+use shipped::state::run::{run_match, RunError};
+use std::panic::catch_unwind;
+use tools::host::{get_coord, Bot};
+
+#[test]
+fn matches_report_the_template_whose_bot_is_missing() {
+  let mut level = BFState::new(Board::default());
+  level.initialize_bot(1).unwrap();
+  if let EntityState::Entity(template, _) = level.get_entities()[1].clone() {
+    let brain = Mix::Full(Full {
+      half: [0, 0],
+      code_name: "missing.wasm".to_string(),
+      gas: 0,
+    });
+    level
+      .update_bot(1, MixTemplate { brain, ..template })
+      .unwrap();
+  }
+  assert!(matches!(
+    run_match(
+      &Config::resolve(None, None, None),
+      &level,
+      &[&level, &level],
+      1
+    ),
+    Err(RunError::LoadBrains {
+      source: BrainError::LoadWasm { index: 1, .. }
+    })
+  ));
+}
+
+struct Panicker;
+
+impl Bot for Panicker {
+  fn execute(&mut self) -> i64 {
+    panic!("bot gave up")
+  }
+}
+
+#[test]
+fn panicking_bots_do_not_leave_their_host_behind() {
+  let run = Scenario::new("0")
+    .blue(0, bot("panicker"))
+    .native("panicker", || Box::new(Panicker))
+    .run(1)
+    .unwrap();
+  assert_eq!(run.faults[0].len(), 1);
+  assert!(catch_unwind(get_coord).is_err());
+}